@@ -0,0 +1,138 @@
+use std::cell::RefCell;
+use std::fmt;
+use std::future::Future;
+use std::pin::Pin;
+use std::rc::Rc;
+use std::task::{Context, Poll, Waker};
+
+struct Inner {
+    n: usize,
+    arrived: usize,
+    generation: u64,
+    wakers: Vec<Waker>,
+}
+
+/// Lets a number of tasks on the current thread wait until all of them have
+/// reached the same point.
+///
+/// Clones of a barrier share the same state. Once `n` tasks are waiting on
+/// it they're all released, and the barrier can be used again by the next
+/// `n` tasks.
+#[derive(Clone)]
+pub struct Barrier {
+    inner: Rc<RefCell<Inner>>,
+}
+
+impl Barrier {
+    /// Creates a barrier which releases tasks in groups of `n`.
+    ///
+    /// A barrier for zero tasks behaves like one for a single task, never
+    /// making `wait` wait.
+    pub fn new(n: usize) -> Self {
+        Self {
+            inner: Rc::new(RefCell::new(Inner {
+                n: n.max(1),
+                arrived: 0,
+                generation: 0,
+                wakers: Vec::new(),
+            })),
+        }
+    }
+
+    /// Returns a future which resolves once `n` tasks are waiting on this
+    /// barrier, including this one.
+    ///
+    /// Dropping the future before it resolves withdraws from the barrier.
+    pub fn wait(&self) -> BarrierWait {
+        BarrierWait {
+            barrier: self.clone(),
+            generation: None,
+        }
+    }
+}
+
+impl fmt::Debug for Barrier {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let inner = self.inner.borrow();
+        f.debug_struct("Barrier")
+            .field("n", &inner.n)
+            .field("arrived", &inner.arrived)
+            .finish()
+    }
+}
+
+/// Future returned by `Barrier::wait`.
+#[derive(Debug)]
+pub struct BarrierWait {
+    barrier: Barrier,
+    // The generation this future arrived in, once it has
+    generation: Option<u64>,
+}
+
+impl Future for BarrierWait {
+    type Output = BarrierWaitResult;
+
+    fn poll(mut self: Pin<&mut Self>, cx: &mut Context) -> Poll<BarrierWaitResult> {
+        let wakers = {
+            let mut inner = self.barrier.inner.borrow_mut();
+
+            if let Some(generation) = self.generation {
+                if generation != inner.generation {
+                    drop(inner);
+                    self.generation = None;
+                    return Poll::Ready(BarrierWaitResult { is_leader: false });
+                }
+                if !inner.wakers.iter().any(|w| w.will_wake(cx.waker())) {
+                    inner.wakers.push(cx.waker().clone());
+                }
+                return Poll::Pending;
+            }
+
+            inner.arrived += 1;
+            if inner.arrived < inner.n {
+                let generation = inner.generation;
+                inner.wakers.push(cx.waker().clone());
+                drop(inner);
+                self.generation = Some(generation);
+                return Poll::Pending;
+            }
+
+            // The last task to arrive releases everyone else.
+            inner.arrived = 0;
+            inner.generation += 1;
+            std::mem::take(&mut inner.wakers)
+        };
+
+        for waker in wakers {
+            waker.wake();
+        }
+        Poll::Ready(BarrierWaitResult { is_leader: true })
+    }
+}
+
+impl Drop for BarrierWait {
+    fn drop(&mut self) {
+        let generation = match self.generation {
+            Some(generation) => generation,
+            None => return,
+        };
+        let mut inner = self.barrier.inner.borrow_mut();
+        if generation == inner.generation {
+            inner.arrived -= 1;
+        }
+    }
+}
+
+/// The result of waiting on a `Barrier`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct BarrierWaitResult {
+    is_leader: bool,
+}
+
+impl BarrierWaitResult {
+    /// Returns whether this task was the last to arrive, and so released the
+    /// others. Exactly one task per group is the leader.
+    pub fn is_leader(&self) -> bool {
+        self.is_leader
+    }
+}
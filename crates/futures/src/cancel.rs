@@ -0,0 +1,343 @@
+use crate::queue::find_timer_function;
+use js_sys::{Array, Function, Reflect};
+use std::cell::{Cell, RefCell};
+use std::collections::HashMap;
+use std::fmt;
+use std::future::Future;
+use std::pin::Pin;
+use std::rc::Rc;
+use std::task::{Context, Poll, Waker};
+use wasm_bindgen::prelude::*;
+use wasm_bindgen::JsCast;
+
+struct Inner {
+    cancelled: Cell<bool>,
+    // The wakers of the `Cancelled` futures waiting on the token, keyed so
+    // each can remove its own once it's dropped
+    wakers: RefCell<HashMap<u64, Waker>>,
+    next_key: Cell<u64>,
+}
+
+/// A handle used to cooperatively cancel work on the current thread.
+///
+/// Clones of a token share the same state, so cancelling any one of them
+/// cancels them all. Futures and streams observe cancellation through
+/// `cancelled`, `with_cancellation`, and `stream::take_until`.
+#[derive(Clone)]
+pub struct CancellationToken {
+    inner: Rc<Inner>,
+}
+
+impl CancellationToken {
+    /// Creates a new token which has not been cancelled.
+    pub fn new() -> Self {
+        Self {
+            inner: Rc::new(Inner {
+                cancelled: Cell::new(false),
+                wakers: RefCell::new(HashMap::new()),
+                next_key: Cell::new(0),
+            }),
+        }
+    }
+
+    /// Cancels this token, waking every future waiting on it.
+    ///
+    /// Cancelling a token more than once has no further effect.
+    pub fn cancel(&self) {
+        if self.inner.cancelled.replace(true) {
+            return;
+        }
+
+        // Take the wakers out before waking them so that a waker which polls
+        // synchronously can't observe the list borrowed.
+        let wakers = self.inner.wakers.replace(HashMap::new());
+        for (_, waker) in wakers {
+            waker.wake();
+        }
+    }
+
+    /// Returns whether `cancel` has been called on this token.
+    pub fn is_cancelled(&self) -> bool {
+        self.inner.cancelled.get()
+    }
+
+    /// Returns a future which resolves once this token is cancelled.
+    pub fn cancelled(&self) -> Cancelled {
+        Cancelled {
+            token: self.clone(),
+            key: None,
+        }
+    }
+
+    // Stores `waker` under `key`, allocating a key the first time
+    fn register(&self, key: &mut Option<u64>, waker: &Waker) {
+        let key = *key.get_or_insert_with(|| {
+            let next = self.inner.next_key.get();
+            self.inner.next_key.set(next.wrapping_add(1));
+            next
+        });
+        let mut wakers = self.inner.wakers.borrow_mut();
+        match wakers.get_mut(&key) {
+            Some(stored) if stored.will_wake(waker) => {}
+            Some(stored) => *stored = waker.clone(),
+            None => {
+                wakers.insert(key, waker.clone());
+            }
+        }
+    }
+}
+
+impl Default for CancellationToken {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl fmt::Debug for CancellationToken {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("CancellationToken")
+            .field("cancelled", &self.is_cancelled())
+            .finish()
+    }
+}
+
+/// A future which resolves once a `CancellationToken` is cancelled.
+///
+/// Created by `CancellationToken::cancelled`.
+#[derive(Debug)]
+pub struct Cancelled {
+    token: CancellationToken,
+    // The key of this future's waker in the token, once it has one
+    key: Option<u64>,
+}
+
+impl Future for Cancelled {
+    type Output = ();
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context) -> Poll<()> {
+        if self.token.is_cancelled() {
+            return Poll::Ready(());
+        }
+        let this = self.get_mut();
+        this.token.register(&mut this.key, cx.waker());
+        Poll::Pending
+    }
+}
+
+impl Drop for Cancelled {
+    fn drop(&mut self) {
+        if let Some(key) = self.key {
+            let waker = self.token.inner.wakers.borrow_mut().remove(&key);
+            drop(waker);
+        }
+    }
+}
+
+/// Runs `future` until it completes or `token` is cancelled.
+///
+/// Resolves to `Some` with the future's output if it completes first, or to
+/// `None` if the token is cancelled first. Once cancelled the future is not
+/// polled again.
+pub fn with_cancellation<F>(future: F, token: &CancellationToken) -> WithCancellation<F>
+where
+    F: Future,
+{
+    WithCancellation {
+        future,
+        cancelled: token.cancelled(),
+    }
+}
+
+/// Future returned by `with_cancellation`.
+#[derive(Debug)]
+pub struct WithCancellation<F> {
+    future: F,
+    cancelled: Cancelled,
+}
+
+impl<F: Future> Future for WithCancellation<F> {
+    type Output = Option<F::Output>;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context) -> Poll<Self::Output> {
+        // Safety: `future` is structurally pinned and never moved out of `self`.
+        let this = unsafe { self.get_unchecked_mut() };
+
+        if Pin::new(&mut this.cancelled).poll(cx).is_ready() {
+            return Poll::Ready(None);
+        }
+
+        let future = unsafe { Pin::new_unchecked(&mut this.future) };
+        future.poll(cx).map(Some)
+    }
+}
+
+/// Cancels a token when an `AbortSignal` fires, for as long as it's alive.
+pub(crate) struct AbortListener {
+    signal: web_sys::AbortSignal,
+    on_abort: Closure<dyn FnMut()>,
+}
+
+impl AbortListener {
+    pub(crate) fn new(signal: web_sys::AbortSignal, token: &CancellationToken) -> Self {
+        if signal.aborted() {
+            token.cancel();
+        }
+
+        let on_abort = {
+            let token = token.clone();
+            Closure::wrap(Box::new(move || token.cancel()) as Box<dyn FnMut()>)
+        };
+        signal
+            .add_event_listener_with_callback("abort", on_abort.as_ref().unchecked_ref())
+            .unwrap_throw();
+
+        Self { signal, on_abort }
+    }
+}
+
+impl Drop for AbortListener {
+    fn drop(&mut self) {
+        let _ = self
+            .signal
+            .remove_event_listener_with_callback("abort", self.on_abort.as_ref().unchecked_ref());
+    }
+}
+
+/// Returns an `AbortSignal` which aborts after `ms` milliseconds, as with
+/// the static `AbortSignal.timeout`.
+///
+/// Hosts without `AbortSignal.timeout` get an equivalent built from an
+/// `AbortController` and a `setTimeout` timer, which frees itself once it
+/// fires.
+///
+/// # Panics
+///
+/// Panics if the host has neither `AbortSignal.timeout` nor `setTimeout`.
+pub fn abort_signal_timeout(ms: u32) -> web_sys::AbortSignal {
+    if let Some((class, timeout)) = abort_signal_static("timeout") {
+        return timeout
+            .call1(&class, &JsValue::from(ms))
+            .unwrap_throw()
+            .unchecked_into();
+    }
+
+    let controller = web_sys::AbortController::new().unwrap_throw();
+    let signal = controller.signal();
+    let on_timeout = Closure::once_into_js(move || {
+        // Abort with the same reason as `AbortSignal.timeout` where the
+        // host can tell.
+        let reason =
+            dom_exception("signal timed out", "TimeoutError").unwrap_or(JsValue::UNDEFINED);
+        abort_with_reason(&controller, &reason);
+    });
+
+    let (scope, set_timeout) =
+        find_timer_function("setTimeout").expect_throw("no `setTimeout` to time out with");
+    set_timeout
+        .call2(&scope, &on_timeout, &JsValue::from(ms))
+        .unwrap_throw();
+    signal
+}
+
+/// Returns an `AbortSignal` which aborts as soon as any of `signals` does,
+/// with the same reason, as with the static `AbortSignal.any`.
+///
+/// Hosts without `AbortSignal.any` get an equivalent built from an
+/// `AbortController` and an "abort" listener on each of `signals`. Once one
+/// of them aborts the listener is removed from all of them and freed, but
+/// until then it's kept alive by the signals.
+pub fn abort_signal_any(signals: &[web_sys::AbortSignal]) -> web_sys::AbortSignal {
+    if let Some((class, any)) = abort_signal_static("any") {
+        let signals = signals.iter().collect::<Array>();
+        return any.call1(&class, &signals).unwrap_throw().unchecked_into();
+    }
+
+    let controller = web_sys::AbortController::new().unwrap_throw();
+    let signal = controller.signal();
+    if let Some(aborted) = signals.iter().find(|signal| signal.aborted()) {
+        abort_with_reason(&controller, &abort_reason(aborted));
+        return signal;
+    }
+
+    // One listener is shared by every input, and takes itself out of `slot`
+    // to be removed and freed once any of them aborts.
+    let slot = Rc::new(RefCell::new(None::<Closure<dyn FnMut()>>));
+    let on_abort = {
+        let slot = Rc::clone(&slot);
+        let signals = signals.to_vec();
+        Closure::wrap(Box::new(move || {
+            let on_abort = match slot.borrow_mut().take() {
+                Some(on_abort) => on_abort,
+                None => return,
+            };
+            for signal in &signals {
+                let _ = signal.remove_event_listener_with_callback(
+                    "abort",
+                    on_abort.as_ref().unchecked_ref(),
+                );
+            }
+            if let Some(aborted) = signals.iter().find(|signal| signal.aborted()) {
+                abort_with_reason(&controller, &abort_reason(aborted));
+            }
+
+            // This is still running inside of `on_abort`, so leave freeing
+            // it to a task.
+            crate::spawn_local(async move { drop(on_abort) });
+        }) as Box<dyn FnMut()>)
+    };
+    for signal in signals {
+        signal
+            .add_event_listener_with_callback("abort", on_abort.as_ref().unchecked_ref())
+            .unwrap_throw();
+    }
+    *slot.borrow_mut() = Some(on_abort);
+    signal
+}
+
+/// Looks up a static method of the global `AbortSignal`, along with the
+/// class to call it on.
+fn abort_signal_static(name: &str) -> Option<(JsValue, Function)> {
+    let class = Reflect::get(&js_sys::global(), &JsValue::from("AbortSignal")).ok()?;
+    let method = Reflect::get(&class, &JsValue::from(name))
+        .ok()?
+        .dyn_into::<Function>()
+        .ok()?;
+    Some((class, method))
+}
+
+/// The reason an aborted signal was aborted with, where the host tracks it.
+pub(crate) fn abort_reason(signal: &web_sys::AbortSignal) -> JsValue {
+    Reflect::get(signal, &JsValue::from("reason")).unwrap_or(JsValue::UNDEFINED)
+}
+
+/// Creates a `DOMException` with the given message and name, or `None` if
+/// the host has no `DOMException`.
+pub(crate) fn dom_exception(message: &str, name: &str) -> Option<JsValue> {
+    let class = Reflect::get(&js_sys::global(), &JsValue::from("DOMException")).ok()?;
+    let class = class.dyn_into::<Function>().ok()?;
+    let args = Array::of2(&message.into(), &name.into());
+    Reflect::construct(&class, &args).ok()
+}
+
+/// Creates the `AbortError` an `AbortController` aborts with by default,
+/// or an `Error` with that name on hosts without `DOMException`.
+pub(crate) fn abort_error() -> JsValue {
+    let message = "The operation was aborted.";
+    dom_exception(message, "AbortError").unwrap_or_else(|| {
+        let error = js_sys::Error::new(message);
+        error.set_name("AbortError");
+        error.into()
+    })
+}
+
+/// Aborts `controller` with `reason`, which hosts that don't support
+/// reasons ignore. An undefined reason gives the default `AbortError`.
+fn abort_with_reason(controller: &web_sys::AbortController, reason: &JsValue) {
+    match Reflect::get(controller, &JsValue::from("abort")) {
+        Ok(abort) => {
+            let abort = abort.unchecked_into::<Function>();
+            let _ = abort.call1(controller, reason);
+        }
+        Err(_) => controller.abort(),
+    }
+}
@@ -0,0 +1,62 @@
+use js_sys::{Object, Reflect, Symbol};
+use std::future::Future;
+use wasm_bindgen::prelude::*;
+
+/// Creates a JS object whose `[Symbol.dispose]` method runs `cleanup`, for
+/// use with JS's explicit resource management.
+///
+/// This lets Rust hand resources to JS which a `using` declaration (or a
+/// `DisposableStack`) releases at the end of its scope. `cleanup` runs
+/// synchronously when the method is called, and is meant to run just once:
+/// like any Rust closure passed to JS to be called once, calling the method
+/// again throws. If it's never called `cleanup` is leaked, along with
+/// everything it captured.
+///
+/// Hosts without `Symbol.dispose` get the `Symbol.for("Symbol.dispose")`
+/// which transpilers such as TypeScript fall back to as well.
+pub fn disposable<F>(cleanup: F) -> Object
+where
+    F: FnOnce() + 'static,
+{
+    with_method("dispose", Closure::once_into_js(cleanup))
+}
+
+/// Creates a JS object whose `[Symbol.asyncDispose]` method runs the future
+/// returned by `cleanup`, for use with `await using` declarations.
+///
+/// The method returns a `Promise` which resolves once the future completes,
+/// with the future run by the executor on the current thread like any task
+/// spawned with `spawn_local`. As with `disposable`, the method is meant to
+/// be called just once, and hosts without `Symbol.asyncDispose` get
+/// `Symbol.for("Symbol.asyncDispose")` instead.
+pub fn async_disposable<F, Fut>(cleanup: F) -> Object
+where
+    F: FnOnce() -> Fut + 'static,
+    Fut: Future<Output = ()> + 'static,
+{
+    let dispose = Closure::once_into_js(move || {
+        let future = cleanup();
+        crate::future_to_promise(async move {
+            future.await;
+            Ok(JsValue::undefined())
+        })
+    });
+    with_method("asyncDispose", dispose)
+}
+
+/// Creates an object with `method` as its `[Symbol[name]]` method.
+fn with_method(name: &str, method: JsValue) -> Object {
+    let object = Object::new();
+    Reflect::set(&object, &well_known_symbol(name), &method).unwrap_throw();
+    object
+}
+
+/// Looks up the well known symbol `Symbol[name]`, falling back to the
+/// registered symbol polyfills use for it.
+fn well_known_symbol(name: &str) -> JsValue {
+    Reflect::get(&js_sys::global(), &JsValue::from("Symbol"))
+        .and_then(|symbol| Reflect::get(&symbol, &JsValue::from(name)))
+        .ok()
+        .filter(JsValue::is_symbol)
+        .unwrap_or_else(|| Symbol::for_(&format!("Symbol.{}", name)).into())
+}
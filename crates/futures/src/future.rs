@@ -0,0 +1,447 @@
+use std::fmt;
+use std::future::Future;
+use std::pin::Pin;
+use std::task::{Context, Poll, RawWaker, RawWakerVTable, Waker};
+
+/// Creates a future which is driven by calling `f` each time it's polled.
+///
+/// This is handy for bridging callback based APIs: `f` can stash
+/// `cx.waker()` somewhere a callback will find it, and return `Pending`
+/// until the callback has stored a result. A closure which returns
+/// `Pending` must arrange for the waker to be woken, otherwise the future
+/// will never be polled again. In debug builds such lost wakeups are
+/// detected and reported with `log::warn!`.
+pub fn poll_fn<T, F>(f: F) -> PollFn<F>
+where
+    F: FnMut(&mut Context) -> Poll<T>,
+{
+    PollFn {
+        f,
+        #[cfg(debug_assertions)]
+        tracker: None,
+    }
+}
+
+/// Future returned by `poll_fn`.
+pub struct PollFn<F> {
+    f: F,
+    // Created on the first poll and reused after that, so the closure sees
+    // the same waker on every poll, as it would in release builds
+    #[cfg(debug_assertions)]
+    tracker: Option<tracking::Tracker>,
+}
+
+impl<F> Unpin for PollFn<F> {}
+
+impl<F> fmt::Debug for PollFn<F> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "PollFn {{ ... }}")
+    }
+}
+
+impl<T, F> Future for PollFn<F>
+where
+    F: FnMut(&mut Context) -> Poll<T>,
+{
+    type Output = T;
+
+    #[cfg(not(debug_assertions))]
+    fn poll(mut self: Pin<&mut Self>, cx: &mut Context) -> Poll<T> {
+        (self.f)(cx)
+    }
+
+    #[cfg(debug_assertions)]
+    fn poll(self: Pin<&mut Self>, cx: &mut Context) -> Poll<T> {
+        let this = self.get_mut();
+        let waker = cx.waker();
+        let tracker = this
+            .tracker
+            .get_or_insert_with(|| tracking::Tracker::new(waker));
+        tracker.start_poll(waker);
+        let poll = (this.f)(&mut Context::from_waker(tracker.waker()));
+        if poll.is_pending() && !tracker.is_armed() {
+            log::warn!(
+                "a `poll_fn` closure returned `Pending` without cloning or \
+                 waking its waker, so it will never be polled again"
+            );
+        }
+        poll
+    }
+}
+
+/// Wraps `future` so that `f` is called with its output once it's ready,
+/// which is then passed through unchanged.
+///
+/// This is meant for side effects like logging the settled result of a
+/// `JsFuture` at an `.await`, without having to restructure the code around
+/// it. Beyond calling `f` the wrapper adds nothing to polling `future`.
+pub fn inspect<F, G>(future: F, f: G) -> Inspect<F, G>
+where
+    F: Future,
+    G: FnOnce(&F::Output),
+{
+    Inspect { future, f: Some(f) }
+}
+
+/// Future returned by `inspect`.
+pub struct Inspect<F, G> {
+    future: F,
+    f: Option<G>,
+}
+
+impl<F: fmt::Debug, G> fmt::Debug for Inspect<F, G> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("Inspect")
+            .field("future", &self.future)
+            .finish()
+    }
+}
+
+impl<F, G> Future for Inspect<F, G>
+where
+    F: Future,
+    G: FnOnce(&F::Output),
+{
+    type Output = F::Output;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context) -> Poll<F::Output> {
+        // Safety: `future` is structurally pinned and never moved out of
+        // `self`, while `f` is never pinned.
+        let this = unsafe { self.get_unchecked_mut() };
+        let future = unsafe { Pin::new_unchecked(&mut this.future) };
+        let output = match future.poll(cx) {
+            Poll::Ready(output) => output,
+            Poll::Pending => return Poll::Pending,
+        };
+        if let Some(f) = this.f.take() {
+            f(&output);
+        }
+        Poll::Ready(output)
+    }
+}
+
+/// How a future spawned with `spawn_local_with_first_yield` got through its
+/// first poll.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum FirstYield {
+    /// The future returned `Pending`, reaching its first await point.
+    Suspended,
+
+    /// The future completed without ever returning `Pending`.
+    CompletedSynchronously,
+}
+
+/// Calls `on_first_yield` once `future` is done with its first poll.
+pub(crate) struct OnFirstYield<F, C> {
+    future: F,
+    on_first_yield: Option<C>,
+}
+
+impl<F, C> OnFirstYield<F, C> {
+    pub(crate) fn new(future: F, on_first_yield: C) -> Self {
+        OnFirstYield {
+            future,
+            on_first_yield: Some(on_first_yield),
+        }
+    }
+}
+
+impl<F, C> Future for OnFirstYield<F, C>
+where
+    F: Future,
+    C: FnOnce(FirstYield),
+{
+    type Output = F::Output;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context) -> Poll<F::Output> {
+        // Safety: `future` is structurally pinned and never moved out of
+        // `self`, while the callback is never pinned.
+        let this = unsafe { self.get_unchecked_mut() };
+        let future = unsafe { Pin::new_unchecked(&mut this.future) };
+        let poll = future.poll(cx);
+        if let Some(on_first_yield) = this.on_first_yield.take() {
+            on_first_yield(match poll {
+                Poll::Pending => FirstYield::Suspended,
+                Poll::Ready(_) => FirstYield::CompletedSynchronously,
+            });
+        }
+        poll
+    }
+}
+
+/// Wraps `future` so that it resolves to an error if it's polled more than
+/// `max` times without completing, to catch futures which keep waking
+/// themselves without ever making progress.
+///
+/// This is a debugging aid, so the polls are only counted in debug builds.
+/// In release builds the wrapper always resolves to `Ok` with the output of
+/// `future`, however often it's polled.
+pub fn bounded_polls<F>(future: F, max: u32) -> BoundedPolls<F>
+where
+    F: Future,
+{
+    BoundedPolls {
+        future,
+        max,
+        polls: 0,
+    }
+}
+
+/// Future returned by `bounded_polls`.
+#[derive(Debug)]
+pub struct BoundedPolls<F> {
+    future: F,
+    max: u32,
+    polls: u32,
+}
+
+/// Error returned by `bounded_polls` when its future was polled more times
+/// than allowed without completing.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct PollLimitExceeded {
+    /// The number of polls that were allowed.
+    pub max: u32,
+}
+
+impl fmt::Display for PollLimitExceeded {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "future was polled {} times without completing", self.max)
+    }
+}
+
+impl std::error::Error for PollLimitExceeded {}
+
+impl<F: Future> Future for BoundedPolls<F> {
+    type Output = Result<F::Output, PollLimitExceeded>;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context) -> Poll<Self::Output> {
+        // Safety: `future` is structurally pinned and never moved out of `self`.
+        let this = unsafe { self.get_unchecked_mut() };
+
+        if cfg!(debug_assertions) {
+            if this.polls >= this.max {
+                return Poll::Ready(Err(PollLimitExceeded { max: this.max }));
+            }
+            this.polls += 1;
+        }
+
+        let future = unsafe { Pin::new_unchecked(&mut this.future) };
+        future.poll(cx).map(Ok)
+    }
+}
+
+/// Wraps `future` so that `on_done` is called exactly once, as soon as the
+/// future either completes or is dropped without completing.
+///
+/// This is the async equivalent of a `finally` block: `on_done` runs however
+/// the future ends, whether its output is an error, it's cancelled (as with
+/// `with_cancellation`), or the task running it is aborted.
+pub fn finally<F, G>(future: F, on_done: G) -> Finally<F, G>
+where
+    F: Future,
+    G: FnOnce(),
+{
+    Finally {
+        future,
+        on_done: Some(on_done),
+    }
+}
+
+/// Future returned by `finally`.
+pub struct Finally<F, G: FnOnce()> {
+    future: F,
+    on_done: Option<G>,
+}
+
+impl<F: fmt::Debug, G: FnOnce()> fmt::Debug for Finally<F, G> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("Finally")
+            .field("future", &self.future)
+            .finish()
+    }
+}
+
+impl<F, G> Future for Finally<F, G>
+where
+    F: Future,
+    G: FnOnce(),
+{
+    type Output = F::Output;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context) -> Poll<F::Output> {
+        // Safety: `future` is structurally pinned and never moved out of
+        // `self`, while `on_done` is never pinned.
+        let this = unsafe { self.get_unchecked_mut() };
+        let future = unsafe { Pin::new_unchecked(&mut this.future) };
+        let output = match future.poll(cx) {
+            Poll::Ready(output) => output,
+            Poll::Pending => return Poll::Pending,
+        };
+        if let Some(on_done) = this.on_done.take() {
+            on_done();
+        }
+        Poll::Ready(output)
+    }
+}
+
+impl<F, G: FnOnce()> Drop for Finally<F, G> {
+    fn drop(&mut self) {
+        if let Some(on_done) = self.on_done.take() {
+            on_done();
+        }
+    }
+}
+
+/// Races `primary` against `secondary`, resolving with the output of
+/// whichever completes first and dropping the other.
+///
+/// Each poll checks `primary` before `secondary`, so if both are ready at
+/// once `primary` wins, like with `futures::select_biased!`. This suits
+/// races where one outcome should take precedence, such as preferring a
+/// cancellation over the data it's cancelling. Note that a `primary` which
+/// keeps waking itself can starve `secondary`.
+pub fn race2_biased<A, B>(primary: A, secondary: B) -> Race2Biased<A, B>
+where
+    A: Future,
+    B: Future,
+{
+    Race2Biased { primary, secondary }
+}
+
+/// Future returned by `race2_biased`.
+#[derive(Debug)]
+pub struct Race2Biased<A, B> {
+    primary: A,
+    secondary: B,
+}
+
+/// The output of `race2_biased`, telling which future won.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum RaceWinner<A, B> {
+    /// `primary` completed first, or at the same time as `secondary`.
+    Primary(A),
+
+    /// `secondary` completed first.
+    Secondary(B),
+}
+
+impl<A, B> Future for Race2Biased<A, B>
+where
+    A: Future,
+    B: Future,
+{
+    type Output = RaceWinner<A::Output, B::Output>;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context) -> Poll<Self::Output> {
+        // Safety: both futures are structurally pinned and never moved out
+        // of `self`.
+        let this = unsafe { self.get_unchecked_mut() };
+        let primary = unsafe { Pin::new_unchecked(&mut this.primary) };
+        if let Poll::Ready(output) = primary.poll(cx) {
+            return Poll::Ready(RaceWinner::Primary(output));
+        }
+        let secondary = unsafe { Pin::new_unchecked(&mut this.secondary) };
+        secondary.poll(cx).map(RaceWinner::Secondary)
+    }
+}
+
+/// Polls `future` a single time, returning what it returned.
+///
+/// This is the primitive for step debuggers and custom drivers which want to
+/// advance a future by hand. Called from within a task the future is given
+/// that task's waker, so if it returns `Pending` and is woken later the task
+/// is queued again like after any other await. Called from anywhere else it
+/// gets a waker which does nothing, so the caller has to decide when to poll
+/// again, say once `drive_n` has run whatever the future is waiting on.
+pub fn poll_once<F>(future: Pin<&mut F>) -> Poll<F::Output>
+where
+    F: Future + ?Sized,
+{
+    let task = crate::queue::QUEUE.with(|queue| queue.current_task());
+    let waker = match task {
+        Some(task) => crate::task::Task::waker(&task),
+        None => noop_waker(),
+    };
+    future.poll(&mut Context::from_waker(&waker))
+}
+
+fn noop_waker() -> Waker {
+    fn clone(_: *const ()) -> RawWaker {
+        RawWaker::new(std::ptr::null(), &VTABLE)
+    }
+    fn noop(_: *const ()) {}
+
+    const VTABLE: RawWakerVTable = RawWakerVTable::new(clone, noop, noop, noop);
+
+    // Safety: the waker has no data for its functions to misuse.
+    unsafe { Waker::from_raw(RawWaker::new(std::ptr::null(), &VTABLE)) }
+}
+
+#[cfg(debug_assertions)]
+mod tracking {
+    use std::sync::atomic::{AtomicBool, Ordering};
+    use std::sync::{Arc, Mutex};
+    use std::task::{Wake, Waker};
+
+    struct Shared {
+        // The waker of the task polling the `PollFn`
+        inner: Mutex<Waker>,
+        // Whether the tracking waker was woken during the current poll
+        woken: AtomicBool,
+    }
+
+    impl Wake for Shared {
+        fn wake(self: Arc<Self>) {
+            self.wake_by_ref();
+        }
+
+        fn wake_by_ref(self: &Arc<Self>) {
+            self.woken.store(true, Ordering::Relaxed);
+            // Clone the waker out in case waking it polls synchronously.
+            let waker = self.inner.lock().unwrap().clone();
+            waker.wake();
+        }
+    }
+
+    /// A waker wrapping the one the `PollFn` is polled with, which can tell
+    /// whether a wakeup was arranged: either it was woken, or a clone of it
+    /// is being kept somewhere.
+    ///
+    /// It's built from `Arc` and `Mutex` rather than the `Rc` the task wakers
+    /// use, so that `PollFn` is `Send` and `Sync` in debug builds exactly
+    /// when it is in release builds.
+    pub(super) struct Tracker {
+        waker: Waker,
+        shared: Arc<Shared>,
+    }
+
+    impl Tracker {
+        pub(super) fn new(waker: &Waker) -> Self {
+            let shared = Arc::new(Shared {
+                inner: Mutex::new(waker.clone()),
+                woken: AtomicBool::new(false),
+            });
+            let waker = Waker::from(Arc::clone(&shared));
+            Tracker { waker, shared }
+        }
+
+        /// Points the tracking waker at `waker`, for a new poll.
+        pub(super) fn start_poll(&self, waker: &Waker) {
+            let mut inner = self.shared.inner.lock().unwrap();
+            if !inner.will_wake(waker) {
+                *inner = waker.clone();
+            }
+            drop(inner);
+            self.shared.woken.store(false, Ordering::Relaxed);
+        }
+
+        pub(super) fn waker(&self) -> &Waker {
+            &self.waker
+        }
+
+        pub(super) fn is_armed(&self) -> bool {
+            // `self` holds two references of its own, one in `waker`.
+            self.shared.woken.load(Ordering::Relaxed) || Arc::strong_count(&self.shared) > 2
+        }
+    }
+}
@@ -0,0 +1,329 @@
+use crate::cancel::{with_cancellation, CancellationToken};
+use futures_core::Stream;
+use std::cell::{Cell, RefCell};
+use std::collections::{HashMap, VecDeque};
+use std::fmt;
+use std::future::Future;
+use std::pin::Pin;
+use std::rc::{Rc, Weak};
+use std::task::{Context, Poll, Waker};
+
+// Shared between a task and its handles. The task holds on to this until its
+// future is dropped, so a `WeakJoinHandle` can only upgrade while the task
+// (or another handle) is still around.
+struct Shared {
+    finished: Cell<bool>,
+    abort: CancellationToken,
+}
+
+// Owned by the task's future, marking the task finished once that future is
+// dropped, whether it completed or was aborted.
+struct Finish(Rc<Shared>);
+
+impl Drop for Finish {
+    fn drop(&mut self) {
+        self.0.finished.set(true);
+    }
+}
+
+/// A handle to a task spawned with `spawn_local_with_handle`.
+///
+/// Dropping the handle detaches the task, which keeps running. Handles can
+/// be cloned, and `downgrade` turns one into a `WeakJoinHandle` which
+/// doesn't keep the task's state alive.
+#[derive(Clone)]
+pub struct JoinHandle {
+    shared: Rc<Shared>,
+}
+
+impl JoinHandle {
+    pub(crate) fn spawn<F>(future: F) -> Self
+    where
+        F: Future<Output = ()> + 'static,
+    {
+        let shared = Rc::new(Shared {
+            finished: Cell::new(false),
+            abort: CancellationToken::new(),
+        });
+        let finish = Finish(Rc::clone(&shared));
+        crate::spawn_local(async move {
+            with_cancellation(future, &finish.0.abort).await;
+        });
+        JoinHandle { shared }
+    }
+
+    /// Returns whether the task has finished, either by completing or by
+    /// being aborted.
+    ///
+    /// A task is only finished once its future has been dropped, so right
+    /// after `abort` this is still `false` until the executor gets to it.
+    pub fn is_finished(&self) -> bool {
+        self.shared.finished.get()
+    }
+
+    /// Aborts the task, dropping its future without polling it again.
+    ///
+    /// The future is dropped the next time the executor runs the task rather
+    /// than within this call, so `abort` is safe to call from inside the task
+    /// itself. Aborting a finished task has no effect.
+    pub fn abort(&self) {
+        self.shared.abort.cancel();
+    }
+
+    /// Creates a `WeakJoinHandle` for the same task.
+    pub fn downgrade(&self) -> WeakJoinHandle {
+        WeakJoinHandle {
+            shared: Rc::downgrade(&self.shared),
+        }
+    }
+}
+
+impl fmt::Debug for JoinHandle {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("JoinHandle")
+            .field("finished", &self.is_finished())
+            .finish()
+    }
+}
+
+/// A handle to a task which, unlike `JoinHandle`, doesn't keep the task's
+/// state alive once the task is done.
+///
+/// This suits registries of in-flight work, such as deduplicating requests
+/// by id: entries can be checked with `is_finished` and reused with
+/// `upgrade` without holding on to anything for tasks that are gone.
+#[derive(Clone)]
+pub struct WeakJoinHandle {
+    shared: Weak<Shared>,
+}
+
+impl WeakJoinHandle {
+    /// Returns whether the task has finished, either by completing or by
+    /// being aborted.
+    pub fn is_finished(&self) -> bool {
+        match self.shared.upgrade() {
+            Some(shared) => shared.finished.get(),
+            None => true,
+        }
+    }
+
+    /// Returns a `JoinHandle` for the task, or `None` if it has finished.
+    pub fn upgrade(&self) -> Option<JoinHandle> {
+        let shared = self.shared.upgrade()?;
+        if shared.finished.get() {
+            return None;
+        }
+        Some(JoinHandle { shared })
+    }
+}
+
+impl fmt::Debug for WeakJoinHandle {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("WeakJoinHandle")
+            .field("finished", &self.is_finished())
+            .finish()
+    }
+}
+
+// Shared between a `CompletionStream` and the tasks it spawned.
+struct Completions<T> {
+    outputs: VecDeque<(usize, T)>,
+    // Tasks which haven't finished yet
+    remaining: usize,
+    waker: Option<Waker>,
+}
+
+// Owned by each task of a `CompletionStream`, counting it as finished once
+// its future is dropped, whether it completed or was aborted.
+struct Completion<T>(Rc<RefCell<Completions<T>>>);
+
+impl<T> Drop for Completion<T> {
+    fn drop(&mut self) {
+        let waker = {
+            let mut completions = self.0.borrow_mut();
+            completions.remaining -= 1;
+            completions.waker.take()
+        };
+        if let Some(waker) = waker {
+            waker.wake();
+        }
+    }
+}
+
+/// Stream returned by `spawn_local_batch`, yielding each task's output along
+/// with its index as soon as the task completes.
+///
+/// Dropping the stream aborts the tasks which haven't completed yet.
+pub struct CompletionStream<T> {
+    completions: Rc<RefCell<Completions<T>>>,
+    handles: Vec<JoinHandle>,
+}
+
+impl<T: 'static> CompletionStream<T> {
+    pub(crate) fn spawn<I>(futures: I) -> Self
+    where
+        I: IntoIterator,
+        I::Item: Future<Output = T> + 'static,
+    {
+        let completions = Rc::new(RefCell::new(Completions {
+            outputs: VecDeque::new(),
+            remaining: 0,
+            waker: None,
+        }));
+        let handles = futures
+            .into_iter()
+            .enumerate()
+            .map(|(index, future)| {
+                completions.borrow_mut().remaining += 1;
+                let completion = Completion(Rc::clone(&completions));
+                JoinHandle::spawn(async move {
+                    let output = future.await;
+                    completion.0.borrow_mut().outputs.push_back((index, output));
+                    // Dropping `completion` wakes the stream.
+                })
+            })
+            .collect();
+        CompletionStream {
+            completions,
+            handles,
+        }
+    }
+}
+
+impl<T> Stream for CompletionStream<T> {
+    type Item = (usize, T);
+
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context) -> Poll<Option<(usize, T)>> {
+        let mut completions = self.completions.borrow_mut();
+        if let Some(output) = completions.outputs.pop_front() {
+            return Poll::Ready(Some(output));
+        }
+        if completions.remaining == 0 {
+            return Poll::Ready(None);
+        }
+        completions.waker = Some(cx.waker().clone());
+        Poll::Pending
+    }
+}
+
+impl<T> Drop for CompletionStream<T> {
+    fn drop(&mut self) {
+        for handle in &self.handles {
+            handle.abort();
+        }
+    }
+}
+
+impl<T> fmt::Debug for CompletionStream<T> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("CompletionStream")
+            .field("remaining", &self.completions.borrow().remaining)
+            .finish()
+    }
+}
+
+// Shared between a `TaskRegistry` and its tasks, which deregister
+// themselves once they're done.
+struct Registered {
+    tasks: RefCell<HashMap<u64, JoinHandle>>,
+    next_id: Cell<u64>,
+}
+
+// Owned by a registered task's future, removing the task from the registry
+// once that future is dropped, whether it completed or was aborted.
+struct Deregister {
+    registered: Weak<Registered>,
+    id: u64,
+}
+
+impl Drop for Deregister {
+    fn drop(&mut self) {
+        if let Some(registered) = self.registered.upgrade() {
+            let handle = registered.tasks.borrow_mut().remove(&self.id);
+            drop(handle);
+        }
+    }
+}
+
+/// A set of tasks which can be aborted together, such as all the tasks of a
+/// subsystem on teardown.
+///
+/// Tasks are added with `spawn_local_registered`, and leave the registry on
+/// their own once they finish, so it only ever holds the ones still running.
+/// Clones of a registry share the same tasks.
+#[derive(Clone)]
+pub struct TaskRegistry {
+    registered: Rc<Registered>,
+}
+
+impl TaskRegistry {
+    /// Creates an empty registry.
+    pub fn new() -> Self {
+        TaskRegistry {
+            registered: Rc::new(Registered {
+                tasks: RefCell::new(HashMap::new()),
+                next_id: Cell::new(0),
+            }),
+        }
+    }
+
+    pub(crate) fn spawn<F>(&self, future: F) -> JoinHandle
+    where
+        F: Future<Output = ()> + 'static,
+    {
+        let id = self.registered.next_id.get();
+        self.registered.next_id.set(id.wrapping_add(1));
+
+        let deregister = Deregister {
+            registered: Rc::downgrade(&self.registered),
+            id,
+        };
+        let handle = JoinHandle::spawn(async move {
+            let _deregister = deregister;
+            future.await;
+        });
+        // The future is already gone if the executor refused it.
+        if !handle.is_finished() {
+            self.registered
+                .tasks
+                .borrow_mut()
+                .insert(id, handle.clone());
+        }
+        handle
+    }
+
+    /// Aborts every task in the registry, as `JoinHandle::abort` does.
+    ///
+    /// Tasks spawned into the registry afterwards aren't affected.
+    pub fn abort_all(&self) {
+        let handles: Vec<_> = self.registered.tasks.borrow().values().cloned().collect();
+        for handle in handles {
+            handle.abort();
+        }
+    }
+
+    /// Returns the number of tasks in the registry, which are the ones that
+    /// haven't finished yet.
+    pub fn len(&self) -> usize {
+        self.registered.tasks.borrow().len()
+    }
+
+    /// Returns whether the registry has no tasks.
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+}
+
+impl Default for TaskRegistry {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl fmt::Debug for TaskRegistry {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("TaskRegistry")
+            .field("len", &self.len())
+            .finish()
+    }
+}
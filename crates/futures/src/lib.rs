@@ -34,15 +34,54 @@
 #![deny(missing_docs)]
 
 use js_sys::Promise;
-use std::cell::RefCell;
+use std::cell::{Cell, RefCell};
 use std::fmt;
 use std::future::Future;
 use std::pin::Pin;
 use std::rc::Rc;
 use std::task::{Context, Poll, Waker};
 use wasm_bindgen::prelude::*;
+use wasm_bindgen::JsCast;
 
+mod barrier;
+mod cancel;
+mod dispose;
+mod future;
+mod join;
+mod observer;
 mod queue;
+mod single_flight;
+pub mod stream;
+mod strict;
+mod task_local;
+mod timer;
+
+pub use barrier::{Barrier, BarrierWait, BarrierWaitResult};
+pub use cancel::{
+    abort_signal_any, abort_signal_timeout, with_cancellation, CancellationToken, Cancelled,
+    WithCancellation,
+};
+pub use dispose::{async_disposable, disposable};
+pub use future::{
+    bounded_polls, finally, inspect, poll_fn, poll_once, race2_biased, BoundedPolls, Finally,
+    FirstYield, Inspect, PollFn, PollLimitExceeded, Race2Biased, RaceWinner,
+};
+pub use join::{CompletionStream, JoinHandle, TaskRegistry, WeakJoinHandle};
+pub use observer::{
+    add_queue_observer, remove_queue_observer, set_queue_observer, ObserverId, QueueObserver,
+};
+pub use queue::{
+    ExecutorError, Priority, QueueBuilder, QueueDepths, QueueInUse, QueueStats, SchedulerKind,
+    WakeMode,
+};
+pub use single_flight::{SingleFlight, SingleFlightWait};
+pub use strict::{set_strict_mode, strict_borrow, strict_borrow_mut, StrictRef, StrictRefMut};
+pub use task_local::{AccessError, TaskLocalKey};
+pub use timer::{
+    before_next_frame, next_idle_deadline, sleep, sleep_handle, timeout_or, BeforeNextFrame,
+    CancelableSleep, IdleDeadline, NextIdleDeadline, PeriodicHandle, Sleep, SleepCanceller,
+    TimeoutOr,
+};
 
 mod task {
     use cfg_if::cfg_if;
@@ -64,32 +103,51 @@ mod task {
 ///
 /// The `future` must be `'static` because it will be scheduled
 /// to run in the background and cannot contain any stack references.
+/// Capture what it needs by value instead, with an `async move` block. If
+/// the compiler reports that an async block "may outlive the current
+/// function" this is what it's asking for.
+///
+/// Unlike the `spawn` of multithreaded executors the `future` need not be
+/// `Send`, so it can hold `JsValue`s, `Rc`s and the like. The flip side is
+/// that it always runs on the thread which spawned it, right alongside that
+/// thread's other tasks: code written for a multithreaded executor which
+/// expects spawned futures to run in parallel, or relies on blocking on one
+/// another, won't work here even though it compiles.
 ///
 /// The `future` will always be run on the next microtask tick even if it
-/// immediately returns `Poll::Ready`.
+/// immediately returns `Poll::Ready`. It's queued with normal priority,
+/// unless `set_default_spawn_priority` says otherwise.
+///
+/// It's safe to call this from a `Drop` impl, including one which runs while
+/// the executor drops a finished task's future (and so everything the future
+/// captured). The executor never holds on to its internal state while user
+/// destructors run.
 ///
 /// # Panics
 ///
-/// This function has the same panic behavior as `future_to_promise`.
+/// This function has the same panic behavior as `future_to_promise`, except
+/// that there's no promise to reject. Instead the error (or exception) is
+/// rethrown from a later microtask, so it still reaches the host as an
+/// uncaught exception while the executor carries on with the other tasks.
 #[inline]
 pub fn spawn_local<F>(future: F)
 where
     F: Future<Output = ()> + 'static,
 {
-    task::Task::spawn(Box::pin(future), false);
+    task::Task::spawn(Box::pin(future), default_high_priority());
 }
 
 /// Runs a Rust `Future` on the current thread.
 ///
-/// The `future` must be `'static` because it will be scheduled
-/// to run in the background and cannot contain any stack references.
+/// The `future` must be `'static` but need not be `Send`, as described for
+/// `spawn_local`.
 ///
 /// The `future` will always be run on the next microtask tick even if it
 /// immediately returns `Poll::Ready`.
 ///
 /// # Panics
 ///
-/// This function has the same panic behavior as `future_to_promise`.
+/// This function has the same panic behavior as `spawn_local`.
 #[inline]
 pub fn spawn_local_high_priority<F>(future: F)
 where
@@ -98,21 +156,867 @@ where
     task::Task::spawn(Box::pin(future), true);
 }
 
+/// Runs the future of the async closure `f` on the current thread like
+/// `spawn_local`.
+///
+/// This saves wrapping the closure in an `async move` block of its own, so
+/// `spawn_local_async(async || ...)` works as well as
+/// `spawn_local(async move { ... })`. The closure is called on the spot,
+/// and its future is run like any other.
+///
+/// This requires the `async-closures` feature, as async closures need Rust
+/// 1.85 or later.
+#[cfg(feature = "async-closures")]
+pub fn spawn_local_async<F>(f: F)
+where
+    F: std::ops::AsyncFnOnce() + 'static,
+{
+    spawn_local(async move { f().await });
+}
+
+/// Runs a Rust `Future` on the current thread like `spawn_local`, calling
+/// `on_first_yield` as soon as its first poll is over.
+///
+/// That's when the future first returns `Pending` at an await point, or, if
+/// it never does, when it completes, which the `FirstYield` passed to the
+/// callback tells apart. This lets instrumentation measure how long a task
+/// took to get going separately from how long it took to finish.
+pub fn spawn_local_with_first_yield<F, C>(future: F, on_first_yield: C)
+where
+    F: Future<Output = ()> + 'static,
+    C: FnOnce(FirstYield) + 'static,
+{
+    spawn_local(future::OnFirstYield::new(future, on_first_yield));
+}
+
+/// Runs a Rust `Future` on the current thread, ordered by `weight` among the
+/// normal priority tasks.
+///
+/// Whenever the executor picks the next normal priority task to run it takes
+/// the one with the highest weight, and tasks of equal weight run in the
+/// order they were woken. Tasks spawned with `spawn_local` have a weight of
+/// 0. High priority tasks still run first, and the coop budget applies as
+/// usual.
+///
+/// This requires the `task-weights` feature, without which normal priority
+/// tasks always run in the order they were woken.
+#[cfg(feature = "task-weights")]
+pub fn spawn_local_priority<F>(future: F, weight: u32)
+where
+    F: Future<Output = ()> + 'static,
+{
+    task::Task::spawn_weighted(Box::pin(future), weight);
+}
+
+/// Runs a Rust `Future` on the current thread as part of the lane `lane`,
+/// so that no one lane can starve the others.
+///
+/// Normally the executor runs normal priority tasks in the order they were
+/// woken, which lets a source of many tasks (a chatty subscription, say)
+/// hold up everything queued behind it. While tasks spawned with this are
+/// queued, the executor instead takes turns between lanes, running one task
+/// from each lane with queued tasks per turn. Within a lane tasks run in the
+/// order they were woken. Tasks spawned any other way share a lane of their
+/// own, separate from every `lane` passed here. High priority tasks still
+/// run first, and the coop budget applies as usual.
+pub fn spawn_local_lane<F>(lane: u32, future: F)
+where
+    F: Future<Output = ()> + 'static,
+{
+    task::Task::spawn_in_lane(Box::pin(future), lane);
+}
+
+/// Runs a Rust `Future` on the current thread like `spawn_local`, except
+/// that `shutdown` runs the task instead of dropping it.
+///
+/// This is for cleanup that mustn't be lost when the executor is shut down,
+/// like releasing JS handles or closing sockets. Note that `shutdown` only
+/// polls the task while it's queued, and only for as long as it makes
+/// progress synchronously, so cleanup awaiting JS doesn't get to finish.
+pub fn spawn_local_on_shutdown<F>(future: F)
+where
+    F: Future<Output = ()> + 'static,
+{
+    task::Task::spawn_on_shutdown(Box::pin(future));
+}
+
+/// Runs a Rust `Future` on the current thread until it completes or `signal`
+/// is aborted.
+///
+/// Once the signal fires the future is dropped without being polled again,
+/// running its destructors. This lets a single `AbortController` cancel both
+/// a `fetch` and the Rust task processing its response. If the signal has
+/// already been aborted the future never runs at all.
+///
+/// The listener registered on `signal` is removed when the task finishes,
+/// whichever way that happens.
+pub fn spawn_local_with_signal<F>(future: F, signal: web_sys::AbortSignal)
+where
+    F: Future<Output = ()> + 'static,
+{
+    let token = CancellationToken::new();
+    let listener = cancel::AbortListener::new(signal, &token);
+    spawn_local(async move {
+        let _listener = listener;
+        with_cancellation(future, &token).await;
+    });
+}
+
+/// Runs a Rust `Future` on the current thread like `spawn_local`, returning
+/// a `JoinHandle` which can check whether it has finished or abort it.
+pub fn spawn_local_with_handle<F>(future: F) -> JoinHandle
+where
+    F: Future<Output = ()> + 'static,
+{
+    JoinHandle::spawn(future)
+}
+
+/// Runs a Rust `Future` on the current thread like
+/// `spawn_local_with_handle`, adding it to `registry` until it finishes.
+///
+/// `TaskRegistry::abort_all` then aborts it along with every other task in
+/// the registry.
+pub fn spawn_local_registered<F>(registry: &TaskRegistry, future: F) -> JoinHandle
+where
+    F: Future<Output = ()> + 'static,
+{
+    registry.spawn(future)
+}
+
+/// Runs the future returned by `f` on the current thread, then again every
+/// `period_ms` milliseconds until the returned handle is stopped or dropped.
+///
+/// Runs never overlap: the next one is only started once the previous one
+/// has finished. They're timed from when each was due, so the cadence doesn't
+/// drift by however long `f` takes. If a run takes longer than the period,
+/// the runs it overlapped are skipped and the next one starts right after.
+///
+/// # Panics
+///
+/// The task panics if the host has no `setTimeout` to wait with.
+pub fn spawn_periodic<F, Fut>(period_ms: i32, f: F) -> PeriodicHandle
+where
+    F: FnMut() -> Fut + 'static,
+    Fut: Future<Output = ()> + 'static,
+{
+    PeriodicHandle::spawn(period_ms, f)
+}
+
+/// Runs each of `futures` on the current thread like `spawn_local`,
+/// returning a stream of their outputs in the order they complete.
+///
+/// Each output comes tagged with the index of its future in `futures`, so
+/// callers can react to whichever finishes first (the first mirror to
+/// answer, say) and still tell which one it was. The stream ends once every
+/// task has completed. Dropping it aborts the tasks still running, as
+/// `JoinHandle::abort` does.
+pub fn spawn_local_batch<I, T>(futures: I) -> CompletionStream<T>
+where
+    I: IntoIterator,
+    I::Item: Future<Output = T> + 'static,
+    T: 'static,
+{
+    CompletionStream::spawn(futures)
+}
+
+/// Runs a Rust `Future` on the current thread, returning an error if the
+/// executor couldn't schedule a tick to run it.
+///
+/// This is the same as `spawn_local`, except that scheduling failures (for
+/// example in a runtime missing `setTimeout`) are returned instead of
+/// thrown. The future is spawned either way, and runs as soon as a later
+/// spawn or wakeup manages to schedule a tick. The exception is
+/// `ExecutorError::QueueFull`, for which see `set_max_queue_depth`. Like
+/// `spawn_local` it goes by `set_default_spawn_priority`.
+pub fn try_spawn_local<F>(future: F) -> Result<(), ExecutorError>
+where
+    F: Future<Output = ()> + 'static,
+{
+    task::Task::try_spawn(Box::pin(future), default_high_priority())
+}
+
+/// Same as `try_spawn_local`, but spawns the future with high priority as
+/// `spawn_local_high_priority` does.
+pub fn try_spawn_local_high_priority<F>(future: F) -> Result<(), ExecutorError>
+where
+    F: Future<Output = ()> + 'static,
+{
+    task::Task::try_spawn(Box::pin(future), true)
+}
+
+thread_local! {
+    static DEFAULT_SPAWN_PRIORITY: Cell<Priority> = Cell::new(Priority::Normal);
+}
+
+fn default_high_priority() -> bool {
+    DEFAULT_SPAWN_PRIORITY.with(|priority| priority.get()) == Priority::High
+}
+
+/// Sets the priority `spawn_local` and `try_spawn_local` spawn futures with
+/// on the current thread, which is `Priority::Normal` by default.
+///
+/// This lets a latency critical subsystem have all of its tasks run with
+/// high priority without changing every call site. It only affects spawns
+/// made from now on: tasks which were already spawned keep the priority
+/// they were spawned with. Spawn functions which pick a priority or lane
+/// themselves, like `spawn_local_high_priority`, ignore it.
+pub fn set_default_spawn_priority(priority: Priority) {
+    DEFAULT_SPAWN_PRIORITY.with(|default| default.set(priority));
+}
+
+/// A boxed future as spawned on the current thread, which is what spawn
+/// middleware works with.
+pub type LocalBoxFuture = Pin<Box<dyn Future<Output = ()> + 'static>>;
+
+type SpawnMiddleware = Rc<dyn Fn(LocalBoxFuture) -> LocalBoxFuture>;
+
+thread_local! {
+    static SPAWN_MIDDLEWARE: RefCell<Option<SpawnMiddleware>> = RefCell::new(None);
+}
+
+/// Sets a function which wraps every future spawned on the current thread
+/// before it becomes a task. Passing `None` removes the current one.
+///
+/// This lets an app handle cross-cutting concerns uniformly, such as
+/// propagating tracing context into tasks or capturing their errors,
+/// without touching every call site. It applies to all spawn functions,
+/// each spawn being wrapped exactly once (even by functions like
+/// `spawn_local_with_handle` which wrap the future themselves), and only to
+/// spawns made from now on. Without middleware spawning costs nothing extra.
+pub fn set_spawn_middleware(middleware: Option<Box<dyn Fn(LocalBoxFuture) -> LocalBoxFuture>>) {
+    SPAWN_MIDDLEWARE.with(|current| {
+        drop(current.replace(middleware.map(Rc::from)));
+    });
+}
+
+/// Wraps a future about to be spawned with the spawn middleware, if any.
+pub(crate) fn apply_spawn_middleware(future: LocalBoxFuture) -> LocalBoxFuture {
+    // Clone the middleware out so that it's free to spawn or replace itself.
+    let middleware = SPAWN_MIDDLEWARE.with(|current| current.borrow().clone());
+    match middleware {
+        Some(middleware) => middleware(future),
+        None => future,
+    }
+}
 
 /// Set the maximum number of tasks that the executor will run during a tick.
 /// 
 /// Once this budget is exceeded, it will yield to allow the event loop to process other tasks 
-/// before resuming work.
+/// before resuming work. At least one task is always run per tick, so a
+/// budget of zero behaves like a budget of one.
+///
+/// To configure the budget before the executor is first used, see
+/// `QueueBuilder`.
 pub fn set_coop_budget(budget: u32) {
-    crate::queue::QUEUE.with(|queue| {
-        queue.set_coop_budget(budget);
-    });
+    swap_coop_budget(budget);
+}
+
+/// Sets the coop budget like `set_coop_budget`, returning the previous one.
+pub fn swap_coop_budget(budget: u32) -> u32 {
+    crate::queue::QUEUE.with(|queue| queue.swap_coop_budget(budget))
+}
+
+/// Sets the coop budget until the returned guard is dropped, at which point
+/// the previous budget is restored.
+///
+/// This is handy for temporarily raising the budget around a known bulk
+/// operation. The guard restores the budget even if the scope unwinds
+/// because of a panic. Nested guards should be dropped in the reverse order
+/// they were created, as each one restores the budget it replaced.
+pub fn scoped_coop_budget(budget: u32) -> CoopBudgetGuard {
+    CoopBudgetGuard {
+        previous: swap_coop_budget(budget),
+    }
+}
+
+/// Guard returned by `scoped_coop_budget`, which restores the previous coop
+/// budget when dropped.
+#[derive(Debug)]
+#[must_use = "the previous budget is restored as soon as the guard is dropped"]
+pub struct CoopBudgetGuard {
+    previous: u32,
+}
+
+impl Drop for CoopBudgetGuard {
+    fn drop(&mut self) {
+        swap_coop_budget(self.previous);
+    }
+}
+
+/// Sets the function the executor uses to schedule macrotasks in hosts
+/// without a `setTimeout`. Passing `None` removes the current one.
+///
+/// Macrotasks are needed to resume work after a tick yields because its coop
+/// budget ran out. The executor uses the window's `setTimeout` if there is a
+/// window, and otherwise the global scope's, which covers workers and most
+/// other JS hosts. Only in hosts with neither is `scheduler` called, with a
+/// callback to invoke from a later turn of the event loop and the delay in
+/// milliseconds configured with `QueueBuilder::reschedule_delay_ms`. Without
+/// a scheduler such hosts fail with `ExecutorError::NoScheduler`.
+///
+/// The callback must not be invoked from inside a task. A tick started that
+/// way is ignored, and panics in debug builds.
+pub fn set_custom_scheduler(scheduler: Option<Box<queue::SchedulerFn>>) {
+    crate::queue::QUEUE.with(|queue| queue.set_custom_scheduler(scheduler));
+}
+
+/// Returns the API the executor on the current thread uses to schedule the
+/// microtask which runs its next tick.
+///
+/// This is detected once, when the executor is created: `queueMicrotask` if
+/// the host has it, and `Promise.then` otherwise. Returns `None` if neither
+/// could be used, in which case spawning fails with
+/// `ExecutorError::Unavailable`. While `run_budgeted_on_raf` is on this is
+/// `SchedulerKind::AnimationFrame` instead, if the host has it.
+pub fn active_scheduler() -> Option<SchedulerKind> {
+    crate::queue::QUEUE.with(|queue| queue.microtask_scheduler())
+}
+
+/// Returns the API the executor on the current thread would use to schedule
+/// a macrotask, which resumes work after a tick yields because its budget or
+/// deadline ran out, or `None` if it has none and would fail with
+/// `ExecutorError::NoScheduler`.
+///
+/// A `MessageChannel` is detected when the executor is created and only used
+/// with a reschedule delay of 0. `setTimeout` and the custom scheduler are
+/// looked up each time, so the result can change as they come and go. While
+/// `run_budgeted_on_raf` is on this is `SchedulerKind::AnimationFrame`
+/// instead, if the host has it.
+pub fn active_macrotask_scheduler() -> Option<SchedulerKind> {
+    crate::queue::QUEUE.with(|queue| queue.macrotask_scheduler())
+}
+
+/// Sets a hook which runs at the start of every executor tick, before any
+/// task is polled. Passing `None` removes the current hook.
+///
+/// A tick is one run of the executor from the event loop, however many tasks
+/// it polls, which makes this a good place to refresh state that all tasks
+/// in the tick can share (such as a cached `performance.now()`). Tasks run
+/// by `drive_n` or `flush_microtasks` aren't part of a tick.
+pub fn set_tick_prologue(hook: Option<Box<dyn FnMut()>>) {
+    crate::queue::QUEUE.with(|queue| queue.set_tick_prologue(hook));
+}
+
+/// Sets a hook which runs at the end of every executor tick, after its last
+/// task was polled. Passing `None` removes the current hook.
+///
+/// See `set_tick_prologue` for what makes up a tick.
+pub fn set_tick_epilogue(hook: Option<Box<dyn FnMut()>>) {
+    crate::queue::QUEUE.with(|queue| queue.set_tick_epilogue(hook));
+}
+
+/// Stops the executor on the current thread from running normal priority
+/// tasks until `resume_normal` is called, while high priority tasks keep
+/// running as usual.
+///
+/// This suits latency critical phases, such as handling a drag gesture, in
+/// which input continuations spawned with `spawn_local_high_priority` should
+/// stay responsive while background work waits. Normal priority tasks are
+/// still queued while paused (by spawns and wakeups alike), and `drive_n`
+/// and `flush_microtasks` skip them too.
+pub fn pause_normal() {
+    // Pausing never needs to schedule anything.
+    let _ = crate::queue::QUEUE.with(|queue| queue.set_normal_paused(true));
+}
+
+/// Lets the executor on the current thread run normal priority tasks again
+/// after `pause_normal`, scheduling a tick for the ones that were queued in
+/// the meantime.
+///
+/// Returns an error if that tick couldn't be scheduled, in which case the
+/// tasks stay queued for the next spawn or wakeup to retry. Calling this
+/// while not paused does nothing.
+pub fn resume_normal() -> Result<(), ExecutorError> {
+    crate::queue::QUEUE.with(|queue| queue.set_normal_paused(false))
+}
+
+/// Clears the executor on the current thread, returning whether it did so.
+///
+/// Every queued task is dropped without being polled again, except for the
+/// ones spawned with `spawn_local_on_shutdown`, which run first instead. Any
+/// tasks they wake are handled the same way, until nothing at all is
+/// queued. Tasks waiting on JS (a timer or an unsettled promise, say) aren't
+/// queued, so they're left alone. The executor keeps working afterwards, so
+/// tasks spawned or woken later run as usual.
+///
+/// Calling this from within a task returns `false` without doing anything,
+/// as for `flush_microtasks`.
+pub fn shutdown() -> bool {
+    crate::queue::QUEUE.with(|queue| queue.shutdown())
+}
+
+/// Returns a future which resolves once the executor on the current thread
+/// has quiesced, with both its high priority and normal queues empty.
+///
+/// This is checked every time a tick runs out of tasks, and the future
+/// resolves at the first such moment, after every task spawned or woken in
+/// the meantime has run. Unlike `flush_microtasks` it doesn't run anything
+/// itself, so it can be awaited from a task, which is what test harnesses
+/// and shutdown code want. Tasks waiting on JS (a timer or an unsettled
+/// promise, say) aren't queued, so they don't hold it up. If nothing is
+/// running or queued when it's first polled it resolves right away.
+pub fn until_idle() -> UntilIdle {
+    UntilIdle { waiter: None }
+}
+
+/// Future returned by `until_idle`.
+pub struct UntilIdle {
+    waiter: Option<Rc<queue::IdleWaiter>>,
+}
+
+impl Future for UntilIdle {
+    type Output = ();
+
+    fn poll(mut self: Pin<&mut Self>, cx: &mut Context) -> Poll<()> {
+        crate::queue::QUEUE.with(|queue| queue.poll_idle(&mut self.waiter, cx))
+    }
+}
+
+impl fmt::Debug for UntilIdle {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "UntilIdle {{ ... }}")
+    }
+}
+
+/// Aborts the task calling this, so that its future is dropped as soon as
+/// the current poll returns instead of ever being polled again.
+///
+/// This is for a future which finds out mid-poll that it can't go on, such
+/// as when an invariant broke, and would rather stop cleanly than panic. The
+/// poll still runs to completion and whatever it returns is ignored, even
+/// `Pending`, and wakeups of the task are ignored from then on. It returns
+/// `false`, doing nothing, when called outside of a task.
+pub fn abort_current_task() -> bool {
+    crate::queue::QUEUE.with(|queue| queue.abort_current_task())
+}
+
+/// Sets how the task calling this is queued whenever it's woken from now on.
+///
+/// With `WakeMode::Macrotask` the task resumes on a macrotask instead of the
+/// next microtask tick, which gives the browser a chance to render first:
+/// after a layout-affecting DOM write, say, or between the steps of a long
+/// animation. Tasks woken this way still run in the order they were woken
+/// among themselves, and fall back to a microtask if the host can't
+/// schedule macrotasks.
+///
+/// This has no effect on high priority tasks, which always resume on the
+/// next microtask tick. It returns `false`, doing nothing, when called
+/// outside of a task.
+pub fn set_wake_mode(mode: WakeMode) -> bool {
+    crate::queue::QUEUE.with(|queue| queue.set_current_wake_mode(mode))
+}
+
+/// Makes the next wake of the task calling this queue it with high
+/// priority, just that once.
+///
+/// This is for a normal priority task which knows that the continuation
+/// after its next await is latency sensitive, such as applying a response
+/// to the UI, without reclassifying the task for good. Once woken, the task
+/// runs ahead of the normal priority tasks already queued, and goes back to
+/// normal priority for the wakes after that. The boost takes precedence over
+/// the task's wake mode.
+///
+/// It returns `false`, doing nothing, when called outside of a task.
+pub fn boost_current_task_next_wake() -> bool {
+    crate::queue::QUEUE.with(|queue| queue.boost_current_task())
+}
+
+/// Sets whether `JsFuture`s on the current thread cooperate with the coop
+/// budget, which is off by default.
+///
+/// The budget limits how many tasks run per tick, but a single task which
+/// awaits a long chain of `JsFuture`s that are already complete (such as
+/// ones made with `JsFuture::resolved`) runs through all of them in one go.
+/// With this enabled, once more `JsFuture`s than the budget complete during
+/// a single poll of a task, the next one to complete yields instead: its
+/// task goes to the back of the queue and picks up the result once the
+/// tasks ahead of it have had their turn. This changes the order tasks run
+/// in, hence the opt in.
+pub fn set_jsfuture_coop(enabled: bool) {
+    crate::queue::QUEUE.with(|queue| queue.set_jsfuture_coop(enabled));
+}
+
+/// Sets whether the executor on the current thread runs all ready tasks
+/// when the page is being hidden or unloaded, which is off by default.
+///
+/// Enabling this registers a listener for the `pagehide` and `beforeunload`
+/// events which does what `flush_microtasks` does, giving tasks spawned late
+/// (to send analytics or save state, say) a last chance to run before
+/// navigation cuts them off. This is best effort: the tasks only run up to
+/// their next await, so async work such as a `fetch` they start won't
+/// complete, unless the browser keeps it alive on its own as with
+/// `navigator.sendBeacon`. Disabling it removes the listener again.
+pub fn drain_on_page_hide(enabled: bool) {
+    crate::queue::QUEUE.with(|queue| queue.set_drain_on_page_hide(enabled));
+}
+
+/// Makes the executor on the current thread run normal priority tasks in a
+/// pseudo-random order derived from `seed`, instead of the order they were
+/// queued in. Passing `None` goes back to the usual order, which is also the
+/// default.
+///
+/// This is a debugging aid: running tests with a few different seeds flushes
+/// out code which relies on the incidental order tasks happen to run in.
+/// The order is reproducible for a given seed, since setting a seed restarts
+/// its sequence, as long as the same tasks get queued in the same order.
+/// High priority tasks aren't shuffled, and neither are tasks ordered by the
+/// `task-weights` feature.
+pub fn set_shuffle_seed(seed: Option<u64>) {
+    crate::queue::QUEUE.with(|queue| queue.set_shuffle_seed(seed));
+}
+
+/// Runs the executor on the current thread on animation frames, spending up
+/// to `frame_budget_ms` milliseconds on normal tasks each frame.
+///
+/// Instead of draining the queue on a microtask as soon as a task is woken,
+/// every tick waits for the next `requestAnimationFrame` callback, and the
+/// tasks still queued once the budget runs out are left for the frame after
+/// that. This paces work to the display's refresh rate and keeps it from
+/// competing with rendering within a frame. The frame budget takes the place
+/// of the tick deadline, while the coop budget still applies as well. High
+/// priority tasks aren't budgeted, but run on frames too.
+///
+/// Hosts without `requestAnimationFrame`, such as most workers, keep
+/// scheduling ticks as usual. Passing `None` turns this back off, though a
+/// tick already waiting on a frame still runs on it.
+pub fn run_budgeted_on_raf(frame_budget_ms: Option<f64>) {
+    crate::queue::QUEUE.with(|queue| queue.set_frame_budget_ms(frame_budget_ms));
+}
+
+/// Makes ticks of the executor on the current thread yield to the event
+/// loop as soon as the host reports pending user input, returning whether
+/// the host supports that.
+///
+/// While this is on, each normal priority task a tick runs is followed by a
+/// call to `navigator.scheduling.isInputPending()`, and if it returns `true`
+/// the tick ends early, leaving the rest of the queue for a macrotask as if
+/// the coop budget had run out. This lets input handlers run the moment the
+/// user interacts instead of after background work. High priority tasks
+/// aren't affected, and at least one normal task still runs per tick.
+///
+/// The API is looked up when this is turned on. Hosts without it keep going
+/// by the coop budget and tick deadline alone, and this returns `false`.
+/// It's off by default.
+pub fn set_yield_on_input_pending(enabled: bool) -> bool {
+    crate::queue::QUEUE.with(|queue| queue.set_yield_on_input_pending(enabled))
+}
+
+/// Batches the ticks of the executor on the current thread which would
+/// start within `window_ms` milliseconds of the previous one into a single
+/// macrotask. Passing `None` turns this back off, which is the default.
+///
+/// Wakeups from the same synchronous burst of JS callbacks already share a
+/// tick, but a burst spread over several microtasks normally gets a tick for
+/// each. With a window set, a wakeup arriving too soon after the last tick
+/// schedules the next one with `setTimeout` for the end of the window
+/// instead, and every wakeup until then joins it. This trades a little
+/// latency for fewer ticks under bursty load. High priority tasks are
+/// delayed along with the rest, and if the timer can't be scheduled the tick
+/// runs on a microtask as usual.
+pub fn set_coalesce_window_ms(window_ms: Option<f64>) {
+    crate::queue::QUEUE.with(|queue| queue.set_coalesce_window_ms(window_ms));
+}
+
+/// Limits how many tasks can be queued on the current thread at once, to
+/// protect against runaway producers. Passing `None` removes the limit,
+/// which is the default.
+///
+/// Once the high priority and normal queues together hold `max_depth`
+/// tasks, counting ones woken in `WakeMode::Macrotask` that haven't been
+/// moved into the queue yet, spawning fails until the executor has worked some of them off:
+/// `try_spawn_local` and `try_spawn_local_high_priority` return
+/// `ExecutorError::QueueFull`, the other spawn functions drop the future and
+/// log a warning, and `future_to_promise` rejects its promise with the
+/// error. The future is dropped without ever being polled in every case.
+/// Wakeups of tasks which were already spawned are never refused, so tasks
+/// can't get lost halfway through.
+pub fn set_max_queue_depth(max_depth: Option<usize>) {
+    crate::queue::QUEUE.with(|queue| queue.set_max_depth(max_depth));
+}
+
+/// Returns how many tasks are waiting in each of the executor's queues on
+/// the current thread, which helps tell whether a backlog is made of high
+/// or normal priority work.
+pub fn queue_depths() -> QueueDepths {
+    crate::queue::QUEUE.with(|queue| queue.depths())
+}
+
+/// Returns counters describing how the executor on the current thread has
+/// been scheduling its ticks, such as how often it fell back to macrotasks.
+pub fn queue_stats() -> QueueStats {
+    crate::queue::QUEUE.with(|queue| queue.stats())
+}
+
+/// Returns a JS array describing the tasks queued on the executor of the
+/// current thread, for devtools and other JS-side tooling to inspect.
+///
+/// Each entry is an object with the task's `id`, its `priority` (`"high"` or
+/// `"normal"`) and `age_ticks`, the number of ticks started since it was
+/// queued. High priority tasks come first in the order they'll run, while the
+/// order of the rest isn't meaningful. The array is a copy, so it doesn't
+/// change as the tasks run.
+///
+/// Tasks aren't given names when they're spawned, so entries have no `name`
+/// field. To tell tasks apart, match their ids with the ones reported to a
+/// `QueueObserver` or in `tracing` spans.
+pub fn pending_tasks_snapshot() -> JsValue {
+    crate::queue::QUEUE.with(|queue| queue.pending_tasks_snapshot().into())
+}
+
+/// Sets the executor's internal counters, such as its tick count, to
+/// `value`. This is only meant for testing how they wrap around.
+#[doc(hidden)]
+pub fn __set_scheduling_counters(value: u64) {
+    crate::queue::QUEUE.with(|queue| queue.set_scheduling_counters(value));
+}
+
+/// Synchronously runs up to `max_tasks` ready tasks on the current thread,
+/// returning how many actually ran.
+///
+/// High priority tasks run before normal ones, and the coop budget doesn't
+/// apply, which lets embedders pace the executor themselves. Any tasks left
+/// over still run on the next tick as usual.
+///
+/// Calling this from within a task returns 0 without running anything, as
+/// the executor is already draining the queue.
+pub fn drive_n(max_tasks: usize) -> usize {
+    crate::queue::QUEUE.with(|queue| queue.drive_n(max_tasks, true))
+}
+
+/// Synchronously runs the tasks on the current thread which have been
+/// queued for at least `min_age_ticks` ticks, returning how many ran.
+///
+/// This relieves a backlog of long-pending work, say under memory pressure,
+/// without draining the whole queue: the oldest tasks run first, and younger
+/// ones are left queued to run on the next tick as usual, along with any the
+/// stale ones wake. Tasks held back by `pause_normal` are left alone.
+///
+/// Calling this from within a task returns 0 without running anything, as
+/// for `drive_n`.
+pub fn drain_stale_tasks(min_age_ticks: u32) -> usize {
+    crate::queue::QUEUE.with(|queue| queue.drain_stale_tasks(min_age_ticks))
+}
+
+/// Synchronously runs the high priority tasks on the current thread until
+/// none are left, returning how many ran.
+///
+/// This is meant for synchronous input handlers which want the continuation
+/// work responding to the input, spawned with `spawn_local_high_priority`,
+/// done before they return, without paying for bulk background work. Normal
+/// priority tasks are left queued and run on the next tick as usual, while
+/// high priority tasks woken along the way run as well.
+///
+/// Calling this from within a task returns 0 without running anything, as
+/// for `drive_n`.
+pub fn drain_high_priority_only() -> usize {
+    crate::queue::QUEUE.with(|queue| queue.drive_n(usize::MAX, false))
+}
+
+/// Calls `f` with the executor on the current thread switched to manual
+/// wakes, so that no task runs unless `f` runs it through the driver.
+///
+/// Spawning or waking a task queues it as usual, but no tick is scheduled
+/// for it: `ManualDriver::run_one` and `ManualDriver::run_ready` run queued
+/// tasks on the spot instead. This makes future-heavy logic deterministic
+/// to test, stepping through it one poll at a time without depending on
+/// microtask timing. It's meant to be called from a synchronous test, as the
+/// driver can't run anything while a task is being polled.
+///
+/// Tasks left queued once `f` returns get a tick scheduled as usual. Tasks
+/// woken from JS, say by a promise settling, are only queued after `f` has
+/// returned, so they're out of the driver's reach.
+pub fn with_manual_wakes<F, R>(f: F) -> R
+where
+    F: FnOnce(&ManualDriver) -> R,
+{
+    struct Restore(bool);
+
+    impl Drop for Restore {
+        fn drop(&mut self) {
+            crate::queue::QUEUE.with(|queue| queue.set_manual_wakes(self.0));
+        }
+    }
+
+    let _restore = Restore(crate::queue::QUEUE.with(|queue| queue.set_manual_wakes(true)));
+    f(&ManualDriver { _private: () })
+}
+
+/// Runs queued tasks on behalf of `with_manual_wakes`.
+#[derive(Debug)]
+pub struct ManualDriver {
+    _private: (),
+}
+
+impl ManualDriver {
+    /// Polls the next queued task, high priority ones first, returning
+    /// whether there was one.
+    pub fn run_one(&self) -> bool {
+        drive_n(1) == 1
+    }
+
+    /// Runs tasks until none are queued, including any woken along the way,
+    /// returning how many polls that took.
+    pub fn run_ready(&self) -> usize {
+        drive_n(usize::MAX)
+    }
+}
+
+/// Synchronously runs tasks on the current thread until none are ready,
+/// returning whether it did so.
+///
+/// This is useful for pumping the executor from a callback that JS expects
+/// to have finished its work before it returns. A task which keeps waking
+/// itself keeps this running as well.
+///
+/// Calling this from within a task returns `false` without running anything:
+/// the executor is already draining the queue, and will get to the rest of
+/// the tasks in order once the current one yields.
+pub fn flush_microtasks() -> bool {
+    crate::queue::QUEUE.with(|queue| queue.flush())
 }
 
 struct Inner {
     result: Option<Result<JsValue, JsValue>>,
     task: Option<Waker>,
     callbacks: Option<(Closure<dyn FnMut(JsValue)>, Closure<dyn FnMut(JsValue)>)>,
+    // Whether this future is still counted as live for leak detection
+    counted: bool,
+    // Whether the `JsFuture` is gone, so nothing can observe the result
+    dropped: bool,
+}
+
+impl Inner {
+    fn uncount(&mut self) {
+        if std::mem::replace(&mut self.counted, false) {
+            LIVE_JS_FUTURES.with(|live| live.remove());
+        }
+    }
+}
+
+struct LiveJsFutures {
+    live: Cell<usize>,
+    threshold: Cell<Option<usize>>,
+    reported: Cell<bool>,
+}
+
+impl LiveJsFutures {
+    fn add(&self) {
+        let live = self.live.get() + 1;
+        self.live.set(live);
+
+        let exceeded = match self.threshold.get() {
+            Some(threshold) => live > threshold && !self.reported.replace(true),
+            None => false,
+        };
+        if !exceeded {
+            return;
+        }
+
+        let notified = observer::notify(|observer| observer.jsfuture_leak_threshold_exceeded(live));
+        if !notified {
+            log::warn!(
+                "{} `JsFuture`s are waiting on their promises, which may mean \
+                 some of them never settle",
+                live
+            );
+        }
+    }
+
+    fn remove(&self) {
+        let live = self.live.get() - 1;
+        self.live.set(live);
+
+        // Report again if the count climbs back over the threshold later.
+        if matches!(self.threshold.get(), Some(threshold) if live <= threshold) {
+            self.reported.set(false);
+        }
+    }
+}
+
+thread_local! {
+    static LIVE_JS_FUTURES: LiveJsFutures = LiveJsFutures {
+        live: Cell::new(0),
+        threshold: Cell::new(None),
+        reported: Cell::new(false),
+    };
+}
+
+type RejectionFn = dyn FnMut(&JsValue);
+type RejectionHook = Rc<RefCell<Box<RejectionFn>>>;
+
+thread_local! {
+    static UNHANDLED_REJECTION_HOOK: RefCell<Option<RejectionHook>> = RefCell::new(None);
+}
+
+/// Sets a hook which is called with the error of every `JsFuture` whose
+/// promise rejected but whose `Err` was never observed. Passing `None`
+/// removes the current hook.
+///
+/// This is the Rust-side counterpart of the browser's `unhandledrejection`
+/// event: it fires when a `JsFuture` whose promise has already rejected is
+/// dropped before being polled to completion. Such errors are otherwise
+/// silently lost. Dropping a `JsFuture` whose promise is still pending
+/// cancels it, so a rejection arriving after that isn't reported.
+pub fn set_unhandled_rejection_hook(hook: Option<Box<RejectionFn>>) {
+    UNHANDLED_REJECTION_HOOK.with(|current| {
+        drop(current.replace(hook.map(|hook| Rc::new(RefCell::new(hook)))));
+    });
+}
+
+fn report_unhandled_rejection(error: &JsValue) {
+    // Clone the hook out so that it's free to replace itself.
+    let hook = UNHANDLED_REJECTION_HOOK.with(|current| current.borrow().clone());
+    if let Some(hook) = hook {
+        (hook.borrow_mut())(error);
+    }
+}
+
+/// Reports when more than `threshold` `JsFuture`s are live at once, to help
+/// track down futures whose promises never settle.
+///
+/// A `JsFuture` counts as live from its creation until its promise settles or
+/// it's dropped, whichever comes first. When the count first goes over the
+/// threshold the queue observer is notified (see `set_queue_observer`), or a
+/// warning is logged if there is none. It's only reported again once the
+/// count has dropped back down to the threshold.
+///
+/// This is off by default, and passing `None` turns it back off.
+pub fn set_jsfuture_leak_threshold(threshold: Option<usize>) {
+    LIVE_JS_FUTURES.with(|live| {
+        live.threshold.set(threshold);
+        live.reported.set(false);
+    });
+}
+
+/// Watches for the executor getting stuck, reporting when it has had tasks
+/// to run without running a tick for more than `threshold` milliseconds.
+///
+/// A queue can only get stuck like this if scheduling its next tick failed
+/// without anyone noticing, such as a throwing `setTimeout` on a wakeup from
+/// a JS callback, leaving its tasks waiting for the next spawn or wakeup to
+/// try again. The watchdog checks on the queue with `setInterval`, and
+/// reports to the queue observer (see `set_queue_observer`), or logs a
+/// warning if there is none. Normal tasks held back by `pause_normal` don't
+/// count as stuck.
+///
+/// This is off by default, and passing `None` turns it back off, clearing
+/// the interval.
+pub fn set_stall_threshold_ms(threshold: Option<f64>) {
+    crate::queue::QUEUE.with(|queue| queue.set_stall_threshold_ms(threshold));
+}
+
+/// Reports every poll of a task which takes longer than `threshold`
+/// milliseconds, to help track down tasks that block the thread.
+///
+/// This is like the browser's Long Tasks API, but for individual tasks of
+/// the executor: each report comes with the task's id and how long that poll
+/// ran for. Reports go to the queue observer (see `set_queue_observer`), or
+/// are logged as warnings if there is none. Polls are timed with
+/// `performance.now()`.
+///
+/// This is off by default, and passing `None` turns it back off.
+pub fn set_long_task_threshold_ms(threshold: Option<f64>) {
+    crate::queue::QUEUE.with(|queue| queue.set_long_task_threshold_ms(threshold));
 }
 
 /// A Rust `Future` backed by a JavaScript `Promise`.
@@ -125,6 +1029,8 @@ struct Inner {
 /// Currently this type is constructed with `JsFuture::from`.
 pub struct JsFuture {
     inner: Rc<RefCell<Inner>>,
+    // Aborted if this is dropped before the promise settles
+    abort_on_drop: Option<web_sys::AbortController>,
 }
 
 impl fmt::Debug for JsFuture {
@@ -133,6 +1039,52 @@ impl fmt::Debug for JsFuture {
     }
 }
 
+impl JsFuture {
+    /// Creates a future which is immediately ready with `Ok(value)`.
+    ///
+    /// This behaves like `JsFuture::from(Promise::resolve(&value))`, except
+    /// that no promise or callbacks are involved, so it's the cheap option
+    /// when the value is already at hand. `JsFuture::from` can't take this
+    /// shortcut itself as JS doesn't expose whether a promise has settled.
+    pub fn resolved(value: JsValue) -> JsFuture {
+        JsFuture::ready(Ok(value))
+    }
+
+    /// Creates a future which is immediately ready with `Err(error)`, the
+    /// counterpart of `JsFuture::resolved` for rejections.
+    pub fn rejected(error: JsValue) -> JsFuture {
+        JsFuture::ready(Err(error))
+    }
+
+    /// Aborts `controller` if this future is dropped before its promise
+    /// settles.
+    ///
+    /// Promises can't be cancelled, so dropping a `JsFuture` normally leaves
+    /// the operation behind it running with nobody waiting on the result.
+    /// For operations which take an `AbortSignal`, like `fetch`, this ties
+    /// the operation to the future instead: pass the controller whose signal
+    /// the operation was started with. Once the promise has settled dropping
+    /// the future leaves the controller alone, so it's still safe to use its
+    /// signal for whatever comes next, such as reading a response's body.
+    pub fn with_abort_on_drop(mut self, controller: web_sys::AbortController) -> JsFuture {
+        self.abort_on_drop = Some(controller);
+        self
+    }
+
+    fn ready(result: Result<JsValue, JsValue>) -> JsFuture {
+        JsFuture {
+            inner: Rc::new(RefCell::new(Inner {
+                result: Some(result),
+                task: None,
+                callbacks: None,
+                counted: false,
+                dropped: false,
+            })),
+            abort_on_drop: None,
+        }
+    }
+}
+
 impl From<Promise> for JsFuture {
     fn from(js: Promise) -> JsFuture {
         // Use the `then` method to schedule two callbacks, one for the
@@ -154,7 +1106,10 @@ impl From<Promise> for JsFuture {
             result: None,
             task: None,
             callbacks: None,
+            counted: true,
+            dropped: false,
         }));
+        LIVE_JS_FUTURES.with(|live| live.add());
 
         fn finish(state: &RefCell<Inner>, val: Result<JsValue, JsValue>) {
             let task = {
@@ -165,6 +1120,15 @@ impl From<Promise> for JsFuture {
                 // First up drop our closures as they'll never be invoked again and
                 // this is our chance to clean up their state.
                 drop(state.callbacks.take());
+                state.uncount();
+
+                // Nobody is left to see the value if the future is gone. A
+                // rejection arriving only now isn't reported: dropping a
+                // pending future is how it's cancelled, and for futures that
+                // abort on drop the rejection is the abort itself.
+                if state.dropped {
+                    return;
+                }
 
                 // Next, store the value into the internal state.
                 state.result = Some(val);
@@ -192,7 +1156,33 @@ impl From<Promise> for JsFuture {
 
         state.borrow_mut().callbacks = Some((resolve, reject));
 
-        JsFuture { inner: state }
+        JsFuture {
+            inner: state,
+            abort_on_drop: None,
+        }
+    }
+}
+
+impl Drop for JsFuture {
+    fn drop(&mut self) {
+        let unobserved = {
+            let mut inner = self.inner.borrow_mut();
+            inner.uncount();
+            inner.dropped = true;
+            if inner.callbacks.is_none() {
+                // The promise has settled, so there's nothing left to abort.
+                self.abort_on_drop = None;
+            }
+            inner.result.take()
+        };
+        if let Some(Err(error)) = unobserved {
+            report_unhandled_rejection(&error);
+        }
+        // Aborting may well run listeners, so it's done once nothing is
+        // borrowed.
+        if let Some(controller) = self.abort_on_drop.take() {
+            controller.abort();
+        }
     }
 }
 
@@ -202,9 +1192,15 @@ impl Future for JsFuture {
     fn poll(self: Pin<&mut Self>, cx: &mut Context) -> Poll<Self::Output> {
         let mut inner = self.inner.borrow_mut();
 
-        // If our value has come in then we return it...
-        if let Some(val) = inner.result.take() {
-            return Poll::Ready(val);
+        // If our value has come in then we return it, unless the task has
+        // been busy long enough that it should let the others run first...
+        if inner.result.is_some() {
+            if crate::queue::QUEUE.with(|queue| queue.jsfuture_should_yield()) {
+                drop(inner);
+                cx.waker().wake_by_ref();
+                return Poll::Pending;
+            }
+            return Poll::Ready(inner.result.take().unwrap());
         }
 
         // ... otherwise we arrange ourselves to get woken up once the value
@@ -223,7 +1219,22 @@ impl Future for JsFuture {
 /// background and cannot contain any stack references.
 ///
 /// The returned `Promise` will be resolved or rejected when the future completes,
-/// depending on whether it finishes with `Ok` or `Err`.
+/// depending on whether it finishes with `Ok` or `Err`. That makes this the
+/// way to hand the result of fallible async Rust code to JS, without
+/// resolving or rejecting a `Promise::new` by hand.
+///
+/// # Ordering
+///
+/// The `Promise` is settled from within the executor's tick, right as the
+/// future completes, and JS only runs its `then` callbacks from a later
+/// microtask once that tick is over. So everything the future did is visible
+/// to them, and so is everything done by tasks it spawned or woke, since the
+/// tick keeps running tasks until none are left. This is also what the
+/// `Promise` returned by an exported `async fn` does.
+///
+/// The one exception is a tick which yields to the event loop because its
+/// coop budget or deadline ran out: the tasks it left over run from a
+/// macrotask, after the `then` callbacks.
 ///
 /// # Panics
 ///
@@ -231,9 +1242,12 @@ impl Future for JsFuture {
 /// this case means that a JavaScript exception is thrown. The wasm module is
 /// still usable (likely erroneously) after Rust panics.
 ///
-/// If the `future` provided panics then the returned `Promise` **will not
-/// resolve**. Instead it will be a leaked promise. This is an unfortunate
-/// limitation of wasm currently that's hoped to be fixed one day!
+/// If the `future` provided panics then the returned `Promise` is rejected
+/// with a JavaScript `Error` carrying the panic message. The executor
+/// installs a panic hook (chained to any existing one) to capture that
+/// message; if it's unavailable the promise is instead rejected with the
+/// exception the panic turned into. The panicked future itself is leaked
+/// and never polled again.
 pub fn future_to_promise<F>(future: F) -> Promise
 where
     F: Future<Output = Result<JsValue, JsValue>> + 'static,
@@ -243,15 +1257,114 @@ where
     Promise::new(&mut |resolve, reject| {
         let future = future.take().unwrap_throw();
 
-        spawn_local(async move {
-            match future.await {
-                Ok(val) => {
-                    resolve.call1(&JsValue::undefined(), &val).unwrap_throw();
-                }
-                Err(val) => {
-                    reject.call1(&JsValue::undefined(), &val).unwrap_throw();
-                }
+        let on_panic = {
+            let reject = reject.clone();
+            move |error: JsValue| {
+                let _ = reject.call1(&JsValue::undefined(), &error);
             }
-        });
+        };
+
+        task::Task::spawn_catching(
+            Box::pin(async move {
+                match future.await {
+                    Ok(val) => {
+                        resolve.call1(&JsValue::undefined(), &val).unwrap_throw();
+                    }
+                    Err(val) => {
+                        reject.call1(&JsValue::undefined(), &val).unwrap_throw();
+                    }
+                }
+            }),
+            false,
+            Box::new(on_panic),
+        );
     })
 }
+
+/// Converts a Rust `Future` into a JavaScript `Promise` which JS can cancel.
+///
+/// Returns a JS object `{ promise, cancel }`. `promise` is settled as by
+/// `future_to_promise`, while calling `cancel()` aborts the task like
+/// `JoinHandle::abort`: the future is dropped without being polled again and
+/// `promise` is rejected with an `AbortError` `DOMException` (or an `Error`
+/// named `AbortError` on hosts without `DOMException`). Calling `cancel()`
+/// once the promise has settled, or more than once, has no effect.
+///
+/// `cancel` is the `abort` method of an `AbortController` bound to it rather
+/// than a Rust closure, so nothing is leaked if it's never called.
+pub fn future_to_cancelable_promise<F>(future: F) -> JsValue
+where
+    F: Future<Output = Result<JsValue, JsValue>> + 'static,
+{
+    let controller = web_sys::AbortController::new().unwrap_throw();
+    let signal = controller.signal();
+    let token = CancellationToken::new();
+    let listener = cancel::AbortListener::new(signal.clone(), &token);
+
+    let promise = future_to_promise(async move {
+        let _listener = listener;
+        match with_cancellation(future, &token).await {
+            Some(result) => result,
+            None => {
+                let reason = cancel::abort_reason(&signal);
+                Err(if reason.is_undefined() {
+                    cancel::abort_error()
+                } else {
+                    reason
+                })
+            }
+        }
+    });
+
+    let cancel = js_sys::Reflect::get(&controller, &JsValue::from("abort"))
+        .unwrap_throw()
+        .unchecked_into::<js_sys::Function>()
+        .bind(&controller);
+
+    let handle = js_sys::Object::new();
+    js_sys::Reflect::set(&handle, &JsValue::from("promise"), &promise).unwrap_throw();
+    js_sys::Reflect::set(&handle, &JsValue::from("cancel"), &cancel).unwrap_throw();
+    handle.into()
+}
+
+/// Creates a `Promise` along with functions which resolve and reject it,
+/// like JS's `Promise.withResolvers`.
+///
+/// Where `future_to_promise` settles a `Promise` with the outcome of a
+/// future, this is for settling one from anywhere: an event handler, a
+/// callback, or a task which only learns of the outcome later. Calling
+/// either function after the `Promise` has settled has no effect, as in JS.
+///
+/// The native `Promise.withResolvers` is used where it exists, and
+/// `new Promise` otherwise.
+pub fn promise_with_resolvers() -> (Promise, impl FnOnce(JsValue), impl FnOnce(JsValue)) {
+    let (promise, resolve, reject) = native_promise_with_resolvers().unwrap_or_else(|| {
+        let mut resolvers = None;
+        let promise = Promise::new(&mut |resolve, reject| resolvers = Some((resolve, reject)));
+        let (resolve, reject) = resolvers.unwrap_throw();
+        (promise, resolve, reject)
+    });
+
+    let settle = |function: js_sys::Function| {
+        move |value: JsValue| {
+            let _ = function.call1(&JsValue::undefined(), &value);
+        }
+    };
+    (promise, settle(resolve), settle(reject))
+}
+
+fn native_promise_with_resolvers() -> Option<(Promise, js_sys::Function, js_sys::Function)> {
+    let promise = js_sys::Reflect::get(&js_sys::global(), &JsValue::from("Promise")).ok()?;
+    let with_resolvers = js_sys::Reflect::get(&promise, &JsValue::from("withResolvers"))
+        .ok()?
+        .dyn_into::<js_sys::Function>()
+        .ok()?;
+    let resolvers = with_resolvers.call0(&promise).ok()?;
+
+    let field = |name: &str| js_sys::Reflect::get(&resolvers, &JsValue::from(name)).ok();
+    Some((
+        field("promise")?.dyn_into().ok()?,
+        field("resolve")?.dyn_into().ok()?,
+        field("reject")?.dyn_into().ok()?,
+    ))
+}
@@ -0,0 +1,137 @@
+use crate::queue::QueueDepths;
+use std::cell::{Cell, RefCell};
+use std::panic::Location;
+use std::rc::Rc;
+
+/// Receives notifications about the executor's activity on the current
+/// thread, for diagnostics and instrumentation.
+///
+/// Every method has a default implementation which does nothing, so an
+/// observer only needs to implement the events it's interested in.
+pub trait QueueObserver {
+    /// Called when the number of live `JsFuture`s first exceeds the
+    /// threshold configured with `set_jsfuture_leak_threshold`, with the
+    /// current number of live futures.
+    fn jsfuture_leak_threshold_exceeded(&self, live: usize) {
+        let _ = live;
+    }
+
+    /// Called when a single poll of a task took longer than the threshold
+    /// configured with `set_long_task_threshold_ms`, with the task's id and
+    /// how long the poll took in milliseconds.
+    ///
+    /// Task ids are unique on the current thread, and match the ids in the
+    /// spans of the `tracing` feature.
+    fn long_task(&self, task_id: u64, duration_ms: f64) {
+        let _ = (task_id, duration_ms);
+    }
+
+    /// Called when the executor has had tasks to run without running a tick
+    /// for longer than the threshold configured with
+    /// `set_stall_threshold_ms`, with how long it's been stalled in
+    /// milliseconds and the tasks it has queued.
+    ///
+    /// This is reported once per stall, and again only after the executor
+    /// has made progress.
+    fn queue_stalled(&self, stalled_ms: f64, pending: QueueDepths) {
+        let _ = (stalled_ms, pending);
+    }
+
+    /// Called in strict mode (see `set_strict_mode`) when a poll of a task
+    /// returned while it was still holding a borrow it took with
+    /// `strict_borrow` or `strict_borrow_mut`, with the task's id and where
+    /// the borrow was taken.
+    fn borrow_held_across_await(&self, task_id: u64, location: &'static Location<'static>) {
+        let _ = (task_id, location);
+    }
+}
+
+/// Identifies an observer added with `add_queue_observer`, for removing it
+/// again.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct ObserverId(u64);
+
+/// Adds `observer` to the observers notified about the executor on the
+/// current thread, returning an id which `remove_queue_observer` takes.
+///
+/// Every observer gets each notification, in the order they were added, so
+/// independent subsystems such as metrics and tracing can each add their own
+/// without coordinating.
+pub fn add_queue_observer(observer: Box<dyn QueueObserver>) -> ObserverId {
+    add_observer(Rc::from(observer))
+}
+
+fn add_observer(observer: Rc<dyn QueueObserver>) -> ObserverId {
+    let id = ObserverId(NEXT_OBSERVER_ID.with(|next| next.replace(next.get() + 1)));
+    OBSERVERS.with(|observers| {
+        Rc::make_mut(&mut observers.borrow_mut()).push((id, observer));
+    });
+    id
+}
+
+/// Removes the observer added with `add_queue_observer` which returned
+/// `id`, returning whether it was still there.
+pub fn remove_queue_observer(id: ObserverId) -> bool {
+    // The observer is dropped outside of the borrow, in case dropping it
+    // adds or removes another one.
+    let removed = OBSERVERS.with(|observers| {
+        let mut observers = observers.borrow_mut();
+        let index = observers.iter().position(|(other, _)| *other == id)?;
+        Some(Rc::make_mut(&mut observers).remove(index))
+    });
+    removed.is_some()
+}
+
+/// Installs `observer` to receive notifications about the executor on the
+/// current thread, replacing the observer previously installed this way.
+/// Passing `None` removes it.
+///
+/// This is a shorthand for managing a single observer, which coexists with
+/// those added with `add_queue_observer`.
+pub fn set_queue_observer(observer: Option<Box<dyn QueueObserver>>) {
+    set_observer(observer.map(Rc::from));
+}
+
+/// Like `set_queue_observer`, for an observer which is already shared.
+pub(crate) fn set_observer(observer: Option<Rc<dyn QueueObserver>>) {
+    let previous = SET_OBSERVER.with(|current| current.take());
+    if let Some(id) = previous {
+        remove_queue_observer(id);
+    }
+    let id = observer.map(add_observer);
+    SET_OBSERVER.with(|current| current.set(id));
+}
+
+/// Calls `f` with each observer in turn, returning whether there were any.
+///
+/// The observers are cloned out first, so they're free to add or remove
+/// observers, themselves included.
+pub(crate) fn notify(f: impl Fn(&dyn QueueObserver)) -> bool {
+    let observers = OBSERVERS.with(|observers| {
+        let observers = observers.borrow();
+        if observers.is_empty() {
+            None
+        } else {
+            Some(Rc::clone(&observers))
+        }
+    });
+    match observers {
+        Some(observers) => {
+            for (_, observer) in observers.iter() {
+                f(&**observer);
+            }
+            true
+        }
+        None => false,
+    }
+}
+
+type Observers = Vec<(ObserverId, Rc<dyn QueueObserver>)>;
+
+thread_local! {
+    // Copied on write, so that notifying only takes a reference count
+    static OBSERVERS: RefCell<Rc<Observers>> = RefCell::new(Rc::new(Vec::new()));
+    static NEXT_OBSERVER_ID: Cell<u64> = Cell::new(0);
+    // The observer installed with `set_queue_observer`
+    static SET_OBSERVER: Cell<Option<ObserverId>> = Cell::new(None);
+}
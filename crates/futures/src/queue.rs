@@ -1,15 +1,225 @@
-use js_sys::Promise;
+use crate::observer::QueueObserver;
+use js_sys::{Array, Function, Object, Promise};
 use std::cell::{Cell, RefCell};
 use std::collections::VecDeque;
+use std::error::Error;
+use std::fmt;
+use std::panic;
 use std::rc::Rc;
 use wasm_bindgen::{JsCast, prelude::*};
 
+mod lanes;
+mod watchdog;
+#[cfg(feature = "task-weights")]
+mod weights;
+
+// Normal priority tasks run in the order they're queued, unless the
+// `task-weights` feature orders them by weight instead.
+#[cfg(not(feature = "task-weights"))]
+type NormalTasks = VecDeque<Rc<crate::task::Task>>;
+#[cfg(feature = "task-weights")]
+type NormalTasks = weights::WeightedTasks;
+
+#[wasm_bindgen]
+extern "C" {
+    #[wasm_bindgen(js_namespace = performance, js_name = now)]
+    pub(crate) fn performance_now() -> f64;
+
+    #[wasm_bindgen(catch, js_name = queueMicrotask)]
+    fn queue_microtask(callback: &JsValue) -> Result<(), JsValue>;
+
+    #[wasm_bindgen(catch, js_namespace = Promise, js_name = resolve)]
+    fn resolved_promise(value: &JsValue) -> Result<Promise, JsValue>;
+}
+
+/// How the executor schedules the microtask for its next tick.
+enum Microtasks {
+    // `queueMicrotask`, which unlike `promise.then` needs no new allocations
+    // to schedule the same callback again
+    QueueMicrotask,
+    // `then` on an already resolved promise, called through `Function::call1`
+    // to catch what it throws
+    Promise(Promise, Function),
+    // Neither was usable, with the exception creating the promise threw.
+    // Creating it is retried each time a tick needs scheduling.
+    Unavailable(JsValue),
+}
+
+impl Microtasks {
+    fn detect() -> Self {
+        if global_function("queueMicrotask").is_some() {
+            return Microtasks::QueueMicrotask;
+        }
+        match Microtasks::promise() {
+            Ok(microtasks) => microtasks,
+            Err(error) => {
+                log::warn!(
+                    "the executor can't schedule microtasks, as there's no \
+                     `queueMicrotask` and creating a `Promise` threw {:?}; \
+                     spawning will fail until that succeeds",
+                    error
+                );
+                Microtasks::Unavailable(error)
+            }
+        }
+    }
+
+    fn promise() -> Result<Self, JsValue> {
+        let promise = resolved_promise(&JsValue::undefined())?;
+        let then = js_sys::Reflect::get(&promise, &JsValue::from("then"))?.dyn_into::<Function>()?;
+        Ok(Microtasks::Promise(promise, then))
+    }
+}
+
+// A user-provided callback, which is cloned out before being called so that it
+// can replace itself
+type Hook<F> = Rc<RefCell<Box<F>>>;
+type TickHook = Hook<dyn FnMut()>;
+type CustomScheduler = Hook<SchedulerFn>;
+pub(crate) type SchedulerFn = dyn FnMut(&Function, i32);
+
 struct QueueStateInner {
     high_priority_tasks: VecDeque<Rc<crate::task::Task>>,
-    tasks: VecDeque<Rc<crate::task::Task>>,
+    tasks: NormalTasks,
+    // Normal priority tasks spawned with `spawn_local_lane`
+    lanes: lanes::Lanes,
 
     /// The number of times a task can be popped off the queue before unblocking the event loop
-    coop_budget: u32
+    coop_budget: u32,
+
+    /// The number of milliseconds a tick may spend on normal tasks before
+    /// unblocking the event loop, if any
+    tick_deadline_ms: Option<f64>,
+
+    /// Picks normal tasks in a seeded random order instead, while set
+    shuffle: Option<ShuffleRng>,
+
+    /// The number of queued tasks past which spawning fails, if any
+    max_depth: Option<usize>,
+}
+
+impl QueueStateInner {
+    fn push_task(&mut self, task: Rc<crate::task::Task>) {
+        match task.lane() {
+            Some(lane) => {
+                // The default lane joins the rotation once lanes are in use.
+                if self.lanes.is_empty() && !self.tasks.is_empty() {
+                    self.lanes.push_default_turn();
+                }
+                self.lanes.push_back(lane, task);
+            }
+            None => {
+                if !self.lanes.is_empty() && self.tasks.is_empty() {
+                    self.lanes.push_default_turn();
+                }
+                self.tasks.push_back(task);
+            }
+        }
+    }
+
+    fn pop_task(&mut self) -> Option<Rc<crate::task::Task>> {
+        if self.lanes.is_empty() {
+            return self.pop_default_task();
+        }
+        match self.lanes.next_turn() {
+            Some((Some(_), task)) => task,
+            Some((None, _)) | None => {
+                let task = self.pop_default_task();
+                if !self.tasks.is_empty() && !self.lanes.is_empty() {
+                    self.lanes.push_default_turn();
+                }
+                task
+            }
+        }
+    }
+
+    fn depth(&self) -> usize {
+        self.high_priority_tasks.len() + self.tasks.len() + self.lanes.len()
+    }
+
+    /// Removes the tasks `pred` picks out, leaving normal priority ones alone
+    /// unless `normal` is set.
+    fn take_where(
+        &mut self,
+        normal: bool,
+        pred: impl Fn(&crate::task::Task) -> bool,
+    ) -> Vec<Rc<crate::task::Task>> {
+        let mut taken = take_where(&mut self.high_priority_tasks, &pred);
+        if !normal {
+            return taken;
+        }
+
+        #[cfg(not(feature = "task-weights"))]
+        taken.extend(take_where(&mut self.tasks, &pred));
+        #[cfg(feature = "task-weights")]
+        taken.extend(self.tasks.take_where(&pred));
+        taken.extend(self.lanes.take_where(&pred));
+
+        // A turn of the default lane with nothing left to run would end the
+        // tick early.
+        if self.tasks.is_empty() {
+            self.lanes.remove_default_turns();
+        }
+        taken
+    }
+
+    fn has_normal_tasks(&self) -> bool {
+        !self.tasks.is_empty() || !self.lanes.is_empty()
+    }
+
+    fn pop_default_task(&mut self) -> Option<Rc<crate::task::Task>> {
+        #[cfg(not(feature = "task-weights"))]
+        {
+            if let Some(rng) = self.shuffle.as_mut() {
+                if self.tasks.is_empty() {
+                    return None;
+                }
+                let index = (rng.next() % self.tasks.len() as u64) as usize;
+                return self.tasks.remove(index);
+            }
+        }
+        self.tasks.pop_front()
+    }
+}
+
+/// Removes the tasks `pred` picks out of `queue`, keeping the order of both
+/// the ones taken and the ones left.
+fn take_where(
+    queue: &mut VecDeque<Rc<crate::task::Task>>,
+    pred: impl Fn(&crate::task::Task) -> bool,
+) -> Vec<Rc<crate::task::Task>> {
+    let mut taken = Vec::new();
+    queue.retain(|task| {
+        if pred(task) {
+            taken.push(Rc::clone(task));
+            false
+        } else {
+            true
+        }
+    });
+    taken
+}
+
+/// The SplitMix64 generator, which is plenty for shuffling tasks and
+/// produces a good sequence from any seed.
+#[cfg_attr(feature = "task-weights", allow(dead_code))]
+struct ShuffleRng(u64);
+
+#[cfg_attr(feature = "task-weights", allow(dead_code))]
+impl ShuffleRng {
+    fn next(&mut self) -> u64 {
+        self.0 = self.0.wrapping_add(0x9e37_79b9_7f4a_7c15);
+        let mut z = self.0;
+        z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+        z ^ (z >> 31)
+    }
+}
+
+/// A future waiting in `until_idle`.
+pub(crate) struct IdleWaiter {
+    released: Cell<bool>,
+    waker: RefCell<Option<std::task::Waker>>,
 }
 
 struct QueueState {
@@ -22,131 +232,1455 @@ struct QueueState {
     // `run_all` or have scheduled `run_all` to run in the future. This is
     // used to ensure that it's only scheduled once.
     is_spinning: Cell<bool>,
+
+    // Set when a tick ran out of budget but couldn't schedule the macrotask
+    // to resume on, so the tasks left in the queue are waiting for the next
+    // push to try again.
+    is_stalled: Cell<bool>,
+
+    // Set by `pause_normal`, which keeps normal priority tasks queued until
+    // `resume_normal` while high priority ones keep running.
+    normal_paused: Cell<bool>,
+
+    // Set by `set_jsfuture_coop`, which makes a task yield once this many
+    // `JsFuture`s completed during a single poll of it exceeds the budget
+    jsfuture_coop: Cell<bool>,
+    ready_jsfutures: Cell<u32>,
+
+    // Futures returned by `until_idle`, released once the queues run dry
+    idle_waiters: RefCell<Vec<Rc<IdleWaiter>>>,
+
+    // Set by `run_budgeted_on_raf`, which runs ticks on animation frames
+    // instead, each spending up to this many milliseconds on normal tasks
+    frame_budget_ms: Cell<Option<f64>>,
+
+    // Set by `set_yield_on_input_pending` if the host has it, the
+    // `navigator.scheduling` object and its `isInputPending` method
+    input_pending: RefCell<Option<(JsValue, Function)>>,
+
+    // Set by `set_coalesce_window_ms`, which delays ticks starting less than
+    // this many milliseconds after the last one, along with when that was
+    coalesce_window_ms: Cell<Option<f64>>,
+    last_tick_at: Cell<f64>,
+
+    // The number of ticks started, which the stall watchdog goes by. It only
+    // ever gets compared for equality, so it's fine for it to wrap around.
+    ticks: Cell<u64>,
+
+    // Set by `set_long_task_threshold_ms`. Tasks are only timed while this
+    // is set.
+    long_task_threshold_ms: Cell<Option<f64>>,
+
+    // The task currently being polled by `run_all`. If polling it throws
+    // (for example because the future panicked) then `run_all` never gets to
+    // clear this, which is how `recover` knows which task to blame.
+    current_task: RefCell<Option<Rc<crate::task::Task>>>,
+
+    // Optional hooks run at the start and end of every tick
+    tick_prologue: RefCell<Option<TickHook>>,
+    tick_epilogue: RefCell<Option<TickHook>>,
+
+    // Set up by `Queue::drive` for `drive_tasks`: how many more tasks it may
+    // run, and whether normal priority ones are among them
+    drive_limit: Cell<(usize, bool)>,
+
+    // Tasks for `run_batch` to run in order
+    batch: RefCell<VecDeque<Rc<crate::task::Task>>>,
 }
 
 impl QueueState {
+    fn run_hook(hook: &RefCell<Option<TickHook>>) {
+        // Clone the hook out so that it's free to replace itself.
+        let hook = hook.borrow().clone();
+        if let Some(hook) = hook {
+            (hook.borrow_mut())();
+        }
+    }
+
+    fn run_task(&self, task: Rc<crate::task::Task>) {
+        let id = task.id();
+        *self.current_task.borrow_mut() = Some(Rc::clone(&task));
+        self.ready_jsfutures.set(0);
+
+        #[cfg(feature = "tracing")]
+        let span = tracing::trace_span!("task", id = task.id());
+        #[cfg(feature = "tracing")]
+        let _enter = span.enter();
+
+        let threshold = self.long_task_threshold_ms.get();
+        let started = threshold.map(|_| performance_now());
+
+        task.run();
+        crate::strict::check_held_borrows(id);
+
+        // This may be the last reference to the task, so drop it outside of
+        // the borrow in case that runs destructors which use the executor.
+        let task = self.current_task.borrow_mut().take();
+        drop(task);
+
+        if let (Some(threshold), Some(started)) = (threshold, started) {
+            let duration = performance_now() - started;
+            if duration > threshold {
+                report_long_task(id, duration);
+            }
+        }
+    }
+
+    /// Runs tasks, high priority ones first, until `drive_limit` runs out.
+    /// Normal priority tasks only run if `drive_limit` allows them.
+    ///
+    /// Like `run_all` this is called through JS (see `Queue::drive`), and
+    /// called again after a task throws, so the limit is kept up to date as
+    /// it goes.
+    fn drive_tasks(&self) {
+        loop {
+            let (left, normal) = self.drive_limit.get();
+            if left == 0 {
+                break;
+            }
+            let task = {
+                let mut inner = self.inner.borrow_mut();
+                match inner.high_priority_tasks.pop_front() {
+                    Some(task) => task,
+                    None if !normal || self.normal_paused.get() => break,
+                    None => match inner.pop_task() {
+                        Some(task) => task,
+                        None => break,
+                    },
+                }
+            };
+            self.drive_limit.set((left - 1, normal));
+            self.run_task(task);
+        }
+    }
+
+    /// Runs the tasks in `batch` in order, like `drive_tasks` for a set of
+    /// tasks picked out beforehand.
+    fn run_batch(&self) {
+        loop {
+            let task = self.batch.borrow_mut().pop_front();
+            match task {
+                Some(task) => self.run_task(task),
+                None => break,
+            }
+        }
+    }
+
+    /// Whether `set_yield_on_input_pending` is on and the host reports input
+    /// waiting to be handled.
+    fn is_input_pending(&self) -> bool {
+        // Clone the method out in case calling it somehow re-enters.
+        let input_pending = self.input_pending.borrow().clone();
+        match input_pending {
+            Some((scheduling, is_input_pending)) => {
+                matches!(is_input_pending.call0(&scheduling), Ok(pending) if pending.is_truthy())
+            }
+            None => false,
+        }
+    }
+
+    /// Whether a task is being polled right now, in which case running any
+    /// other task would re-enter the executor.
+    fn is_polling(&self) -> bool {
+        self.current_task.borrow().is_some()
+    }
+
+    fn has_pending_tasks(&self) -> bool {
+        let inner = self.inner.borrow();
+        let has_normal_tasks = !self.normal_paused.get() && inner.has_normal_tasks();
+        !inner.high_priority_tasks.is_empty() || has_normal_tasks
+    }
+
+    /// Invoked with the exception that escaped `run_all`. Returns whether it
+    /// was thrown by a task, in which case `run_all` can be resumed.
+    fn recover(&self, error: &JsValue) -> bool {
+        let task = match self.current_task.borrow_mut().take() {
+            Some(task) => task,
+            None => return false,
+        };
+
+        // Prefer the panic message captured by our hook over the opaque
+        // `RuntimeError` a wasm trap turns into.
+        let error = match LAST_PANIC.with(|last| last.borrow_mut().take()) {
+            Some(message) => js_sys::Error::new(&message).into(),
+            None => error.clone(),
+        };
+        task.poison(error);
+        true
+    }
+
     fn run_all(&self) {
         debug_assert!(self.is_spinning.get());
 
+        // Forget any panic that happened outside of our tasks so it can't be
+        // misattributed to one of them.
+        LAST_PANIC.with(|last| last.borrow_mut().take());
+
         // Runs all Tasks until empty. This blocks the event loop if a Future is
         // stuck in an infinite loop, so we may want to yield back to the main
         // event loop occasionally. For now though greedy execution should get
         // the job done.
-        loop {
-            let task = match self.inner.borrow_mut().high_priority_tasks.pop_front() {
-                Some(task) => task,
-                None => break,
-            };
-            task.run();
-        }
-
         let mut i = 0;
-        let coop_budget = self.inner.borrow_mut().coop_budget;
+        let mut budget_exceeded = false;
+        let (coop_budget, deadline) = {
+            let inner = self.inner.borrow();
+            // At least one task always runs so that a budget of zero can't
+            // stall the queue forever.
+            let budget_ms = self.frame_budget_ms.get().or(inner.tick_deadline_ms);
+            let deadline = budget_ms.map(|ms| performance_now() + ms);
+            (inner.coop_budget.max(1), deadline)
+        };
 
         loop {
-            if i > coop_budget {
+            // High priority tasks always go first and aren't subject to the
+            // budget. This includes ones woken by the normal tasks run below,
+            // such as a high priority task whose promise just settled.
+            let task = self.inner.borrow_mut().high_priority_tasks.pop_front();
+            if let Some(task) = task {
+                self.run_task(task);
+                continue;
+            }
+
+            // Paused normal tasks wait for `resume_normal` to schedule a
+            // tick for them.
+            if self.normal_paused.get() {
+                break;
+            }
+
+            // Like the coop budget, pending input only cuts a tick short once
+            // it has run a task, so input which stays pending can't starve
+            // the queue.
+            if i >= coop_budget
+                || matches!(deadline, Some(d) if performance_now() >= d)
+                || (i > 0 && self.is_input_pending())
+            {
+                budget_exceeded = true;
                 break;
             }
-            
-            let task = match self.inner.borrow_mut().tasks.pop_front() {
+
+            let task = match self.inner.borrow_mut().pop_task() {
                 Some(task) => task,
                 None => break,
             };
-            task.run();
+            self.run_task(task);
+
+            // Most ticks run a single task, such as the continuation of a
+            // settled promise. If it left nothing behind there's nothing to
+            // budget or reschedule, so finish the tick right away.
+            let idle = {
+                let inner = self.inner.borrow();
+                inner.high_priority_tasks.is_empty() && !inner.has_normal_tasks()
+            };
+            if idle {
+                self.is_spinning.set(false);
+                self.release_idle_waiters();
+                return;
+            }
 
             i += 1;
         }
 
-        if i > coop_budget && !self.inner.borrow_mut().tasks.is_empty() {
+        if budget_exceeded && self.inner.borrow().has_normal_tasks() {
             // our budget was exceeded before the queue was exhausted
-            QUEUE.with(|queue| {
-                queue.schedule_queue_update();
-            });
+            if QUEUE.with(|queue| queue.schedule_queue_update()).is_err() {
+                // There's no one to report this to from here, so park the
+                // remaining tasks until the next push retries scheduling.
+                self.is_stalled.set(true);
+                self.is_spinning.set(false);
+            }
         } else { 
             // All of the Tasks have been run, so it's now possible to schedule the
             // next tick again
             self.is_spinning.set(false);
+
+            let idle = {
+                let inner = self.inner.borrow();
+                inner.high_priority_tasks.is_empty() && !inner.has_normal_tasks()
+            };
+            if idle {
+                self.release_idle_waiters();
+            }
+        }
+    }
+
+    /// Wakes the futures waiting in `until_idle`. This must only be called
+    /// once the tick is no longer spinning, so that the wakeups schedule
+    /// another one.
+    fn release_idle_waiters(&self) {
+        let waiters = self.idle_waiters.replace(Vec::new());
+        for waiter in waiters {
+            waiter.released.set(true);
+            let waker = waiter.waker.borrow_mut().take();
+            if let Some(waker) = waker {
+                waker.wake();
+            }
         }
     }
 }
 
 pub(crate) struct Queue {
     state: Rc<QueueState>,
-    promise: Promise,
-    closure: Closure<dyn FnMut(JsValue)>,
+    microtasks: RefCell<Microtasks>,
+
+    // The entry point of every tick, which calls `run_all` through JS so that
+    // an exception thrown by a task can be caught and the tick resumed. It's
+    // an `Fn` so that a nested call reaches the guard against re-entering a
+    // tick rather than failing in wasm-bindgen's glue.
+    closure: Closure<dyn Fn(JsValue)>,
+    _run_all: Closure<dyn FnMut()>,
+
+    // `QueueState::drive_tasks` and `QueueState::run_batch`, which run tasks
+    // outside of a tick and so are called through JS the same way
+    drive_tasks: Closure<dyn FnMut()>,
+    run_batch: Closure<dyn FnMut()>,
+
+    // A channel whose `port1` runs `closure`, used to schedule a macrotask
+    // with `postMessage` instead of a fresh (and clamped) timer each time
+    channel: Option<web_sys::MessageChannel>,
+
+    /// The `setTimeout` delay used to resume work once a tick runs out of budget
+    reschedule_delay_ms: i32,
+
+    // Schedules macrotasks in hosts without any `setTimeout`
+    custom_scheduler: RefCell<Option<CustomScheduler>>,
+
+    // Flushes the queue on `pagehide` and `beforeunload` while registered.
+    // It's created the first time it's registered and then kept around, as
+    // it may be unregistered from a task it's running.
+    page_hide_listener: RefCell<Option<Closure<dyn FnMut()>>>,
+    drains_on_page_hide: Cell<bool>,
+
+    // Tasks woken in `WakeMode::Macrotask`, waiting for the timer which
+    // `release_deferred` moves them into the queue from
+    deferred_tasks: RefCell<Vec<Rc<crate::task::Task>>>,
+    release_deferred: Closure<dyn FnMut()>,
+
+    // Set by `set_stall_threshold_ms`, and stops watching when dropped
+    watchdog: RefCell<Option<watchdog::Watchdog>>,
+
+    // Set within `with_manual_wakes`, which never schedules ticks
+    manual_wakes: Cell<bool>,
+
+    // The number of ticks scheduled on each path, for `queue_stats`
+    microtask_drains: Cell<u64>,
+    macrotask_drains: Cell<u64>,
 }
 
 impl Queue {
-    pub(crate) fn push_high_priority_task(&self, task: Rc<crate::task::Task>) {
+    /// Queues a high priority task, returning an error if a tick to run it
+    /// couldn't be scheduled. The task stays queued either way.
+    pub(crate) fn push_high_priority_task(&self, task: Rc<crate::task::Task>) -> Result<(), ExecutorError> {
+        task.set_queued_at_tick(self.state.ticks.get());
         self.state.inner.borrow_mut().high_priority_tasks.push_back(task);
+        self.start_spinning()
+    }
 
-        // If we're already inside the `run_all` loop then that'll pick up the
-        // task we just enqueued. If we're not in `run_all`, though, then we need
-        // to schedule a microtask.
-        //
-        // Note that we currently use a promise and a closure to do this, but
-        // eventually we should probably use something like `queueMicrotask`:
-        // https://developer.mozilla.org/en-US/docs/Web/API/WindowOrWorkerGlobalScope/queueMicrotask
-        if !self.state.is_spinning.replace(true) {
-            self.spawn_queue_microtask();
+    /// Queues a task, returning an error if a tick to run it couldn't be
+    /// scheduled. The task stays queued either way.
+    pub(crate) fn push_task(&self, task: Rc<crate::task::Task>) -> Result<(), ExecutorError> {
+        task.set_queued_at_tick(self.state.ticks.get());
+        self.state.inner.borrow_mut().push_task(task);
+        self.start_spinning()
+    }
+
+    /// Queues a task on a macrotask rather than right away, so the host gets
+    /// to render before it runs.
+    ///
+    /// If there's no way to schedule a macrotask the task is queued right
+    /// away instead.
+    pub(crate) fn push_task_on_macrotask(&self, task: Rc<crate::task::Task>) -> Result<(), ExecutorError> {
+        task.set_queued_at_tick(self.state.ticks.get());
+        let first = {
+            let mut deferred = self.deferred_tasks.borrow_mut();
+            deferred.push(task);
+            deferred.len() == 1
+        };
+        if !first {
+            return Ok(());
         }
+
+        // The scheduler is called without the deferred tasks borrowed, as a
+        // custom one may well wake tasks itself.
+        let release = self.release_deferred.as_ref().unchecked_ref();
+        match self.schedule_timer(release, 0) {
+            Ok(()) => Ok(()),
+            Err(_) => self.release_deferred_tasks(),
+        }
+    }
+
+    fn release_deferred_tasks(&self) -> Result<(), ExecutorError> {
+        let deferred = self.deferred_tasks.replace(Vec::new());
+        if deferred.is_empty() {
+            return Ok(());
+        }
+        {
+            let mut inner = self.state.inner.borrow_mut();
+            for task in deferred {
+                inner.push_task(task);
+            }
+        }
+        self.start_spinning()
     }
 
-    pub(crate) fn push_task(&self, task: Rc<crate::task::Task>) {
-        self.state.inner.borrow_mut().tasks.push_back(task);
+    fn start_spinning(&self) -> Result<(), ExecutorError> {
+        // Queued tasks wait for the `ManualDriver` to run them instead.
+        if self.manual_wakes.get() {
+            return Ok(());
+        }
 
         // If we're already inside the `run_all` loop then that'll pick up the
         // task we just enqueued. If we're not in `run_all`, though, then we need
-        // to schedule a microtask.
-        //
-        // Note that we currently use a promise and a closure to do this, but
-        // eventually we should probably use something like `queueMicrotask`:
-        // https://developer.mozilla.org/en-US/docs/Web/API/WindowOrWorkerGlobalScope/queueMicrotask
-        if !self.state.is_spinning.replace(true) {
-            self.spawn_queue_microtask();
+        // to schedule a microtask, or the macrotask a stalled queue was waiting on.
+        if self.state.is_spinning.replace(true) {
+            return Ok(());
+        }
+
+        let result = match self.request_frame() {
+            Some(result) => result,
+            None if self.state.is_stalled.get() => self.schedule_queue_update(),
+            None => match self.coalesce_delay_ms() {
+                // Fall back to a microtask rather than fail over the delay.
+                Some(delay) => self
+                    .schedule_coalesced(delay)
+                    .or_else(|_| self.spawn_queue_microtask()),
+                None => self.spawn_queue_microtask(),
+            },
+        };
+        match result {
+            Ok(()) => self.state.is_stalled.set(false),
+            // Nothing is scheduled, so let the next push try again.
+            Err(_) => self.state.is_spinning.set(false),
+        }
+        result
+    }
+
+    fn spawn_queue_microtask(&self) -> Result<(), ExecutorError> {
+        let mut microtasks = self.microtasks.borrow_mut();
+        if let Microtasks::Unavailable(_) = *microtasks {
+            *microtasks = Microtasks::promise().unwrap_or_else(Microtasks::Unavailable);
+        }
+
+        let result = match &*microtasks {
+            Microtasks::QueueMicrotask => {
+                queue_microtask(self.closure.as_ref()).map_err(ExecutorError::SchedulingThrew)
+            }
+            Microtasks::Promise(promise, then) => then
+                .call1(promise, self.closure.as_ref())
+                .map(drop)
+                .map_err(ExecutorError::SchedulingThrew),
+            Microtasks::Unavailable(error) => Err(ExecutorError::Unavailable(error.clone())),
+        };
+        if result.is_ok() {
+            self.microtask_drains.set(self.microtask_drains.get() + 1);
+        }
+        result
+    }
+
+    /// How long a tick starting now should wait to be coalesced with the
+    /// ones after it, or `None` if it shouldn't.
+    fn coalesce_delay_ms(&self) -> Option<f64> {
+        let window = self.state.coalesce_window_ms.get()?;
+        let elapsed = performance_now() - self.state.last_tick_at.get();
+        if elapsed >= window {
+            return None;
+        }
+        Some(window - elapsed)
+    }
+
+    /// Schedules a tick on a macrotask `delay_ms` from now, which every
+    /// wakeup until then joins.
+    fn schedule_coalesced(&self, delay_ms: f64) -> Result<(), ExecutorError> {
+        let callback = self.closure.as_ref().unchecked_ref();
+        let result = self.schedule_timer(callback, delay_ms.ceil() as i32);
+        if result.is_ok() {
+            self.macrotask_drains.set(self.macrotask_drains.get() + 1);
+        }
+        result
+    }
+
+    pub(crate) fn microtask_scheduler(&self) -> Option<SchedulerKind> {
+        if self.uses_frames() {
+            return Some(SchedulerKind::AnimationFrame);
+        }
+        match *self.microtasks.borrow() {
+            Microtasks::QueueMicrotask => Some(SchedulerKind::QueueMicrotask),
+            Microtasks::Promise(..) => Some(SchedulerKind::PromiseThen),
+            Microtasks::Unavailable(_) => None,
         }
     }
 
-    fn spawn_queue_microtask(&self) {
-        let _ = self.promise.then(&self.closure);
+    /// Which backend `schedule_queue_update` would use right now, checked in
+    /// the same order.
+    pub(crate) fn macrotask_scheduler(&self) -> Option<SchedulerKind> {
+        if self.uses_frames() {
+            Some(SchedulerKind::AnimationFrame)
+        } else if self.channel.is_some() && self.reschedule_delay_ms <= 0 {
+            Some(SchedulerKind::MessageChannel)
+        } else if find_timer_function("setTimeout").is_some() {
+            Some(SchedulerKind::SetTimeout)
+        } else if self.custom_scheduler.borrow().is_some() {
+            Some(SchedulerKind::Custom)
+        } else {
+            None
+        }
     }
 
-    fn schedule_queue_update(&self) {
-        web_sys::window().unwrap().set_timeout_with_callback_and_timeout_and_arguments_0(self.closure.as_ref().unchecked_ref(), 0).unwrap_throw();
+    fn schedule_queue_update(&self) -> Result<(), ExecutorError> {
+        if let Some(result) = self.request_frame() {
+            return result;
+        }
+
+        let result = match &self.channel {
+            Some(channel) if self.reschedule_delay_ms <= 0 => channel
+                .port2()
+                .post_message(&JsValue::undefined())
+                .map_err(ExecutorError::SchedulingThrew),
+            _ => {
+                let callback = self.closure.as_ref().unchecked_ref();
+                self.schedule_timer(callback, self.reschedule_delay_ms)
+            }
+        };
+        if result.is_ok() {
+            self.macrotask_drains.set(self.macrotask_drains.get() + 1);
+        }
+        result
     }
 
-    pub(crate) fn set_coop_budget(&self, budget: u32) {
-        self.state.inner.borrow_mut().coop_budget = budget;
+    /// Whether ticks run on animation frames, which takes both
+    /// `run_budgeted_on_raf` and a host with `requestAnimationFrame`.
+    fn uses_frames(&self) -> bool {
+        self.state.frame_budget_ms.get().is_some()
+            && find_timer_function("requestAnimationFrame").is_some()
+    }
+
+    /// Schedules a tick on the next animation frame if `uses_frames`, and
+    /// returns `None` otherwise.
+    fn request_frame(&self) -> Option<Result<(), ExecutorError>> {
+        self.state.frame_budget_ms.get()?;
+        let (scope, request) = find_timer_function("requestAnimationFrame")?;
+        let result = request
+            .call1(&scope, self.closure.as_ref())
+            .map(drop)
+            .map_err(ExecutorError::SchedulingThrew);
+        if result.is_ok() {
+            self.macrotask_drains.set(self.macrotask_drains.get() + 1);
+        }
+        Some(result)
+    }
+
+    /// Schedules `callback` with `setTimeout`, or the custom scheduler if
+    /// there's none.
+    fn schedule_timer(&self, callback: &Function, delay_ms: i32) -> Result<(), ExecutorError> {
+        if let Some((scope, set_timeout)) = find_timer_function("setTimeout") {
+            return set_timeout
+                .call2(&scope, callback, &JsValue::from(delay_ms))
+                .map(drop)
+                .map_err(ExecutorError::SchedulingThrew);
+        }
+
+        let scheduler = self.custom_scheduler.borrow().clone();
+        match scheduler {
+            Some(scheduler) => {
+                (scheduler.borrow_mut())(callback, delay_ms);
+                Ok(())
+            }
+            None => Err(ExecutorError::NoScheduler),
+        }
+    }
+
+    pub(crate) fn set_custom_scheduler(&self, scheduler: Option<Box<SchedulerFn>>) {
+        let scheduler = scheduler.map(|scheduler| Rc::new(RefCell::new(scheduler)));
+        drop(self.custom_scheduler.replace(scheduler));
+    }
+
+    pub(crate) fn set_normal_paused(&self, paused: bool) -> Result<(), ExecutorError> {
+        let was_paused = self.state.normal_paused.replace(paused);
+        if was_paused && !paused && self.state.inner.borrow().has_normal_tasks() {
+            return self.start_spinning();
+        }
+        Ok(())
+    }
+
+    pub(crate) fn set_shuffle_seed(&self, seed: Option<u64>) {
+        self.state.inner.borrow_mut().shuffle = seed.map(ShuffleRng);
+    }
+
+    pub(crate) fn set_max_depth(&self, max_depth: Option<usize>) {
+        self.state.inner.borrow_mut().max_depth = max_depth;
+    }
+
+    /// Checks whether there's room in the queue for a new task.
+    pub(crate) fn admit(&self) -> Result<(), ExecutorError> {
+        let inner = self.state.inner.borrow();
+        let depth = inner.depth() + self.deferred_tasks.borrow().len();
+        match inner.max_depth {
+            Some(max_depth) if depth >= max_depth => Err(ExecutorError::QueueFull),
+            _ => Ok(()),
+        }
+    }
+
+    pub(crate) fn depths(&self) -> QueueDepths {
+        let inner = self.state.inner.borrow();
+        QueueDepths {
+            high: inner.high_priority_tasks.len(),
+            normal: inner.tasks.len() + inner.lanes.len() + self.deferred_tasks.borrow().len(),
+        }
+    }
+
+    pub(crate) fn set_coalesce_window_ms(&self, window: Option<f64>) {
+        self.state.coalesce_window_ms.set(window);
+    }
+
+    pub(crate) fn set_frame_budget_ms(&self, budget: Option<f64>) {
+        self.state.frame_budget_ms.set(budget);
+    }
+
+    pub(crate) fn pending_tasks_snapshot(&self) -> Array {
+        // Collect the tasks first, so no borrow is held while building the
+        // JS objects.
+        let tasks = {
+            let inner = self.state.inner.borrow();
+            let deferred = self.deferred_tasks.borrow();
+            inner
+                .high_priority_tasks
+                .iter()
+                .chain(inner.tasks.iter())
+                .chain(inner.lanes.iter())
+                .chain(deferred.iter())
+                .map(|task| (task.id(), task.is_high_priority(), task.queued_at_tick()))
+                .collect::<Vec<_>>()
+        };
+
+        let ticks = self.state.ticks.get();
+        let snapshot = Array::new();
+        for (id, high_priority, queued_at) in tasks {
+            let priority = if high_priority { "high" } else { "normal" };
+            let entry = Object::new();
+            let fields = [
+                ("id", JsValue::from(id as f64)),
+                ("priority", JsValue::from(priority)),
+                ("age_ticks", JsValue::from(ticks.wrapping_sub(queued_at) as f64)),
+            ];
+            for (key, value) in fields.iter() {
+                js_sys::Reflect::set(&entry, &JsValue::from(*key), value).unwrap_throw();
+            }
+            snapshot.push(&entry);
+        }
+        snapshot
+    }
+
+    pub(crate) fn stats(&self) -> QueueStats {
+        QueueStats {
+            microtask_drains: self.microtask_drains.get(),
+            macrotask_drains: self.macrotask_drains.get(),
+        }
+    }
+
+    /// Calls `run`, one of the closures running tasks outside of a tick,
+    /// through JS so that an exception thrown by a task is caught. As in a
+    /// tick, the task responsible is discarded and `run` called again to
+    /// carry on with the rest. Without this a task which panicked would stay
+    /// the current task forever, and no tick would ever run again.
+    fn call_catching(&self, run: &Closure<dyn FnMut()>) {
+        // As in `run_all`, don't blame a task for an earlier panic.
+        LAST_PANIC.with(|last| last.borrow_mut().take());
+
+        let run = run.as_ref().unchecked_ref::<Function>();
+        while let Err(error) = run.call0(&JsValue::undefined()) {
+            if !self.state.recover(&error) {
+                self.state.batch.borrow_mut().clear();
+                wasm_bindgen::throw_val(error);
+            }
+        }
+    }
+
+    /// Runs up to `max_tasks` tasks, high priority ones first, returning how
+    /// many ran. Normal priority tasks only run if `normal` is set.
+    ///
+    /// This does nothing if a task is already being polled, since the tick
+    /// (or drive) polling it will get to the rest of the tasks anyway.
+    fn drive(&self, max_tasks: usize, normal: bool) -> usize {
+        if self.state.is_polling() {
+            return 0;
+        }
+
+        self.state.drive_limit.set((max_tasks, normal));
+        self.call_catching(&self.drive_tasks);
+        let (left, _) = self.state.drive_limit.replace((0, false));
+        max_tasks - left
+    }
+
+    /// Runs `tasks` in order, outside of a tick.
+    fn run_tasks(&self, tasks: impl IntoIterator<Item = Rc<crate::task::Task>>) {
+        self.state.batch.borrow_mut().extend(tasks);
+        self.call_catching(&self.run_batch);
+    }
+
+    pub(crate) fn drive_n(&self, max_tasks: usize, normal: bool) -> usize {
+        let ran = self.drive(max_tasks, normal);
+
+        // Any tasks pushed while nothing was spinning must still get a tick.
+        // There's no way to report a failure from here, but a stalled queue
+        // is retried by the next push anyway.
+        if self.state.has_pending_tasks() {
+            let _ = self.start_spinning();
+        }
+        ran
+    }
+
+    pub(crate) fn drain_stale_tasks(&self, min_age_ticks: u32) -> usize {
+        if self.state.is_polling() {
+            return 0;
+        }
+
+        let ticks = self.state.ticks.get();
+        let age = |task: &crate::task::Task| ticks.wrapping_sub(task.queued_at_tick());
+        let is_stale = |task: &crate::task::Task| age(task) >= u64::from(min_age_ticks);
+
+        let normal = !self.state.normal_paused.get();
+        let mut stale = self.state.inner.borrow_mut().take_where(normal, is_stale);
+        if normal {
+            let mut deferred = self.deferred_tasks.borrow_mut();
+            let (taken, kept) = deferred.drain(..).partition::<Vec<_>, _>(|task| is_stale(task));
+            *deferred = kept;
+            stale.extend(taken);
+        }
+
+        // Oldest first. The sort is stable, so tasks queued on the same tick
+        // keep their order.
+        stale.sort_by_key(|task| std::cmp::Reverse(age(task)));
+        let ran = stale.len();
+        self.run_tasks(stale);
+
+        // As with `drive_n`, anything the tasks woke still needs a tick.
+        if self.state.has_pending_tasks() {
+            let _ = self.start_spinning();
+        }
+        ran
+    }
+
+    pub(crate) fn flush(&self) -> bool {
+        if self.state.is_polling() {
+            return false;
+        }
+        self.drive(usize::MAX, true);
+        true
+    }
+
+    pub(crate) fn shutdown(&self) -> bool {
+        if self.state.is_polling() {
+            return false;
+        }
+
+        // Shutdown tasks may well wake other tasks as they run, so keep going
+        // until nothing at all is queued.
+        loop {
+            let mut tasks = Vec::new();
+            {
+                let mut inner = self.state.inner.borrow_mut();
+                tasks.extend(inner.high_priority_tasks.drain(..));
+                while let Some(task) = inner.pop_task() {
+                    tasks.push(task);
+                }
+            }
+            tasks.extend(self.deferred_tasks.replace(Vec::new()));
+            if tasks.is_empty() {
+                return true;
+            }
+
+            let (run, discard): (Vec<_>, Vec<_>) =
+                tasks.into_iter().partition(|task| task.runs_on_shutdown());
+            for task in discard {
+                task.discard();
+            }
+            self.run_tasks(run);
+        }
+    }
+
+    /// Sets whether ticks are scheduled, returning the previous setting.
+    pub(crate) fn set_manual_wakes(&self, enabled: bool) -> bool {
+        let was_enabled = self.manual_wakes.replace(enabled);
+        // Tasks woken in the meantime still need their tick, though there's
+        // no one to report a failure to.
+        if was_enabled && !enabled && self.state.has_pending_tasks() {
+            let _ = self.start_spinning();
+        }
+        was_enabled
+    }
+
+    pub(crate) fn set_drain_on_page_hide(&self, enabled: bool) {
+        if self.drains_on_page_hide.replace(enabled) == enabled {
+            return;
+        }
+        let target = match js_sys::global().dyn_into::<web_sys::EventTarget>() {
+            Ok(target) => target,
+            // Not a host with pages to hide.
+            Err(_) => return,
+        };
+
+        let mut listener = self.page_hide_listener.borrow_mut();
+        let listener = listener.get_or_insert_with(|| {
+            Closure::wrap(Box::new(|| {
+                QUEUE.with(|queue| queue.flush());
+            }) as Box<dyn FnMut()>)
+        });
+        let listener = listener.as_ref().unchecked_ref();
+        for event_type in &["pagehide", "beforeunload"] {
+            let _ = if enabled {
+                target.add_event_listener_with_callback(event_type, listener)
+            } else {
+                target.remove_event_listener_with_callback(event_type, listener)
+            };
+        }
+    }
+
+    pub(crate) fn set_tick_prologue(&self, hook: Option<Box<dyn FnMut()>>) {
+        drop(self.state.tick_prologue.replace(hook.map(|hook| Rc::new(RefCell::new(hook)))));
+    }
+
+    pub(crate) fn set_tick_epilogue(&self, hook: Option<Box<dyn FnMut()>>) {
+        drop(self.state.tick_epilogue.replace(hook.map(|hook| Rc::new(RefCell::new(hook)))));
+    }
+
+    /// Sets the wake mode of the task being polled, returning whether there
+    /// was one.
+    pub(crate) fn set_current_wake_mode(&self, mode: WakeMode) -> bool {
+        match &*self.state.current_task.borrow() {
+            Some(task) => {
+                task.set_wake_mode(mode);
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Boosts the next wake of the task being polled, returning whether
+    /// there was one.
+    pub(crate) fn boost_current_task(&self) -> bool {
+        match &*self.state.current_task.borrow() {
+            Some(task) => {
+                task.boost_next_wake();
+                true
+            }
+            None => false,
+        }
+    }
+
+    pub(crate) fn set_stall_threshold_ms(&self, threshold: Option<f64>) {
+        let watchdog = threshold.and_then(watchdog::Watchdog::start);
+        // The old watchdog is dropped outside of the borrow, in case it's the
+        // one calling this from its report.
+        let old = self.watchdog.replace(watchdog);
+        drop(old);
+    }
+
+    /// Polls a future waiting for the queues to be empty, registering it as
+    /// `waiter` if it has to wait.
+    pub(crate) fn poll_idle(
+        &self,
+        waiter: &mut Option<Rc<IdleWaiter>>,
+        cx: &mut std::task::Context,
+    ) -> std::task::Poll<()> {
+        use std::task::Poll;
+
+        if let Some(waiter) = waiter {
+            if waiter.released.get() {
+                return Poll::Ready(());
+            }
+            drop(waiter.waker.replace(Some(cx.waker().clone())));
+            return Poll::Pending;
+        }
+
+        // With no tick running or scheduled there's nothing to wait for.
+        if !self.state.is_spinning.get() && !self.state.has_pending_tasks() {
+            return Poll::Ready(());
+        }
+
+        let new = Rc::new(IdleWaiter {
+            released: Cell::new(false),
+            waker: RefCell::new(Some(cx.waker().clone())),
+        });
+        self.state.idle_waiters.borrow_mut().push(Rc::clone(&new));
+        *waiter = Some(new);
+        Poll::Pending
+    }
+
+    pub(crate) fn current_task(&self) -> Option<Rc<crate::task::Task>> {
+        self.state.current_task.borrow().clone()
+    }
+
+    /// Aborts the task being polled, returning whether there was one.
+    pub(crate) fn abort_current_task(&self) -> bool {
+        match &*self.state.current_task.borrow() {
+            Some(task) => {
+                task.abort();
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Returns whether the host has `isInputPending` to yield on, which is
+    /// always `false` when turning it off.
+    pub(crate) fn set_yield_on_input_pending(&self, enabled: bool) -> bool {
+        let input_pending = if enabled { find_input_pending() } else { None };
+        let found = input_pending.is_some();
+        *self.state.input_pending.borrow_mut() = input_pending;
+        found
+    }
+
+    pub(crate) fn set_long_task_threshold_ms(&self, threshold: Option<f64>) {
+        self.state.long_task_threshold_ms.set(threshold);
+    }
+
+    pub(crate) fn set_jsfuture_coop(&self, enabled: bool) {
+        self.state.jsfuture_coop.set(enabled);
+    }
+
+    /// Called by a `JsFuture` about to complete, returning whether it should
+    /// yield to the other tasks first.
+    pub(crate) fn jsfuture_should_yield(&self) -> bool {
+        let state = &self.state;
+        if !state.jsfuture_coop.get() {
+            return false;
+        }
+
+        let ready = state.ready_jsfutures.get() + 1;
+        if ready > state.inner.borrow().coop_budget.max(1) {
+            state.ready_jsfutures.set(0);
+            return true;
+        }
+        state.ready_jsfutures.set(ready);
+        false
+    }
+
+    /// Sets the counters used for scheduling metadata, so that tests can
+    /// check how they behave as they wrap around.
+    pub(crate) fn set_scheduling_counters(&self, value: u64) {
+        self.state.ticks.set(value);
+        #[cfg(feature = "task-weights")]
+        self.state.inner.borrow_mut().tasks.set_next_seq(value);
+    }
+
+    pub(crate) fn swap_coop_budget(&self, budget: u32) -> u32 {
+        std::mem::replace(&mut self.state.inner.borrow_mut().coop_budget, budget)
     }
 }
 
 impl Queue {
     fn new() -> Self {
+        QUEUE_CREATED.with(|created| created.set(true));
+        install_panic_hook();
+
+        let config = PENDING_CONFIG
+            .with(|config| config.borrow_mut().take())
+            .unwrap_or_default();
+
         let state = Rc::new(QueueState {
             is_spinning: Cell::new(false),
+            is_stalled: Cell::new(false),
+            normal_paused: Cell::new(false),
+            jsfuture_coop: Cell::new(false),
+            ready_jsfutures: Cell::new(0),
+            idle_waiters: RefCell::new(Vec::new()),
+            frame_budget_ms: Cell::new(None),
+            input_pending: RefCell::new(None),
+            coalesce_window_ms: Cell::new(None),
+            last_tick_at: Cell::new(0.0),
+            ticks: Cell::new(0),
+            long_task_threshold_ms: Cell::new(None),
+            current_task: RefCell::new(None),
+            tick_prologue: RefCell::new(None),
+            tick_epilogue: RefCell::new(None),
+            drive_limit: Cell::new((0, false)),
+            batch: RefCell::new(VecDeque::new()),
             inner: RefCell::new(QueueStateInner {
                 high_priority_tasks: VecDeque::new(),
-                tasks: VecDeque::new(),
-                coop_budget: u32::MAX // effectively unlimited by default
+                tasks: NormalTasks::new(),
+                lanes: lanes::Lanes::new(),
+                coop_budget: config.coop_budget,
+                tick_deadline_ms: config.tick_deadline_ms,
+                shuffle: None,
+                max_depth: None,
             }),
         });
 
-        Self {
-            promise: Promise::resolve(&JsValue::undefined()),
+        let run_all = {
+            let state = Rc::clone(&state);
+            Closure::wrap(Box::new(move || state.run_all()) as Box<dyn FnMut()>)
+        };
+        let drive_tasks = {
+            let state = Rc::clone(&state);
+            Closure::wrap(Box::new(move || state.drive_tasks()) as Box<dyn FnMut()>)
+        };
+        let run_batch = {
+            let state = Rc::clone(&state);
+            Closure::wrap(Box::new(move || state.run_batch()) as Box<dyn FnMut()>)
+        };
 
-            closure: {
-                let state = Rc::clone(&state);
+        // This closure will only be called on the next microtask event tick,
+        // or on a macrotask if the previous tick ran out of budget
+        let closure = {
+            let state = Rc::clone(&state);
+            let run_all = run_all.as_ref().unchecked_ref::<Function>().clone();
+            Closure::wrap(Box::new(move |_| {
+                // A tick can only start inside another one if a task invokes
+                // this synchronously, say through a custom scheduler which
+                // calls back on the spot. The outer tick gets to the queued
+                // tasks anyway, so running them here would only reorder them,
+                // or poll the calling task from inside its own poll. That's a
+                // bug, so debug builds panic, which poisons the calling task.
+                debug_assert!(
+                    !state.is_polling(),
+                    "a tick of the executor was started from inside one of its tasks"
+                );
+                if state.is_polling() {
+                    return;
+                }
 
-                // This closure will only be called on the next microtask event
-                // tick
-                Closure::wrap(Box::new(move |_| state.run_all()))
-            },
+                state.ticks.set(state.ticks.get().wrapping_add(1));
+                if state.coalesce_window_ms.get().is_some() {
+                    state.last_tick_at.set(performance_now());
+                }
+                QueueState::run_hook(&state.tick_prologue);
+
+                // `call0` catches whatever `run_all` throws. Each time it
+                // does, discard the task responsible and keep going.
+                while let Err(error) = run_all.call0(&JsValue::undefined()) {
+                    if !state.recover(&error) {
+                        // Not a task's fault, so the queue itself is broken.
+                        // Let the next push start over and report the error.
+                        state.is_spinning.set(false);
+                        wasm_bindgen::throw_val(error);
+                    }
+                }
+
+                QueueState::run_hook(&state.tick_epilogue);
+            }) as Box<dyn Fn(JsValue)>)
+        };
+
+        let channel = message_channel(&closure);
+
+        let release_deferred = Closure::wrap(Box::new(|| {
+            // Like a waker, there's no one to report a failure to but JS.
+            if let Err(error) = QUEUE.with(|queue| queue.release_deferred_tasks()) {
+                wasm_bindgen::throw_val(error.into());
+            }
+        }) as Box<dyn FnMut()>);
+
+        Self {
+            microtasks: RefCell::new(Microtasks::detect()),
+            closure,
+            _run_all: run_all,
+            drive_tasks,
+            run_batch,
+            channel,
+            reschedule_delay_ms: config.reschedule_delay_ms,
+            custom_scheduler: RefCell::new(config.scheduler),
+            page_hide_listener: RefCell::new(None),
+            drains_on_page_hide: Cell::new(false),
+            deferred_tasks: RefCell::new(Vec::new()),
+            release_deferred,
+            watchdog: RefCell::new(None),
+            manual_wakes: Cell::new(false),
+            microtask_drains: Cell::new(0),
+            macrotask_drains: Cell::new(0),
 
             state,
         }
     }
 }
 
+/// Configures the executor for the current thread before it is first used.
+///
+/// The executor is created lazily the first time a future is spawned, so all
+/// of its parameters must be decided before then. A `QueueBuilder` collects
+/// them in one place and `install` creates the executor with them:
+///
+/// ```no_run
+/// use wasm_bindgen_futures::QueueBuilder;
+///
+/// QueueBuilder::new()
+///     .coop_budget(64)
+///     .tick_deadline_ms(8.0)
+///     .install()
+///     .expect("executor was already in use");
+/// ```
+#[derive(Clone)]
+pub struct QueueBuilder {
+    coop_budget: u32,
+    tick_deadline_ms: Option<f64>,
+    reschedule_delay_ms: i32,
+    scheduler: Option<CustomScheduler>,
+    observer: Option<Rc<dyn QueueObserver>>,
+}
+
+impl QueueBuilder {
+    /// Creates a builder with the default executor parameters.
+    pub fn new() -> Self {
+        Self {
+            coop_budget: u32::MAX, // effectively unlimited by default
+            tick_deadline_ms: None,
+            reschedule_delay_ms: 0,
+            scheduler: None,
+            observer: None,
+        }
+    }
+
+    /// Sets the maximum number of normal priority tasks run during a tick.
+    ///
+    /// See `set_coop_budget` for details.
+    pub fn coop_budget(mut self, budget: u32) -> Self {
+        self.coop_budget = budget;
+        self
+    }
+
+    /// Sets the maximum number of milliseconds a tick may spend running
+    /// tasks before it stops starting normal priority ones and yields to the
+    /// event loop.
+    ///
+    /// The deadline is checked between tasks, so a single long-running task
+    /// can still overrun it. High priority tasks always run, but the time
+    /// they take counts towards the deadline.
+    pub fn tick_deadline_ms(mut self, deadline: f64) -> Self {
+        self.tick_deadline_ms = Some(deadline);
+        self
+    }
+
+    /// Sets the `setTimeout` delay used to resume work after a tick yields
+    /// because its budget or deadline ran out. Defaults to 0.
+    pub fn reschedule_delay_ms(mut self, delay: i32) -> Self {
+        self.reschedule_delay_ms = delay;
+        self
+    }
+
+    /// Sets the function used to schedule macrotasks in hosts without a
+    /// `setTimeout`.
+    ///
+    /// See `set_custom_scheduler` for details, which can still replace it
+    /// later.
+    pub fn scheduler(mut self, scheduler: Box<SchedulerFn>) -> Self {
+        self.scheduler = Some(Rc::new(RefCell::new(scheduler)));
+        self
+    }
+
+    /// Sets the observer to notify about the executor's activity, so that it
+    /// sees everything from the start.
+    ///
+    /// This is the observer installed with `set_queue_observer`, which can
+    /// still replace or remove it later.
+    pub fn observer(mut self, observer: Box<dyn QueueObserver>) -> Self {
+        self.observer = Some(Rc::from(observer));
+        self
+    }
+
+    /// Creates the executor for the current thread with this configuration.
+    ///
+    /// Returns an error if the executor has already been created, for
+    /// example because a future was spawned before this was called.
+    pub fn install(mut self) -> Result<(), QueueInUse> {
+        if QUEUE_CREATED.with(|created| created.get()) {
+            return Err(QueueInUse);
+        }
+
+        // The observer lives outside of the queue, so install it first to
+        // catch anything creating the queue reports.
+        crate::observer::set_observer(self.observer.take());
+        PENDING_CONFIG.with(|config| *config.borrow_mut() = Some(self));
+
+        // Create the queue right away so nothing can observe the defaults.
+        QUEUE.with(|_| ());
+        Ok(())
+    }
+}
+
+impl fmt::Debug for QueueBuilder {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("QueueBuilder")
+            .field("coop_budget", &self.coop_budget)
+            .field("tick_deadline_ms", &self.tick_deadline_ms)
+            .field("reschedule_delay_ms", &self.reschedule_delay_ms)
+            .field("scheduler", &self.scheduler.is_some())
+            .field("observer", &self.observer.is_some())
+            .finish()
+    }
+}
+
+impl Default for QueueBuilder {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Error returned by `QueueBuilder::install` when the executor for the
+/// current thread has already been created.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct QueueInUse;
+
+impl fmt::Display for QueueInUse {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str("the executor for this thread is already in use")
+    }
+}
+
+impl Error for QueueInUse {}
+
+/// The number of tasks waiting to run in each of the executor's queues, as
+/// returned by `queue_depths`.
+///
+/// The task being polled right now isn't counted.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+#[non_exhaustive]
+pub struct QueueDepths {
+    /// Tasks spawned or woken with high priority.
+    pub high: usize,
+
+    /// Normal priority tasks, including ones held back by `pause_normal` and
+    /// ones woken in `WakeMode::Macrotask` that are waiting for their
+    /// macrotask.
+    pub normal: usize,
+}
+
+/// Counters describing how the executor on the current thread has been
+/// running, as returned by `queue_stats`.
+///
+/// Every drain of the queue runs on a tick scheduled either as a microtask,
+/// which is how ticks normally start, or as a macrotask, which is how a tick
+/// that ran out of coop budget or tick deadline resumes. A high share of
+/// macrotask drains means the budget is being exceeded often.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+#[non_exhaustive]
+pub struct QueueStats {
+    /// Ticks scheduled with `queueMicrotask` or a resolved promise.
+    pub microtask_drains: u64,
+
+    /// Ticks scheduled with a `MessageChannel`, `setTimeout` or the custom
+    /// scheduler after the budget ran out or to coalesce wakeups (see
+    /// `set_coalesce_window_ms`), or on an animation frame while
+    /// `run_budgeted_on_raf` is on.
+    pub macrotask_drains: u64,
+}
+
+/// A JS API the executor uses to schedule its ticks, as reported by
+/// `active_scheduler` and `active_macrotask_scheduler`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum SchedulerKind {
+    /// The global `queueMicrotask` function.
+    QueueMicrotask,
+
+    /// `then` on an already resolved `Promise`, for hosts without
+    /// `queueMicrotask`.
+    PromiseThen,
+
+    /// Posting a message to a `MessageChannel`, used for macrotasks when the
+    /// reschedule delay is 0.
+    MessageChannel,
+
+    /// The window's or global scope's `setTimeout`.
+    SetTimeout,
+
+    /// The function set with `set_custom_scheduler`.
+    Custom,
+
+    /// `requestAnimationFrame`, used for every tick while
+    /// `run_budgeted_on_raf` is on.
+    AnimationFrame,
+}
+
+/// Which of the executor's queues a task goes into, as set for `spawn_local`
+/// with `set_default_spawn_priority`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum Priority {
+    /// The normal queue, subject to the coop budget, like `spawn_local` uses
+    /// by default.
+    Normal,
+
+    /// The high priority queue, which always runs first, like
+    /// `spawn_local_high_priority` uses.
+    High,
+}
+
+/// How a task gets back onto the executor's queue when it's woken, as set by
+/// `set_wake_mode`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum WakeMode {
+    /// The task is queued right away and runs on the current or next
+    /// microtask tick, like every task does by default.
+    Microtask,
+
+    /// The task is queued from a `setTimeout` of 0, so the host can render
+    /// (and run other macrotasks) before it resumes.
+    Macrotask,
+}
+
+/// Error returned when the executor fails to schedule a tick to run tasks,
+/// or refuses a new one.
+///
+/// Except with `ExecutorError::QueueFull`, tasks are still queued when this
+/// happens, and run once a later spawn or wakeup manages to schedule a tick
+/// (or `drive_n` runs them directly).
+#[derive(Clone, Debug, PartialEq)]
+#[non_exhaustive]
+pub enum ExecutorError {
+    /// The host offers no way to schedule a macrotask: there's no
+    /// `setTimeout` on the window or global scope, and no custom scheduler
+    /// was set with `set_custom_scheduler`.
+    NoScheduler,
+
+    /// Calling the scheduling API threw the contained value.
+    SchedulingThrew(JsValue),
+
+    /// The host offers no way to schedule a microtask: there's no
+    /// `queueMicrotask`, and creating a resolved `Promise` threw the
+    /// contained value (for example because memory ran out). Creating it is
+    /// retried with every spawn or wakeup.
+    Unavailable(JsValue),
+
+    /// The queue already held as many tasks as `set_max_queue_depth` allows,
+    /// so the future was dropped without being spawned.
+    QueueFull,
+}
+
+impl fmt::Display for ExecutorError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ExecutorError::NoScheduler => {
+                f.write_str("no `setTimeout` or custom scheduler to schedule the executor with")
+            }
+            ExecutorError::SchedulingThrew(error) => {
+                write!(f, "scheduling the executor threw: {:?}", error)
+            }
+            ExecutorError::Unavailable(error) => write!(
+                f,
+                "no `queueMicrotask` to schedule the executor with, and creating a `Promise` threw: {:?}",
+                error
+            ),
+            ExecutorError::QueueFull => f.write_str("the executor's queue is full"),
+        }
+    }
+}
+
+impl Error for ExecutorError {}
+
+impl From<ExecutorError> for JsValue {
+    fn from(error: ExecutorError) -> JsValue {
+        match error {
+            ExecutorError::SchedulingThrew(error) => error,
+            error => js_sys::Error::new(&error.to_string()).into(),
+        }
+    }
+}
+
+/// Reports a poll of task `id` which took longer than the long task
+/// threshold, to the queue observers or as a warning if there are none.
+fn report_long_task(id: u64, duration_ms: f64) {
+    let notified = crate::observer::notify(|observer| observer.long_task(id, duration_ms));
+    if !notified {
+        log::warn!("task {} ran for {:.1}ms without yielding", id, duration_ms);
+    }
+}
+
+/// Looks up `navigator.scheduling.isInputPending`, along with the object to
+/// call it on.
+fn find_input_pending() -> Option<(JsValue, Function)> {
+    let navigator = js_sys::Reflect::get(&js_sys::global(), &JsValue::from("navigator")).ok()?;
+    if !navigator.is_object() {
+        return None;
+    }
+    let scheduling = js_sys::Reflect::get(&navigator, &JsValue::from("scheduling")).ok()?;
+    if !scheduling.is_object() {
+        return None;
+    }
+    let method = js_sys::Reflect::get(&scheduling, &JsValue::from("isInputPending")).ok()?;
+    let method = method.dyn_into::<Function>().ok()?;
+    Some((scheduling, method))
+}
+
+/// Looks up a function on the global object, if it exists.
+fn global_function(name: &str) -> Option<Function> {
+    js_sys::Reflect::get(&js_sys::global(), &JsValue::from(name))
+        .ok()
+        .and_then(|f| f.dyn_into::<Function>().ok())
+}
+
+/// Throws `error` from a later microtask, so it reaches the host as an
+/// uncaught exception without interrupting the tick which caught it. Hosts
+/// without `queueMicrotask` get it logged with `console.error` instead.
+pub(crate) fn rethrow_later(error: JsValue) {
+    if let Some(queue_microtask) = global_function("queueMicrotask") {
+        let rethrow = {
+            let error = error.clone();
+            Closure::once_into_js::<_, _, ()>(move || wasm_bindgen::throw_val(error))
+        };
+        if queue_microtask.call1(&JsValue::undefined(), &rethrow).is_ok() {
+            return;
+        }
+    }
+
+    let console = js_sys::Reflect::get(&js_sys::global(), &JsValue::from("console"));
+    if let Ok(console) = console {
+        let log_error = js_sys::Reflect::get(&console, &JsValue::from("error"))
+            .ok()
+            .and_then(|f| f.dyn_into::<Function>().ok());
+        if let Some(log_error) = log_error {
+            let _ = log_error.call1(&console, &error);
+        }
+    }
+}
+
+/// Finds a timer function such as `setTimeout` and the scope to call it on,
+/// preferring the window's and falling back to the global scope's (as in
+/// workers and other hosts).
+pub(crate) fn find_timer_function(name: &str) -> Option<(JsValue, Function)> {
+    let scope: JsValue = match web_sys::window() {
+        Some(window) => window.into(),
+        None => js_sys::global().into(),
+    };
+    let function = js_sys::Reflect::get(&scope, &JsValue::from(name))
+        .ok()?
+        .dyn_into::<Function>()
+        .ok()?;
+    Some((scope, function))
+}
+
+/// Creates a `MessageChannel` whose `port1` invokes `closure` on each message.
+fn message_channel(closure: &Closure<dyn Fn(JsValue)>) -> Option<web_sys::MessageChannel> {
+    let channel = web_sys::MessageChannel::new().ok()?;
+    let port = channel.port1();
+    port.set_onmessage(Some(closure.as_ref().unchecked_ref()));
+
+    // Node keeps the process alive while a port is listening, unless the
+    // port is unref'd.
+    if let Ok(unref) = js_sys::Reflect::get(&port, &JsValue::from("unref")) {
+        if let Some(unref) = unref.dyn_ref::<Function>() {
+            let _ = unref.call0(&port);
+        }
+    }
+
+    Some(channel)
+}
+
+/// Chains a panic hook which records the panic message, so that a future
+/// which panics can be rejected with something more useful than the
+/// `RuntimeError` produced by the resulting wasm trap.
+fn install_panic_hook() {
+    let prev = panic::take_hook();
+    panic::set_hook(Box::new(move |info| {
+        let _ = LAST_PANIC.try_with(|last| *last.borrow_mut() = Some(info.to_string()));
+        prev(info);
+    }));
+}
+
 thread_local! {
     pub(crate) static QUEUE: Queue = Queue::new();
+
+    static LAST_PANIC: RefCell<Option<String>> = RefCell::new(None);
+
+    // Configuration stashed by `QueueBuilder::install` for `Queue::new`
+    static PENDING_CONFIG: RefCell<Option<QueueBuilder>> = RefCell::new(None);
+
+    static QUEUE_CREATED: Cell<bool> = Cell::new(false);
 }
@@ -0,0 +1,113 @@
+use std::collections::{HashMap, VecDeque};
+use std::rc::Rc;
+
+use crate::task::Task;
+
+/// A lane in the round-robin rotation, where `None` stands for the default
+/// lane of tasks spawned without one.
+pub(super) type Lane = Option<u32>;
+
+/// The normal priority tasks spawned with `spawn_local_lane`, queued per
+/// lane.
+///
+/// While any of them are queued the executor takes turns between the lanes
+/// with queued tasks, one task per turn. The default lane's tasks live in
+/// the executor's usual queue, but it joins the rotation like any other.
+pub(super) struct Lanes {
+    queues: HashMap<u32, VecDeque<Rc<Task>>>,
+    // The lanes with queued tasks, in the order they get their next turn
+    turns: VecDeque<Lane>,
+    len: usize,
+}
+
+impl Lanes {
+    pub(super) fn new() -> Self {
+        Self {
+            queues: HashMap::new(),
+            turns: VecDeque::new(),
+            len: 0,
+        }
+    }
+
+    pub(super) fn push_back(&mut self, lane: u32, task: Rc<Task>) {
+        let queue = self.queues.entry(lane).or_default();
+        if queue.is_empty() {
+            self.turns.push_back(Some(lane));
+        }
+        queue.push_back(task);
+        self.len += 1;
+    }
+
+    /// Gives the default lane a turn, once it has tasks queued again.
+    pub(super) fn push_default_turn(&mut self) {
+        self.turns.push_back(None);
+    }
+
+    /// Takes the lane whose turn it is. An explicit lane gets its next task
+    /// popped as well, while the caller pops the default lane's.
+    pub(super) fn next_turn(&mut self) -> Option<(Lane, Option<Rc<Task>>)> {
+        let lane = self.turns.pop_front()?;
+        let id = match lane {
+            Some(id) => id,
+            None => return Some((None, None)),
+        };
+
+        let queue = self.queues.get_mut(&id)?;
+        let task = queue.pop_front();
+        if queue.is_empty() {
+            self.queues.remove(&id);
+        } else {
+            self.turns.push_back(lane);
+        }
+        self.len -= 1;
+
+        // Without explicit lanes left the default lane no longer needs
+        // turns.
+        if self.len == 0 {
+            self.turns.clear();
+        }
+        Some((lane, task))
+    }
+
+    /// Removes the tasks `pred` picks out, in the order they were queued
+    /// within each lane.
+    pub(super) fn take_where(&mut self, pred: impl Fn(&Task) -> bool) -> Vec<Rc<Task>> {
+        let mut taken = Vec::new();
+        for queue in self.queues.values_mut() {
+            taken.extend(super::take_where(queue, &pred));
+        }
+        if taken.is_empty() {
+            return taken;
+        }
+
+        self.queues.retain(|_, queue| !queue.is_empty());
+        let queues = &self.queues;
+        self.turns.retain(|lane| match lane {
+            Some(id) => queues.contains_key(id),
+            None => true,
+        });
+        self.len -= taken.len();
+        if self.len == 0 {
+            self.turns.clear();
+        }
+        taken
+    }
+
+    /// Takes the default lane out of the rotation, once it has run out of
+    /// tasks outside of its turns.
+    pub(super) fn remove_default_turns(&mut self) {
+        self.turns.retain(Option::is_some);
+    }
+
+    pub(super) fn iter(&self) -> impl Iterator<Item = &Rc<Task>> {
+        self.queues.values().flatten()
+    }
+
+    pub(super) fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    pub(super) fn len(&self) -> usize {
+        self.len
+    }
+}
@@ -0,0 +1,86 @@
+use std::cell::Cell;
+use wasm_bindgen::prelude::*;
+
+use super::{find_timer_function, performance_now, QueueDepths, QUEUE};
+
+/// Checks with `setInterval` that the executor makes progress while it has
+/// tasks to run, reporting once it hasn't for longer than the threshold.
+///
+/// Progress means a tick started. A queue with nothing to run, or whose
+/// normal tasks are paused, is never considered stalled.
+pub(super) struct Watchdog {
+    interval_id: JsValue,
+    // Kept alive until the interval is cleared
+    _check: Closure<dyn FnMut()>,
+}
+
+impl Watchdog {
+    /// Starts watching, or returns `None` if there's no `setInterval` to
+    /// watch with.
+    pub(super) fn start(threshold_ms: f64) -> Option<Self> {
+        let (scope, set_interval) = find_timer_function("setInterval")?;
+
+        let last_ticks = Cell::new(None);
+        let stalled_since = Cell::new(None);
+        let reported = Cell::new(false);
+        let check = Closure::wrap(Box::new(move || {
+            let (ticks, pending) = QUEUE.with(|queue| {
+                let pending = if queue.state.has_pending_tasks() {
+                    Some(queue.depths())
+                } else {
+                    None
+                };
+                (queue.state.ticks.get(), pending)
+            });
+
+            let now = performance_now();
+            let progressed = last_ticks.replace(Some(ticks)) != Some(ticks);
+            let pending = match pending {
+                Some(pending) if !progressed => pending,
+                _ => {
+                    stalled_since.set(None);
+                    reported.set(false);
+                    return;
+                }
+            };
+
+            let since = stalled_since.get().unwrap_or(now);
+            stalled_since.set(Some(since));
+            let stalled_ms = now - since;
+            if stalled_ms >= threshold_ms && !reported.replace(true) {
+                report_stall(stalled_ms, pending);
+            }
+        }) as Box<dyn FnMut()>);
+
+        // Checking twice per threshold reports a stall within one and a half
+        // thresholds of its start.
+        let interval_ms = (threshold_ms / 2.0).max(1.0);
+        let interval_id = set_interval
+            .call2(&scope, check.as_ref(), &JsValue::from(interval_ms))
+            .ok()?;
+
+        Some(Watchdog {
+            interval_id,
+            _check: check,
+        })
+    }
+}
+
+impl Drop for Watchdog {
+    fn drop(&mut self) {
+        if let Some((scope, clear_interval)) = find_timer_function("clearInterval") {
+            let _ = clear_interval.call1(&scope, &self.interval_id);
+        }
+    }
+}
+
+fn report_stall(stalled_ms: f64, pending: QueueDepths) {
+    let notified = crate::observer::notify(|observer| observer.queue_stalled(stalled_ms, pending));
+    if !notified {
+        log::warn!(
+            "the executor has had tasks to run ({:?}) but hasn't run a tick for {:.0}ms",
+            pending,
+            stalled_ms
+        );
+    }
+}
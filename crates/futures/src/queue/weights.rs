@@ -0,0 +1,97 @@
+use std::cmp::Ordering;
+use std::collections::BinaryHeap;
+use std::rc::Rc;
+
+use crate::task::Task;
+
+struct Entry {
+    weight: u32,
+    // Tasks of equal weight still run in the order they were queued. This
+    // wraps around rather than overflowing, so it's compared as a distance.
+    seq: u64,
+    task: Rc<Task>,
+}
+
+impl PartialEq for Entry {
+    fn eq(&self, other: &Self) -> bool {
+        self.cmp(other) == Ordering::Equal
+    }
+}
+
+impl Eq for Entry {}
+
+impl PartialOrd for Entry {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for Entry {
+    fn cmp(&self, other: &Self) -> Ordering {
+        // Queued tasks are never anywhere near 2^63 apart, so a distance
+        // past that means `seq` wrapped around in between.
+        let distance = self.seq.wrapping_sub(other.seq) as i64;
+        self.weight
+            .cmp(&other.weight)
+            .then_with(|| 0.cmp(&distance))
+    }
+}
+
+/// The normal priority queue when tasks are weighted, which pops the
+/// heaviest task first.
+///
+/// It mirrors the parts of `VecDeque` the executor uses, so the two are
+/// interchangeable.
+pub(super) struct WeightedTasks {
+    heap: BinaryHeap<Entry>,
+    next_seq: u64,
+}
+
+impl WeightedTasks {
+    pub(super) fn new() -> Self {
+        Self {
+            heap: BinaryHeap::new(),
+            next_seq: 0,
+        }
+    }
+
+    pub(super) fn push_back(&mut self, task: Rc<Task>) {
+        let seq = self.next_seq;
+        self.next_seq = seq.wrapping_add(1);
+        self.heap.push(Entry {
+            weight: task.weight(),
+            seq,
+            task,
+        });
+    }
+
+    pub(super) fn set_next_seq(&mut self, seq: u64) {
+        self.next_seq = seq;
+    }
+
+    pub(super) fn pop_front(&mut self) -> Option<Rc<Task>> {
+        self.heap.pop().map(|entry| entry.task)
+    }
+
+    /// Removes the tasks `pred` picks out, in the order they were queued.
+    pub(super) fn take_where(&mut self, pred: impl Fn(&Task) -> bool) -> Vec<Rc<Task>> {
+        let (mut taken, kept): (Vec<_>, Vec<_>) =
+            self.heap.drain().partition(|entry| pred(&entry.task));
+        self.heap = kept.into();
+        taken.sort_by(|a, b| (a.seq.wrapping_sub(b.seq) as i64).cmp(&0));
+        taken.into_iter().map(|entry| entry.task).collect()
+    }
+
+    /// Iterates over the queued tasks in no particular order.
+    pub(super) fn iter(&self) -> impl Iterator<Item = &Rc<Task>> {
+        self.heap.iter().map(|entry| &entry.task)
+    }
+
+    pub(super) fn is_empty(&self) -> bool {
+        self.heap.is_empty()
+    }
+
+    pub(super) fn len(&self) -> usize {
+        self.heap.len()
+    }
+}
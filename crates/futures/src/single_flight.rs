@@ -0,0 +1,170 @@
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::fmt;
+use std::future::Future;
+use std::hash::Hash;
+use std::pin::Pin;
+use std::rc::Rc;
+use std::task::{Context, Poll, Waker};
+
+// An operation in flight, shared by everyone waiting on it.
+struct Flight<V> {
+    result: Option<V>,
+    // Set once the operation's task is gone, with or without a result
+    done: bool,
+    wakers: Vec<Waker>,
+}
+
+type Flights<K, V> = Rc<RefCell<HashMap<K, Rc<RefCell<Flight<V>>>>>>;
+
+/// Deduplicates concurrent async operations on the current thread by key.
+///
+/// `get_or_spawn` only spawns an operation for a key if none is in flight
+/// for it already, and otherwise waits on the one that is. Everyone waiting
+/// on an operation receives a clone of its result. This suits deduplicating
+/// concurrent fetches of the same resource, say. Once an operation completes
+/// its key is forgotten, so the next `get_or_spawn` for it starts afresh.
+///
+/// Clones of a `SingleFlight` share the same operations.
+pub struct SingleFlight<K, V> {
+    flights: Flights<K, V>,
+}
+
+impl<K, V> SingleFlight<K, V>
+where
+    K: Eq + Hash + Clone + 'static,
+    V: Clone + 'static,
+{
+    /// Creates a `SingleFlight` with no operations in flight.
+    pub fn new() -> Self {
+        SingleFlight {
+            flights: Rc::new(RefCell::new(HashMap::new())),
+        }
+    }
+
+    /// Returns a future resolving to the result of the operation for `key`,
+    /// spawning the future `f` returns as that operation with `spawn_local`
+    /// if there's none in flight.
+    ///
+    /// The operation runs to completion even if every future waiting on it
+    /// is dropped. The returned future resolves to `None` if the operation's
+    /// task ends without a result instead, because the executor refused to
+    /// spawn it or it panicked, say.
+    pub fn get_or_spawn<F, Fut>(&self, key: K, f: F) -> SingleFlightWait<V>
+    where
+        F: FnOnce() -> Fut,
+        Fut: Future<Output = V> + 'static,
+    {
+        if let Some(flight) = self.flights.borrow().get(&key) {
+            return SingleFlightWait {
+                flight: Rc::clone(flight),
+            };
+        }
+
+        let flight = Rc::new(RefCell::new(Flight {
+            result: None,
+            done: false,
+            wakers: Vec::new(),
+        }));
+        self.flights
+            .borrow_mut()
+            .insert(key.clone(), Rc::clone(&flight));
+
+        let future = f();
+        let land = Land {
+            flights: Rc::clone(&self.flights),
+            key,
+            flight: Rc::clone(&flight),
+        };
+        crate::spawn_local(async move {
+            let result = future.await;
+            land.flight.borrow_mut().result = Some(result);
+            drop(land);
+        });
+
+        SingleFlightWait { flight }
+    }
+
+    /// Returns the number of operations in flight.
+    pub fn in_flight(&self) -> usize {
+        self.flights.borrow().len()
+    }
+}
+
+// Owned by an operation's task, so its key is forgotten and everyone
+// waiting on it is woken however the task ends.
+struct Land<K: Eq + Hash, V> {
+    flights: Flights<K, V>,
+    key: K,
+    flight: Rc<RefCell<Flight<V>>>,
+}
+
+impl<K: Eq + Hash, V> Drop for Land<K, V> {
+    fn drop(&mut self) {
+        self.flights.borrow_mut().remove(&self.key);
+        let wakers = {
+            let mut flight = self.flight.borrow_mut();
+            flight.done = true;
+            std::mem::take(&mut flight.wakers)
+        };
+        for waker in wakers {
+            waker.wake();
+        }
+    }
+}
+
+impl<K, V> Clone for SingleFlight<K, V> {
+    fn clone(&self) -> Self {
+        SingleFlight {
+            flights: Rc::clone(&self.flights),
+        }
+    }
+}
+
+impl<K, V> Default for SingleFlight<K, V>
+where
+    K: Eq + Hash + Clone + 'static,
+    V: Clone + 'static,
+{
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<K, V> fmt::Debug for SingleFlight<K, V> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("SingleFlight")
+            .field("in_flight", &self.flights.borrow().len())
+            .finish()
+    }
+}
+
+/// Future returned by `SingleFlight::get_or_spawn`.
+pub struct SingleFlightWait<V> {
+    flight: Rc<RefCell<Flight<V>>>,
+}
+
+impl<V: Clone> Future for SingleFlightWait<V> {
+    type Output = Option<V>;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context) -> Poll<Option<V>> {
+        let mut flight = self.flight.borrow_mut();
+        if flight.done {
+            return Poll::Ready(flight.result.clone());
+        }
+        if !flight
+            .wakers
+            .iter()
+            .any(|waker| waker.will_wake(cx.waker()))
+        {
+            flight.wakers.push(cx.waker().clone());
+        }
+        Poll::Pending
+    }
+}
+
+impl<V> fmt::Debug for SingleFlightWait<V> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "SingleFlightWait {{ ... }}")
+    }
+}
@@ -0,0 +1,235 @@
+//! Adapters for working with asynchronous streams on the current thread.
+//!
+//! Streams are modeled with the `Stream` trait from the `futures-core`
+//! crate, which is re-exported here for convenience.
+//!
+//! Sources of items which can outpace their consumer, like `channel`,
+//! `events` and `callback_stream`, buffer a bounded number of items and
+//! apply an `OverflowPolicy` once they're full rather than growing without
+//! bound.
+
+use crate::cancel::{CancellationToken, Cancelled};
+use js_sys::{Array, Function, Object, Promise, Reflect};
+use std::cell::RefCell;
+use std::future::Future;
+use std::pin::Pin;
+use std::rc::Rc;
+use std::task::{Context, Poll, Waker};
+use wasm_bindgen::prelude::*;
+use wasm_bindgen::JsCast;
+
+mod buffered;
+mod callback;
+mod channel;
+mod chunks;
+mod collect;
+mod dedup;
+mod events;
+mod flat_map;
+mod interval_timing;
+mod latest;
+mod merge;
+mod messages;
+mod peekable;
+mod scan;
+mod take;
+mod throttle;
+mod timeout_items;
+mod try_stream;
+mod unfold;
+mod zip;
+
+pub use buffered::{buffer_unordered, buffered, BufferUnordered, Buffered};
+pub use callback::{callback_stream, CallbackStream, Unregister};
+pub use channel::{channel, OverflowPolicy, Overflowed, Receiver, Sender};
+pub use chunks::{chunks, Chunks};
+pub use collect::{collect_to_array, collect_to_map, last, CollectToArray, CollectToMap, Last};
+pub use dedup::{dedup, dedup_by_key, Dedup, DedupByKey};
+pub use events::{events, once_event, EventStream, OnceEvent};
+pub use flat_map::{flat_map, flat_map_unordered, flatten, FlatMap, FlatMapUnordered, Flatten};
+pub use futures_core::Stream;
+pub use interval_timing::{with_interval_timing, WithIntervalTiming};
+pub use latest::{latest, Latest};
+pub use merge::{merge, Merge};
+pub use messages::{messages, MessageStream, MessageTarget};
+pub use peekable::{peekable, Peek, Peekable};
+pub use scan::{scan, Scan};
+pub use take::{skip, step_by, take, Skip, StepBy, Take};
+pub use throttle::{throttle, Throttle};
+pub use timeout_items::{timeout_items, Timeout, TimeoutItems};
+pub use try_stream::{try_stream, AsyncTryStream, YieldItem, Yielder};
+pub use unfold::{unfold, Unfold};
+pub use zip::{zip, Zip};
+
+/// Yields items from `stream` until `token` is cancelled, then ends.
+///
+/// Cancellation is checked before the underlying stream is polled, so an
+/// item the stream has already produced is never dropped; once the token is
+/// cancelled the stream simply isn't asked for another one.
+pub fn take_until<S>(stream: S, token: &CancellationToken) -> TakeUntil<S>
+where
+    S: Stream,
+{
+    TakeUntil {
+        stream,
+        cancelled: token.cancelled(),
+        done: false,
+    }
+}
+
+/// Stream returned by `take_until`.
+#[derive(Debug)]
+pub struct TakeUntil<S> {
+    stream: S,
+    cancelled: Cancelled,
+    done: bool,
+}
+
+impl<S: Stream> Stream for TakeUntil<S> {
+    type Item = S::Item;
+
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context) -> Poll<Option<S::Item>> {
+        // Safety: `stream` is structurally pinned and never moved out of `self`.
+        let this = unsafe { self.get_unchecked_mut() };
+
+        if this.done {
+            return Poll::Ready(None);
+        }
+
+        if Pin::new(&mut this.cancelled).poll(cx).is_ready() {
+            this.done = true;
+            return Poll::Ready(None);
+        }
+
+        let stream = unsafe { Pin::new_unchecked(&mut this.stream) };
+        match stream.poll_next(cx) {
+            Poll::Ready(None) => {
+                this.done = true;
+                Poll::Ready(None)
+            }
+            other => other,
+        }
+    }
+}
+
+#[wasm_bindgen]
+extern "C" {
+    type ReadableStreamDefaultController;
+
+    #[wasm_bindgen(method)]
+    fn enqueue(this: &ReadableStreamDefaultController, chunk: &JsValue);
+
+    #[wasm_bindgen(method)]
+    fn close(this: &ReadableStreamDefaultController);
+}
+
+type Callbacks = (
+    Closure<dyn FnMut(JsValue) -> Promise>,
+    Closure<dyn FnMut(JsValue)>,
+);
+
+struct Source<S> {
+    // `None` once the `ReadableStream` has been cancelled
+    stream: RefCell<Option<Pin<Box<S>>>>,
+    // The waker of a `pull` which is waiting on `stream`
+    waker: RefCell<Option<Waker>>,
+    // The `pull` and `cancel` callbacks, which refer back to this source and
+    // are dropped once the `ReadableStream` is closed or cancelled
+    callbacks: RefCell<Option<Callbacks>>,
+}
+
+impl<S: Stream<Item = JsValue>> Source<S> {
+    /// Polls for the next item, or `None` if the stream was cancelled.
+    fn poll_item(&self, cx: &mut Context) -> Poll<Option<Option<JsValue>>> {
+        let mut stream = self.stream.borrow_mut();
+        let stream = match stream.as_mut() {
+            Some(stream) => stream,
+            None => return Poll::Ready(None),
+        };
+        match stream.as_mut().poll_next(cx) {
+            Poll::Ready(item) => Poll::Ready(Some(item)),
+            Poll::Pending => {
+                drop(self.waker.replace(Some(cx.waker().clone())));
+                Poll::Pending
+            }
+        }
+    }
+
+    fn cancel(&self) {
+        let stream = self.stream.borrow_mut().take();
+        drop(stream);
+
+        // Let a pending `pull` see that it has nothing left to do.
+        let waker = self.waker.borrow_mut().take();
+        if let Some(waker) = waker {
+            waker.wake();
+        }
+        self.finish();
+    }
+
+    fn finish(&self) {
+        let callbacks = self.callbacks.borrow_mut().take();
+        drop(callbacks);
+    }
+}
+
+/// Converts a stream of `JsValue`s into a JS `ReadableStream`, so that it
+/// can be consumed from JS or handed to web APIs such as `Response`.
+///
+/// Items are pulled from `stream` only when the `ReadableStream` asks for
+/// more, which with the default queuing strategy means when its internal
+/// queue is empty. Each item is enqueued as is (for a `Response` body that
+/// means `Uint8Array` chunks), and the `ReadableStream` is closed once
+/// `stream` ends. Cancelling the `ReadableStream` drops `stream`.
+pub fn into_readable_stream<S>(stream: S) -> web_sys::ReadableStream
+where
+    S: Stream<Item = JsValue> + 'static,
+{
+    let source = Rc::new(Source {
+        stream: RefCell::new(Some(Box::pin(stream))),
+        waker: RefCell::new(None),
+        callbacks: RefCell::new(None),
+    });
+
+    let pull = {
+        let source = Rc::clone(&source);
+        Closure::wrap(Box::new(move |controller: JsValue| {
+            let source = Rc::clone(&source);
+            let controller = controller.unchecked_into::<ReadableStreamDefaultController>();
+            crate::future_to_promise(async move {
+                match crate::poll_fn(|cx| source.poll_item(cx)).await {
+                    Some(Some(item)) => controller.enqueue(&item),
+                    Some(None) => {
+                        controller.close();
+                        source.finish();
+                    }
+                    // Cancelled, so the controller no longer accepts anything.
+                    None => {}
+                }
+                Ok(JsValue::undefined())
+            })
+        }) as Box<dyn FnMut(JsValue) -> Promise>)
+    };
+
+    let cancel = {
+        let source = Rc::clone(&source);
+        Closure::wrap(Box::new(move |_reason: JsValue| source.cancel()) as Box<dyn FnMut(JsValue)>)
+    };
+
+    let underlying_source = Object::new();
+    Reflect::set(&underlying_source, &JsValue::from("pull"), pull.as_ref()).unwrap_throw();
+    Reflect::set(
+        &underlying_source,
+        &JsValue::from("cancel"),
+        cancel.as_ref(),
+    )
+    .unwrap_throw();
+    *source.callbacks.borrow_mut() = Some((pull, cancel));
+
+    let constructor = Reflect::get(&js_sys::global(), &JsValue::from("ReadableStream"))
+        .unwrap_throw()
+        .unchecked_into::<Function>();
+    Reflect::construct(&constructor, &Array::of1(&underlying_source))
+        .unwrap_throw()
+        .unchecked_into()
+}
@@ -0,0 +1,295 @@
+use super::Stream;
+use crate::cancel::{with_cancellation, CancellationToken};
+use std::cell::RefCell;
+use std::collections::VecDeque;
+use std::fmt;
+use std::future::Future;
+use std::marker::PhantomData;
+use std::pin::Pin;
+use std::rc::Rc;
+use std::task::{Context, Poll, Waker};
+
+/// Maps each item of `stream` to a future with `f`, running up to `limit` of
+/// them at once, and yields their outputs in the order of `stream`.
+///
+/// Each future is spawned as a task of its own, so they all make progress
+/// whether or not this stream is being polled, and a finished one holds on
+/// to its output until the ones before it are done too. Items of `stream`
+/// are only pulled while fewer than `limit` futures are in flight. Dropping
+/// the stream drops the futures still in flight.
+///
+/// An item whose future never finishes because its task was refused by the
+/// executor or panicked is skipped.
+///
+/// # Panics
+///
+/// Panics if `limit` is zero.
+pub fn buffered<S, F, Fut>(stream: S, limit: usize, f: F) -> Buffered<S, F, Fut::Output>
+where
+    S: Stream,
+    F: FnMut(S::Item) -> Fut,
+    Fut: Future + 'static,
+{
+    assert!(limit > 0, "buffering needs room for at least one future");
+    Buffered {
+        stream,
+        f,
+        limit,
+        done: false,
+        in_flight: VecDeque::new(),
+        waker: Rc::new(RefCell::new(None)),
+        token: CancellationToken::new(),
+    }
+}
+
+/// Same as `buffered`, except that outputs are yielded as soon as their
+/// future finishes, regardless of the order of `stream`.
+///
+/// # Panics
+///
+/// Panics if `limit` is zero.
+pub fn buffer_unordered<S, F, Fut>(
+    stream: S,
+    limit: usize,
+    f: F,
+) -> BufferUnordered<S, F, Fut::Output>
+where
+    S: Stream,
+    F: FnMut(S::Item) -> Fut,
+    Fut: Future + 'static,
+{
+    assert!(limit > 0, "buffering needs room for at least one future");
+    BufferUnordered {
+        stream,
+        f,
+        limit,
+        done: false,
+        in_flight: 0,
+        finished: Rc::new(RefCell::new(VecDeque::new())),
+        waker: Rc::new(RefCell::new(None)),
+        token: CancellationToken::new(),
+    }
+}
+
+type SharedWaker = Rc<RefCell<Option<Waker>>>;
+
+/// Spawns `future`, handing its output to `on_done` and waking the stream
+/// once it finishes.
+///
+/// `on_done` gets `None` instead if the future is dropped without an output:
+/// when `token` is cancelled, when the spawn is refused or when the task
+/// panics.
+fn spawn_transform<Fut, D>(future: Fut, token: &CancellationToken, waker: &SharedWaker, on_done: D)
+where
+    Fut: Future + 'static,
+    D: FnOnce(Option<Fut::Output>) + 'static,
+{
+    let token = token.clone();
+    let finish = Finish {
+        on_done: Some(on_done),
+        waker: Rc::clone(waker),
+        _output: PhantomData,
+    };
+    crate::spawn_local(async move {
+        let output = with_cancellation(future, &token).await;
+        finish.finish(output);
+    });
+}
+
+/// Owned by the task of `spawn_transform`, so the stream hears about the
+/// future however its task ends.
+struct Finish<D, T>
+where
+    D: FnOnce(Option<T>),
+{
+    on_done: Option<D>,
+    waker: SharedWaker,
+    _output: PhantomData<fn(T)>,
+}
+
+impl<D, T> Finish<D, T>
+where
+    D: FnOnce(Option<T>),
+{
+    fn finish(mut self, output: Option<T>) {
+        if let Some(on_done) = self.on_done.take() {
+            on_done(output);
+        }
+    }
+}
+
+impl<D, T> Drop for Finish<D, T>
+where
+    D: FnOnce(Option<T>),
+{
+    fn drop(&mut self) {
+        if let Some(on_done) = self.on_done.take() {
+            on_done(None);
+        }
+        let waker = self.waker.borrow_mut().take();
+        if let Some(waker) = waker {
+            waker.wake();
+        }
+    }
+}
+
+/// Stream returned by `buffered`.
+pub struct Buffered<S, F, U> {
+    stream: S,
+    f: F,
+    limit: usize,
+    done: bool,
+    // The outputs of the futures in flight, in the order of `stream`. A slot
+    // holds `Some(None)` if its future is gone without an output.
+    in_flight: VecDeque<Rc<RefCell<Option<Option<U>>>>>,
+    waker: SharedWaker,
+    token: CancellationToken,
+}
+
+impl<S, F, Fut> Stream for Buffered<S, F, Fut::Output>
+where
+    S: Stream,
+    F: FnMut(S::Item) -> Fut,
+    Fut: Future + 'static,
+{
+    type Item = Fut::Output;
+
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context) -> Poll<Option<Fut::Output>> {
+        // Safety: `stream` is structurally pinned and never moved out of
+        // `self`.
+        let this = unsafe { self.get_unchecked_mut() };
+
+        loop {
+            while !this.done && this.in_flight.len() < this.limit {
+                let stream = unsafe { Pin::new_unchecked(&mut this.stream) };
+                match stream.poll_next(cx) {
+                    Poll::Ready(Some(item)) => {
+                        let slot = Rc::new(RefCell::new(None));
+                        let output = Rc::clone(&slot);
+                        let future = (this.f)(item);
+                        spawn_transform(future, &this.token, &this.waker, move |value| {
+                            *output.borrow_mut() = Some(value);
+                        });
+                        this.in_flight.push_back(slot);
+                    }
+                    Poll::Ready(None) => this.done = true,
+                    Poll::Pending => break,
+                }
+            }
+
+            let output = match this.in_flight.front() {
+                Some(slot) => slot.borrow_mut().take(),
+                None if this.done => return Poll::Ready(None),
+                None => return Poll::Pending,
+            };
+            match output {
+                Some(Some(output)) => {
+                    this.in_flight.pop_front();
+                    return Poll::Ready(Some(output));
+                }
+                // Skip the item, which makes room for another one.
+                Some(None) => {
+                    this.in_flight.pop_front();
+                }
+                None => {
+                    *this.waker.borrow_mut() = Some(cx.waker().clone());
+                    return Poll::Pending;
+                }
+            }
+        }
+    }
+}
+
+impl<S, F, U> Drop for Buffered<S, F, U> {
+    fn drop(&mut self) {
+        self.token.cancel();
+    }
+}
+
+impl<S: fmt::Debug, F, U> fmt::Debug for Buffered<S, F, U> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("Buffered")
+            .field("stream", &self.stream)
+            .field("limit", &self.limit)
+            .field("in_flight", &self.in_flight.len())
+            .finish()
+    }
+}
+
+/// Stream returned by `buffer_unordered`.
+pub struct BufferUnordered<S, F, U> {
+    stream: S,
+    f: F,
+    limit: usize,
+    done: bool,
+    in_flight: usize,
+    // Outputs which haven't been yielded yet, in the order they finished,
+    // with `None` for each future gone without an output
+    finished: Rc<RefCell<VecDeque<Option<U>>>>,
+    waker: SharedWaker,
+    token: CancellationToken,
+}
+
+impl<S, F, Fut> Stream for BufferUnordered<S, F, Fut::Output>
+where
+    S: Stream,
+    F: FnMut(S::Item) -> Fut,
+    Fut: Future + 'static,
+{
+    type Item = Fut::Output;
+
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context) -> Poll<Option<Fut::Output>> {
+        // Safety: `stream` is structurally pinned and never moved out of
+        // `self`.
+        let this = unsafe { self.get_unchecked_mut() };
+
+        loop {
+            while !this.done && this.in_flight < this.limit {
+                let stream = unsafe { Pin::new_unchecked(&mut this.stream) };
+                match stream.poll_next(cx) {
+                    Poll::Ready(Some(item)) => {
+                        let finished = Rc::clone(&this.finished);
+                        let future = (this.f)(item);
+                        spawn_transform(future, &this.token, &this.waker, move |value| {
+                            finished.borrow_mut().push_back(value);
+                        });
+                        this.in_flight += 1;
+                    }
+                    Poll::Ready(None) => this.done = true,
+                    Poll::Pending => break,
+                }
+            }
+
+            let output = this.finished.borrow_mut().pop_front();
+            match output {
+                Some(Some(output)) => {
+                    this.in_flight -= 1;
+                    return Poll::Ready(Some(output));
+                }
+                // Skip the item, which makes room for another one.
+                Some(None) => this.in_flight -= 1,
+                None if this.done && this.in_flight == 0 => return Poll::Ready(None),
+                None => {
+                    *this.waker.borrow_mut() = Some(cx.waker().clone());
+                    return Poll::Pending;
+                }
+            }
+        }
+    }
+}
+
+impl<S, F, U> Drop for BufferUnordered<S, F, U> {
+    fn drop(&mut self) {
+        self.token.cancel();
+    }
+}
+
+impl<S: fmt::Debug, F, U> fmt::Debug for BufferUnordered<S, F, U> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("BufferUnordered")
+            .field("stream", &self.stream)
+            .field("limit", &self.limit)
+            .field("in_flight", &self.in_flight)
+            .finish()
+    }
+}
@@ -0,0 +1,100 @@
+use super::channel::{channel, OverflowPolicy, Overflowed, Receiver};
+use super::Stream;
+use std::fmt;
+use std::pin::Pin;
+use std::task::{Context, Poll};
+use wasm_bindgen::prelude::*;
+
+/// Undoes the registration made by the function passed to `callback_stream`,
+/// and is called when the stream is dropped.
+pub struct Unregister(Option<Box<dyn FnOnce()>>);
+
+impl Unregister {
+    /// Creates a handle which calls `f` to unregister the callback.
+    pub fn new(f: impl FnOnce() + 'static) -> Unregister {
+        Unregister(Some(Box::new(f)))
+    }
+
+    /// Creates a handle for an API which has no way of unregistering its
+    /// callback.
+    pub fn none() -> Unregister {
+        Unregister(None)
+    }
+
+    fn call(&mut self) {
+        if let Some(f) = self.0.take() {
+            f();
+        }
+    }
+}
+
+impl fmt::Debug for Unregister {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "Unregister {{ ... }}")
+    }
+}
+
+/// Creates a stream of the values a callback-based JS API passes to its
+/// callback, buffering up to `capacity` of them while the consumer lags
+/// behind.
+///
+/// `register` is called right away with the callback to hand to the API, and
+/// returns how to unregister it again. Every time the callback is invoked its
+/// argument is pushed to the stream, and once the buffer is full `policy`
+/// decides which value gets dropped, as with `channel`. The stream never ends
+/// on its own. Dropping it unregisters the callback, which stays alive until
+/// then.
+///
+/// This generalizes `events` to APIs that don't go through an `EventTarget`,
+/// such as `navigator.geolocation.watchPosition`.
+///
+/// # Panics
+///
+/// Panics if `capacity` is zero.
+pub fn callback_stream<F>(capacity: usize, policy: OverflowPolicy, register: F) -> CallbackStream
+where
+    F: FnOnce(&Closure<dyn FnMut(JsValue)>) -> Unregister,
+{
+    let (sender, receiver) = channel(capacity, policy);
+    let callback = Closure::wrap(Box::new(move |value: JsValue| {
+        let _ = sender.send(value);
+    }) as Box<dyn FnMut(JsValue)>);
+    let unregister = register(&callback);
+
+    CallbackStream {
+        receiver,
+        unregister,
+        callback,
+    }
+}
+
+/// Stream returned by `callback_stream`.
+pub struct CallbackStream {
+    receiver: Receiver<JsValue>,
+    unregister: Unregister,
+    // Kept alive for as long as the API may call it
+    callback: Closure<dyn FnMut(JsValue)>,
+}
+
+impl Stream for CallbackStream {
+    type Item = Result<JsValue, Overflowed>;
+
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context) -> Poll<Option<Self::Item>> {
+        Pin::new(&mut self.get_mut().receiver).poll_next(cx)
+    }
+}
+
+impl Drop for CallbackStream {
+    fn drop(&mut self) {
+        // Unregister before the callback itself is dropped.
+        self.unregister.call();
+    }
+}
+
+impl fmt::Debug for CallbackStream {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("CallbackStream")
+            .field("callback", &self.callback)
+            .finish()
+    }
+}
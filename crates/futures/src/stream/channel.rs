@@ -0,0 +1,212 @@
+use super::Stream;
+use std::cell::RefCell;
+use std::collections::VecDeque;
+use std::error::Error;
+use std::fmt;
+use std::pin::Pin;
+use std::rc::Rc;
+use std::task::{Context, Poll, Waker};
+
+/// What a bounded stream buffer does with a new item when it's already full
+/// because the consumer is lagging behind.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum OverflowPolicy {
+    /// Drop the oldest buffered item to make room, keeping the newest ones.
+    DropOldest,
+
+    /// Drop the new item, keeping the oldest ones.
+    DropNewest,
+
+    /// Drop the new item, and yield an `Err(Overflowed)` where it would have
+    /// been.
+    Error,
+}
+
+/// Error item yielded by a stream using `OverflowPolicy::Error` in place of
+/// the items it had to drop because its buffer was full.
+///
+/// Consecutive dropped items are reported by a single error.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Overflowed;
+
+impl fmt::Display for Overflowed {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str("items were dropped because the stream's buffer was full")
+    }
+}
+
+impl Error for Overflowed {}
+
+struct Shared<T> {
+    items: VecDeque<T>,
+    capacity: usize,
+    policy: OverflowPolicy,
+
+    // With `OverflowPolicy::Error`, the number of buffered items to yield
+    // before reporting that items were dropped
+    overflow_after: Option<usize>,
+
+    senders: usize,
+    receiver_alive: bool,
+    waker: Option<Waker>,
+}
+
+/// Creates a channel for feeding items to a stream on the current thread,
+/// buffering up to `capacity` of them.
+///
+/// Sending never waits. Once `capacity` items are buffered `policy` decides
+/// which item gets dropped. The `Receiver` ends once every `Sender` has been
+/// dropped and the buffered items have been yielded.
+///
+/// # Panics
+///
+/// Panics if `capacity` is zero.
+pub fn channel<T>(capacity: usize, policy: OverflowPolicy) -> (Sender<T>, Receiver<T>) {
+    assert!(capacity > 0, "a channel needs room for at least one item");
+
+    let shared = Rc::new(RefCell::new(Shared {
+        items: VecDeque::new(),
+        capacity,
+        policy,
+        overflow_after: None,
+        senders: 1,
+        receiver_alive: true,
+        waker: None,
+    }));
+    let sender = Sender {
+        shared: Rc::clone(&shared),
+    };
+    (sender, Receiver { shared })
+}
+
+/// The sending half of a `channel`.
+pub struct Sender<T> {
+    shared: Rc<RefCell<Shared<T>>>,
+}
+
+impl<T> Sender<T> {
+    /// Sends `item` to the receiver, applying the channel's overflow policy
+    /// if its buffer is full.
+    ///
+    /// Returns the item back if the receiver has been dropped.
+    pub fn send(&self, item: T) -> Result<(), T> {
+        let (dropped, waker) = {
+            let mut shared = self.shared.borrow_mut();
+            if !shared.receiver_alive {
+                return Err(item);
+            }
+
+            let dropped = if shared.items.len() < shared.capacity {
+                shared.items.push_back(item);
+                None
+            } else {
+                match shared.policy {
+                    OverflowPolicy::DropOldest => {
+                        let oldest = shared.items.pop_front();
+                        shared.items.push_back(item);
+                        oldest
+                    }
+                    OverflowPolicy::DropNewest => Some(item),
+                    OverflowPolicy::Error => {
+                        if shared.overflow_after.is_none() {
+                            shared.overflow_after = Some(shared.items.len());
+                        }
+                        Some(item)
+                    }
+                }
+            };
+            (dropped, shared.waker.take())
+        };
+
+        // Items are dropped outside of the borrow in case their destructors
+        // use the channel.
+        drop(dropped);
+        if let Some(waker) = waker {
+            waker.wake();
+        }
+        Ok(())
+    }
+}
+
+impl<T> Clone for Sender<T> {
+    fn clone(&self) -> Self {
+        self.shared.borrow_mut().senders += 1;
+        Sender {
+            shared: Rc::clone(&self.shared),
+        }
+    }
+}
+
+impl<T> Drop for Sender<T> {
+    fn drop(&mut self) {
+        let waker = {
+            let mut shared = self.shared.borrow_mut();
+            shared.senders -= 1;
+            if shared.senders > 0 {
+                return;
+            }
+            shared.waker.take()
+        };
+
+        // Let the receiver see that the channel has ended.
+        if let Some(waker) = waker {
+            waker.wake();
+        }
+    }
+}
+
+impl<T> fmt::Debug for Sender<T> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "Sender {{ ... }}")
+    }
+}
+
+/// The receiving half of a `channel`, which is a stream of the items sent
+/// to it.
+pub struct Receiver<T> {
+    shared: Rc<RefCell<Shared<T>>>,
+}
+
+impl<T> Stream for Receiver<T> {
+    type Item = Result<T, Overflowed>;
+
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context) -> Poll<Option<Self::Item>> {
+        let mut shared = self.shared.borrow_mut();
+
+        if shared.overflow_after == Some(0) {
+            shared.overflow_after = None;
+            return Poll::Ready(Some(Err(Overflowed)));
+        }
+
+        if let Some(item) = shared.items.pop_front() {
+            if let Some(remaining) = shared.overflow_after.as_mut() {
+                *remaining -= 1;
+            }
+            return Poll::Ready(Some(Ok(item)));
+        }
+
+        if shared.senders == 0 {
+            return Poll::Ready(None);
+        }
+
+        shared.waker = Some(cx.waker().clone());
+        Poll::Pending
+    }
+}
+
+impl<T> Drop for Receiver<T> {
+    fn drop(&mut self) {
+        let (items, waker) = {
+            let mut shared = self.shared.borrow_mut();
+            shared.receiver_alive = false;
+            (std::mem::take(&mut shared.items), shared.waker.take())
+        };
+        drop((items, waker));
+    }
+}
+
+impl<T> fmt::Debug for Receiver<T> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "Receiver {{ ... }}")
+    }
+}
@@ -0,0 +1,66 @@
+use super::Stream;
+use std::mem;
+use std::pin::Pin;
+use std::task::{Context, Poll};
+
+/// Groups the items of `stream` into `Vec`s of `n` items each.
+///
+/// Only the last chunk can be shorter, holding whatever was left once
+/// `stream` ended. An empty stream yields no chunks at all.
+///
+/// # Panics
+///
+/// Panics if `n` is zero.
+pub fn chunks<S>(stream: S, n: usize) -> Chunks<S>
+where
+    S: Stream,
+{
+    assert!(n > 0, "chunks need room for at least one item");
+
+    Chunks {
+        stream,
+        items: Vec::with_capacity(n),
+        n,
+        done: false,
+    }
+}
+
+/// Stream returned by `chunks`.
+#[derive(Debug)]
+pub struct Chunks<S: Stream> {
+    stream: S,
+    items: Vec<S::Item>,
+    n: usize,
+    done: bool,
+}
+
+impl<S: Stream> Stream for Chunks<S> {
+    type Item = Vec<S::Item>;
+
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context) -> Poll<Option<Self::Item>> {
+        // Safety: `stream` is structurally pinned and never moved out of
+        // `self`, while the buffered items are never pinned.
+        let this = unsafe { self.get_unchecked_mut() };
+
+        while !this.done {
+            let stream = unsafe { Pin::new_unchecked(&mut this.stream) };
+            match stream.poll_next(cx) {
+                Poll::Ready(Some(item)) => {
+                    this.items.push(item);
+                    if this.items.len() == this.n {
+                        let chunk = mem::replace(&mut this.items, Vec::with_capacity(this.n));
+                        return Poll::Ready(Some(chunk));
+                    }
+                }
+                Poll::Ready(None) => this.done = true,
+                Poll::Pending => return Poll::Pending,
+            }
+        }
+
+        if this.items.is_empty() {
+            Poll::Ready(None)
+        } else {
+            Poll::Ready(Some(mem::take(&mut this.items)))
+        }
+    }
+}
@@ -0,0 +1,159 @@
+use super::Stream;
+use js_sys::{Array, Map};
+use std::fmt;
+use std::future::Future;
+use std::pin::Pin;
+use std::task::{Context, Poll};
+use wasm_bindgen::JsValue;
+
+/// Returns a future which awaits every item of `stream` and resolves to a JS
+/// `Array` of them, in the order they were yielded.
+///
+/// This is handy for returning a stream's results to JS, for example from a
+/// function exported with `#[wasm_bindgen]` through `future_to_promise`.
+pub fn collect_to_array<S>(stream: S) -> CollectToArray<S>
+where
+    S: Stream,
+    S::Item: Into<JsValue>,
+{
+    CollectToArray {
+        stream,
+        array: Array::new(),
+    }
+}
+
+/// Future returned by `collect_to_array`.
+#[derive(Debug)]
+pub struct CollectToArray<S> {
+    stream: S,
+    array: Array,
+}
+
+impl<S> Future for CollectToArray<S>
+where
+    S: Stream,
+    S::Item: Into<JsValue>,
+{
+    type Output = Array;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context) -> Poll<Array> {
+        // Safety: `stream` is structurally pinned and never moved out of
+        // `self`, while the array is never pinned.
+        let this = unsafe { self.get_unchecked_mut() };
+
+        loop {
+            let stream = unsafe { Pin::new_unchecked(&mut this.stream) };
+            match stream.poll_next(cx) {
+                Poll::Ready(Some(item)) => {
+                    this.array.push(&item.into());
+                }
+                Poll::Ready(None) => return Poll::Ready(this.array.clone()),
+                Poll::Pending => return Poll::Pending,
+            }
+        }
+    }
+}
+
+/// Returns a future which awaits every key/value pair of `stream` and
+/// resolves to a JS `Map` of them.
+///
+/// The map's entries keep the order the keys were first yielded in, and a
+/// key yielded more than once keeps the last value it was yielded with, as
+/// with `Map.prototype.set`.
+pub fn collect_to_map<S, K, V>(stream: S) -> CollectToMap<S>
+where
+    S: Stream<Item = (K, V)>,
+    K: Into<JsValue>,
+    V: Into<JsValue>,
+{
+    CollectToMap {
+        stream,
+        map: Map::new(),
+    }
+}
+
+/// Future returned by `collect_to_map`.
+#[derive(Debug)]
+pub struct CollectToMap<S> {
+    stream: S,
+    map: Map,
+}
+
+impl<S, K, V> Future for CollectToMap<S>
+where
+    S: Stream<Item = (K, V)>,
+    K: Into<JsValue>,
+    V: Into<JsValue>,
+{
+    type Output = Map;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context) -> Poll<Map> {
+        // Safety: `stream` is structurally pinned and never moved out of
+        // `self`, while the map is never pinned.
+        let this = unsafe { self.get_unchecked_mut() };
+
+        loop {
+            let stream = unsafe { Pin::new_unchecked(&mut this.stream) };
+            match stream.poll_next(cx) {
+                Poll::Ready(Some((key, value))) => {
+                    this.map.set(&key.into(), &value.into());
+                }
+                Poll::Ready(None) => return Poll::Ready(this.map.clone()),
+                Poll::Pending => return Poll::Pending,
+            }
+        }
+    }
+}
+
+/// Returns a future which awaits every item of `stream` and resolves to the
+/// last one, or `None` if the stream ended without yielding any.
+///
+/// This suits awaiting the final state of a stream of states. Only the most
+/// recent item is held on to, so the stream can be arbitrarily long.
+pub fn last<S>(stream: S) -> Last<S>
+where
+    S: Stream,
+{
+    Last { stream, last: None }
+}
+
+/// Future returned by `last`.
+pub struct Last<S: Stream> {
+    stream: S,
+    last: Option<S::Item>,
+}
+
+impl<S> Future for Last<S>
+where
+    S: Stream,
+{
+    type Output = Option<S::Item>;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context) -> Poll<Option<S::Item>> {
+        // Safety: `stream` is structurally pinned and never moved out of
+        // `self`, while the last item is never pinned.
+        let this = unsafe { self.get_unchecked_mut() };
+
+        loop {
+            let stream = unsafe { Pin::new_unchecked(&mut this.stream) };
+            match stream.poll_next(cx) {
+                Poll::Ready(Some(item)) => this.last = Some(item),
+                Poll::Ready(None) => return Poll::Ready(this.last.take()),
+                Poll::Pending => return Poll::Pending,
+            }
+        }
+    }
+}
+
+impl<S> fmt::Debug for Last<S>
+where
+    S: Stream + fmt::Debug,
+    S::Item: fmt::Debug,
+{
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("Last")
+            .field("stream", &self.stream)
+            .field("last", &self.last)
+            .finish()
+    }
+}
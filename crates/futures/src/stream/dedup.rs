@@ -0,0 +1,85 @@
+use super::Stream;
+use std::fmt;
+use std::pin::Pin;
+use std::task::{Context, Poll};
+
+/// Skips items of `stream` which are equal to the item yielded right before
+/// them, so that only changes come through.
+///
+/// This suits streams of UI state which re-emit unchanged values, sparing
+/// downstream work such as re-rendering on updates which change nothing. A
+/// clone of the last yielded item is kept to compare the next one with.
+pub fn dedup<S>(stream: S) -> Dedup<S>
+where
+    S: Stream,
+    S::Item: PartialEq + Clone,
+{
+    dedup_by_key(stream, <S::Item as Clone>::clone as fn(&S::Item) -> S::Item)
+}
+
+/// Stream returned by `dedup`.
+pub type Dedup<S> =
+    DedupByKey<S, fn(&<S as Stream>::Item) -> <S as Stream>::Item, <S as Stream>::Item>;
+
+/// Like `dedup`, but compares the keys `key` returns for the items instead
+/// of the items themselves.
+///
+/// An item is skipped when its key equals the key of the item yielded right
+/// before it. Only that key is kept, not the item.
+pub fn dedup_by_key<S, F, K>(stream: S, key: F) -> DedupByKey<S, F, K>
+where
+    S: Stream,
+    F: FnMut(&S::Item) -> K,
+    K: PartialEq,
+{
+    DedupByKey {
+        stream,
+        key,
+        last: None,
+    }
+}
+
+/// Stream returned by `dedup_by_key`.
+pub struct DedupByKey<S, F, K> {
+    stream: S,
+    key: F,
+    // The key of the last item yielded
+    last: Option<K>,
+}
+
+impl<S, F, K> Stream for DedupByKey<S, F, K>
+where
+    S: Stream,
+    F: FnMut(&S::Item) -> K,
+    K: PartialEq,
+{
+    type Item = S::Item;
+
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context) -> Poll<Option<S::Item>> {
+        // Safety: `stream` is structurally pinned and never moved out of
+        // `self`, while the key function and last key are never pinned.
+        let this = unsafe { self.get_unchecked_mut() };
+
+        loop {
+            let stream = unsafe { Pin::new_unchecked(&mut this.stream) };
+            let item = match stream.poll_next(cx) {
+                Poll::Ready(Some(item)) => item,
+                other => return other,
+            };
+            let key = (this.key)(&item);
+            if this.last.as_ref() != Some(&key) {
+                this.last = Some(key);
+                return Poll::Ready(Some(item));
+            }
+        }
+    }
+}
+
+impl<S: fmt::Debug, F, K: fmt::Debug> fmt::Debug for DedupByKey<S, F, K> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("DedupByKey")
+            .field("stream", &self.stream)
+            .field("last", &self.last)
+            .finish()
+    }
+}
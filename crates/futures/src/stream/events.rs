@@ -0,0 +1,177 @@
+use super::channel::{channel, OverflowPolicy, Overflowed, Receiver};
+use super::Stream;
+use std::cell::RefCell;
+use std::fmt;
+use std::future::Future;
+use std::pin::Pin;
+use std::rc::Rc;
+use std::task::{Context, Poll, Waker};
+use wasm_bindgen::prelude::*;
+use wasm_bindgen::JsCast;
+
+/// Creates a stream of the `event_type` events dispatched to `target`,
+/// buffering up to `capacity` of them while the consumer lags behind.
+///
+/// Once the buffer is full `policy` decides which event gets dropped, as
+/// with `channel`. The stream never ends on its own, and its listener is
+/// removed when it's dropped.
+///
+/// # Panics
+///
+/// Panics if `capacity` is zero.
+pub fn events(
+    target: &web_sys::EventTarget,
+    event_type: &str,
+    capacity: usize,
+    policy: OverflowPolicy,
+) -> EventStream {
+    let (sender, receiver) = channel(capacity, policy);
+    let listener = Closure::wrap(Box::new(move |event: JsValue| {
+        let _ = sender.send(event.unchecked_into());
+    }) as Box<dyn FnMut(JsValue)>);
+
+    target
+        .add_event_listener_with_callback(event_type, listener.as_ref().unchecked_ref())
+        .unwrap_throw();
+
+    EventStream {
+        receiver,
+        target: target.clone(),
+        event_type: event_type.to_string(),
+        listener,
+    }
+}
+
+/// Stream returned by `events`.
+pub struct EventStream {
+    receiver: Receiver<web_sys::Event>,
+    target: web_sys::EventTarget,
+    event_type: String,
+    listener: Closure<dyn FnMut(JsValue)>,
+}
+
+impl Stream for EventStream {
+    type Item = Result<web_sys::Event, Overflowed>;
+
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context) -> Poll<Option<Self::Item>> {
+        Pin::new(&mut self.get_mut().receiver).poll_next(cx)
+    }
+}
+
+impl Drop for EventStream {
+    fn drop(&mut self) {
+        let _ = self.target.remove_event_listener_with_callback(
+            &self.event_type,
+            self.listener.as_ref().unchecked_ref(),
+        );
+    }
+}
+
+impl fmt::Debug for EventStream {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("EventStream")
+            .field("event_type", &self.event_type)
+            .finish()
+    }
+}
+
+// Shared between a `OnceEvent` and its listener
+struct OnceState {
+    // `Some` from when the event is dispatched until it's handed out
+    event: Option<web_sys::Event>,
+    fired: bool,
+    waker: Option<Waker>,
+}
+
+/// Returns a future which resolves with the first `event_type` event
+/// dispatched to `target`.
+///
+/// This is the counterpart of `events` for events which are only awaited
+/// once, such as `DOMContentLoaded`, `load` or a one-off `click`. The
+/// listener is registered with `{ once: true }`, so the browser removes it
+/// as soon as the event fires, and in any case it's removed when the future
+/// is dropped.
+pub fn once_event(target: &web_sys::EventTarget, event_type: &str) -> OnceEvent {
+    let state = Rc::new(RefCell::new(OnceState {
+        event: None,
+        fired: false,
+        waker: None,
+    }));
+
+    let listener = {
+        let state = Rc::clone(&state);
+        Closure::wrap(Box::new(move |event: JsValue| {
+            let waker = {
+                let mut state = state.borrow_mut();
+                // Hosts which don't know `once` keep calling the listener.
+                if state.fired {
+                    return;
+                }
+                state.fired = true;
+                state.event = Some(event.unchecked_into());
+                state.waker.take()
+            };
+            if let Some(waker) = waker {
+                waker.wake();
+            }
+        }) as Box<dyn FnMut(JsValue)>)
+    };
+
+    let mut options = web_sys::AddEventListenerOptions::new();
+    options.once(true);
+    target
+        .add_event_listener_with_callback_and_add_event_listener_options(
+            event_type,
+            listener.as_ref().unchecked_ref(),
+            &options,
+        )
+        .unwrap_throw();
+
+    OnceEvent {
+        state,
+        target: target.clone(),
+        event_type: event_type.to_string(),
+        listener,
+    }
+}
+
+/// Future returned by `once_event`.
+pub struct OnceEvent {
+    state: Rc<RefCell<OnceState>>,
+    target: web_sys::EventTarget,
+    event_type: String,
+    listener: Closure<dyn FnMut(JsValue)>,
+}
+
+impl Future for OnceEvent {
+    type Output = web_sys::Event;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context) -> Poll<web_sys::Event> {
+        let mut state = self.state.borrow_mut();
+        if let Some(event) = state.event.take() {
+            return Poll::Ready(event);
+        }
+        state.waker = Some(cx.waker().clone());
+        Poll::Pending
+    }
+}
+
+impl Drop for OnceEvent {
+    fn drop(&mut self) {
+        // The listener is only gone already if the host knows `once`, and
+        // removing it again is harmless.
+        let _ = self.target.remove_event_listener_with_callback(
+            &self.event_type,
+            self.listener.as_ref().unchecked_ref(),
+        );
+    }
+}
+
+impl fmt::Debug for OnceEvent {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("OnceEvent")
+            .field("event_type", &self.event_type)
+            .field("fired", &self.state.borrow().fired)
+            .finish()
+    }
+}
@@ -0,0 +1,191 @@
+use super::Stream;
+use std::fmt;
+use std::pin::Pin;
+use std::task::{Context, Poll};
+
+/// Maps each item of `stream` to a sub-stream with `f`, and yields the items
+/// of the sub-streams one sub-stream at a time.
+///
+/// Each sub-stream is drained before the next item of `stream` is even
+/// pulled, so the output keeps the order of `stream`. Use
+/// `flat_map_unordered` to have the sub-streams make progress at the same
+/// time instead.
+pub fn flat_map<S, F, U>(stream: S, f: F) -> FlatMap<S, F, U>
+where
+    S: Stream,
+    F: FnMut(S::Item) -> U,
+    U: Stream,
+{
+    FlatMap {
+        stream,
+        f,
+        current: None,
+        done: false,
+    }
+}
+
+/// Flattens a stream of streams, yielding the items of each sub-stream in
+/// turn, as `flat_map` without the mapping.
+pub fn flatten<S>(stream: S) -> Flatten<S>
+where
+    S: Stream,
+    S::Item: Stream,
+{
+    flat_map(stream, |sub_stream| sub_stream)
+}
+
+/// Stream returned by `flatten`.
+pub type Flatten<S> =
+    FlatMap<S, fn(<S as Stream>::Item) -> <S as Stream>::Item, <S as Stream>::Item>;
+
+/// Stream returned by `flat_map` and `flatten`.
+pub struct FlatMap<S, F, U> {
+    stream: S,
+    f: F,
+    current: Option<U>,
+    done: bool,
+}
+
+impl<S, F, U> Stream for FlatMap<S, F, U>
+where
+    S: Stream,
+    F: FnMut(S::Item) -> U,
+    U: Stream,
+{
+    type Item = U::Item;
+
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context) -> Poll<Option<U::Item>> {
+        // Safety: `stream` and the current sub-stream are structurally
+        // pinned and never moved out of `self`. A finished sub-stream is
+        // dropped in place when it's replaced.
+        let this = unsafe { self.get_unchecked_mut() };
+
+        loop {
+            if let Some(current) = this.current.as_mut() {
+                let current = unsafe { Pin::new_unchecked(current) };
+                match current.poll_next(cx) {
+                    Poll::Ready(Some(item)) => return Poll::Ready(Some(item)),
+                    Poll::Ready(None) => this.current = None,
+                    Poll::Pending => return Poll::Pending,
+                }
+            }
+
+            if this.done {
+                return Poll::Ready(None);
+            }
+            let stream = unsafe { Pin::new_unchecked(&mut this.stream) };
+            match stream.poll_next(cx) {
+                Poll::Ready(Some(item)) => this.current = Some((this.f)(item)),
+                Poll::Ready(None) => this.done = true,
+                Poll::Pending => return Poll::Pending,
+            }
+        }
+    }
+}
+
+impl<S: fmt::Debug, F, U: fmt::Debug> fmt::Debug for FlatMap<S, F, U> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("FlatMap")
+            .field("stream", &self.stream)
+            .field("current", &self.current)
+            .finish()
+    }
+}
+
+/// Maps each item of `stream` to a sub-stream with `f`, and yields the items
+/// of all the sub-streams as they come.
+///
+/// Items of `stream` are pulled as soon as they're ready, and every
+/// sub-stream is polled alongside the others, taking turns so that a busy
+/// one can't starve the rest. The output is therefore interleaved, in the
+/// order items become ready. The sub-streams are driven by whichever task
+/// polls this stream, so they only make progress while it's polled. The
+/// stream ends once `stream` and every sub-stream have ended.
+pub fn flat_map_unordered<S, F, U>(stream: S, f: F) -> FlatMapUnordered<S, F, U>
+where
+    S: Stream,
+    F: FnMut(S::Item) -> U,
+    U: Stream,
+{
+    FlatMapUnordered {
+        stream,
+        f,
+        sub_streams: Vec::new(),
+        next: 0,
+        done: false,
+    }
+}
+
+/// Stream returned by `flat_map_unordered`.
+pub struct FlatMapUnordered<S, F, U> {
+    stream: S,
+    f: F,
+    sub_streams: Vec<Pin<Box<U>>>,
+    // The sub-stream to poll first next time
+    next: usize,
+    done: bool,
+}
+
+impl<S, F, U> Stream for FlatMapUnordered<S, F, U>
+where
+    S: Stream,
+    F: FnMut(S::Item) -> U,
+    U: Stream,
+{
+    type Item = U::Item;
+
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context) -> Poll<Option<U::Item>> {
+        // Safety: `stream` is structurally pinned and never moved out of
+        // `self`, while the sub-streams are pinned in their own boxes.
+        let this = unsafe { self.get_unchecked_mut() };
+
+        let mut pulled = false;
+        if !this.done {
+            let stream = unsafe { Pin::new_unchecked(&mut this.stream) };
+            match stream.poll_next(cx) {
+                Poll::Ready(Some(item)) => {
+                    this.sub_streams.push(Box::pin((this.f)(item)));
+                    pulled = true;
+                }
+                Poll::Ready(None) => this.done = true,
+                Poll::Pending => {}
+            }
+        }
+
+        let mut polled = 0;
+        while polled < this.sub_streams.len() {
+            let index = (this.next + polled) % this.sub_streams.len();
+            match this.sub_streams[index].as_mut().poll_next(cx) {
+                Poll::Ready(Some(item)) => {
+                    this.next = index + 1;
+                    return Poll::Ready(Some(item));
+                }
+                Poll::Ready(None) => {
+                    // Polling continues with whichever sub-stream took its
+                    // place.
+                    this.sub_streams.swap_remove(index);
+                }
+                Poll::Pending => polled += 1,
+            }
+        }
+
+        if this.done && this.sub_streams.is_empty() {
+            return Poll::Ready(None);
+        }
+        if pulled {
+            // `stream` may have more ready, and nothing else would wake us
+            // to pull it.
+            cx.waker().wake_by_ref();
+        }
+        Poll::Pending
+    }
+}
+
+impl<S: fmt::Debug, F, U> fmt::Debug for FlatMapUnordered<S, F, U> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("FlatMapUnordered")
+            .field("stream", &self.stream)
+            .field("sub_streams", &self.sub_streams.len())
+            .finish()
+    }
+}
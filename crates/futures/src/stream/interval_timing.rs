@@ -0,0 +1,49 @@
+use super::Stream;
+use crate::queue::performance_now;
+use std::pin::Pin;
+use std::task::{Context, Poll};
+
+/// Yields each item of `stream` along with the milliseconds elapsed since
+/// the item before it, or 0 for the first item.
+///
+/// Times are taken with `performance.now()` as each item comes out of
+/// `stream`, which helps tell bursty event sources from steady ones when
+/// profiling. The delays of the consumer count too, so this measures the
+/// items' cadence as seen by whoever polls the stream.
+pub fn with_interval_timing<S>(stream: S) -> WithIntervalTiming<S>
+where
+    S: Stream,
+{
+    WithIntervalTiming {
+        stream,
+        last_at: None,
+    }
+}
+
+/// Stream returned by `with_interval_timing`.
+#[derive(Debug)]
+pub struct WithIntervalTiming<S> {
+    stream: S,
+    // When the previous item was yielded
+    last_at: Option<f64>,
+}
+
+impl<S: Stream> Stream for WithIntervalTiming<S> {
+    type Item = (S::Item, f64);
+
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context) -> Poll<Option<Self::Item>> {
+        // Safety: `stream` is structurally pinned and never moved out of `self`.
+        let this = unsafe { self.get_unchecked_mut() };
+
+        let stream = unsafe { Pin::new_unchecked(&mut this.stream) };
+        let item = match stream.poll_next(cx) {
+            Poll::Ready(Some(item)) => item,
+            Poll::Ready(None) => return Poll::Ready(None),
+            Poll::Pending => return Poll::Pending,
+        };
+        let now = performance_now();
+        let elapsed = this.last_at.map_or(0.0, |last_at| now - last_at);
+        this.last_at = Some(now);
+        Poll::Ready(Some((item, elapsed)))
+    }
+}
@@ -0,0 +1,56 @@
+use super::Stream;
+use std::pin::Pin;
+use std::task::{Context, Poll};
+
+/// Yields only the most recent item of `stream` each time it's polled,
+/// dropping the ones produced since the last poll.
+///
+/// This suits high-frequency sources such as pointer or sensor events,
+/// where only the latest value matters to a consumer that can't keep up.
+/// Unlike `dedup` it drops items by recency rather than by equality, and
+/// unlike a buffer it never holds more than one item. The stream ends once
+/// `stream` has ended and its last item has been yielded.
+///
+/// `stream` is drained eagerly on every poll, so it should be one which
+/// becomes pending once it runs out of items, such as `events`.
+pub fn latest<S>(stream: S) -> Latest<S>
+where
+    S: Stream,
+{
+    Latest {
+        stream,
+        done: false,
+    }
+}
+
+/// Stream returned by `latest`.
+#[derive(Debug)]
+pub struct Latest<S> {
+    stream: S,
+    done: bool,
+}
+
+impl<S: Stream> Stream for Latest<S> {
+    type Item = S::Item;
+
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context) -> Poll<Option<S::Item>> {
+        // Safety: `stream` is structurally pinned and never moved out of `self`.
+        let this = unsafe { self.get_unchecked_mut() };
+
+        let mut latest = None;
+        while !this.done {
+            let stream = unsafe { Pin::new_unchecked(&mut this.stream) };
+            match stream.poll_next(cx) {
+                Poll::Ready(Some(item)) => latest = Some(item),
+                Poll::Ready(None) => this.done = true,
+                Poll::Pending => break,
+            }
+        }
+
+        match latest {
+            Some(item) => Poll::Ready(Some(item)),
+            None if this.done => Poll::Ready(None),
+            None => Poll::Pending,
+        }
+    }
+}
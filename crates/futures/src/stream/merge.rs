@@ -0,0 +1,80 @@
+use super::Stream;
+use std::pin::Pin;
+use std::task::{Context, Poll};
+
+/// Merges two streams of the same item type into one, yielding items from
+/// whichever stream produces them first.
+///
+/// The stream polled first alternates with every item, so a busy stream
+/// can't starve the other one. The merged stream ends once both streams
+/// have ended.
+pub fn merge<A, B>(a: A, b: B) -> Merge<A, B>
+where
+    A: Stream,
+    B: Stream<Item = A::Item>,
+{
+    Merge {
+        a,
+        b,
+        a_done: false,
+        b_done: false,
+        b_first: false,
+    }
+}
+
+/// Stream returned by `merge`.
+#[derive(Debug)]
+pub struct Merge<A, B> {
+    a: A,
+    b: B,
+    a_done: bool,
+    b_done: bool,
+    b_first: bool,
+}
+
+/// Polls one half of a merge, recording when it ends.
+fn poll_half<S: Stream>(stream: Pin<&mut S>, done: &mut bool, cx: &mut Context) -> Option<S::Item> {
+    if *done {
+        return None;
+    }
+    match stream.poll_next(cx) {
+        Poll::Ready(Some(item)) => Some(item),
+        Poll::Ready(None) => {
+            *done = true;
+            None
+        }
+        Poll::Pending => None,
+    }
+}
+
+impl<A, B> Stream for Merge<A, B>
+where
+    A: Stream,
+    B: Stream<Item = A::Item>,
+{
+    type Item = A::Item;
+
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context) -> Poll<Option<A::Item>> {
+        // Safety: `a` and `b` are structurally pinned and never moved out of
+        // `self`.
+        let this = unsafe { self.get_unchecked_mut() };
+        let a = unsafe { Pin::new_unchecked(&mut this.a) };
+        let b = unsafe { Pin::new_unchecked(&mut this.b) };
+        let (a_done, b_done) = (&mut this.a_done, &mut this.b_done);
+
+        let b_first = this.b_first;
+        this.b_first = !b_first;
+
+        let item = if b_first {
+            poll_half(b, b_done, cx).or_else(|| poll_half(a, a_done, cx))
+        } else {
+            poll_half(a, a_done, cx).or_else(|| poll_half(b, b_done, cx))
+        };
+
+        match item {
+            Some(item) => Poll::Ready(Some(item)),
+            None if this.a_done && this.b_done => Poll::Ready(None),
+            None => Poll::Pending,
+        }
+    }
+}
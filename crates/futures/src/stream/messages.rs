@@ -0,0 +1,113 @@
+use super::channel::{channel, OverflowPolicy, Overflowed, Receiver};
+use super::Stream;
+use js_sys::Function;
+use std::fmt;
+use std::pin::Pin;
+use std::task::{Context, Poll};
+use wasm_bindgen::prelude::*;
+use wasm_bindgen::JsCast;
+
+/// An object messages are exchanged with through `postMessage` and
+/// `onmessage`, like a `MessagePort` or a `Worker`.
+pub trait MessageTarget {
+    /// Sets the object's `onmessage` handler, or clears it with `None`.
+    fn set_message_handler(&self, handler: Option<&Function>);
+
+    /// Posts `message` to the object, which structured-clones it.
+    fn post(&self, message: &JsValue) -> Result<(), JsValue>;
+}
+
+impl MessageTarget for web_sys::MessagePort {
+    fn set_message_handler(&self, handler: Option<&Function>) {
+        self.set_onmessage(handler);
+    }
+
+    fn post(&self, message: &JsValue) -> Result<(), JsValue> {
+        self.post_message(message)
+    }
+}
+
+impl MessageTarget for web_sys::Worker {
+    fn set_message_handler(&self, handler: Option<&Function>) {
+        self.set_onmessage(handler);
+    }
+
+    fn post(&self, message: &JsValue) -> Result<(), JsValue> {
+        self.post_message(message)
+    }
+}
+
+/// Creates a stream of the data of the messages received by `target`,
+/// buffering up to `capacity` of them while the consumer lags behind.
+///
+/// This takes over `target`'s `onmessage` handler until the stream is
+/// dropped, which clears it. Setting the handler also starts a
+/// `MessagePort`'s queue of messages. Once the buffer is full `policy`
+/// decides which message gets dropped, as with `channel`, and the stream
+/// never ends on its own. `MessageStream::send` posts messages the other
+/// way.
+///
+/// # Panics
+///
+/// Panics if `capacity` is zero.
+pub fn messages<T: MessageTarget + Clone>(
+    target: &T,
+    capacity: usize,
+    policy: OverflowPolicy,
+) -> MessageStream<T> {
+    let (sender, receiver) = channel(capacity, policy);
+    let handler = Closure::wrap(Box::new(move |event: JsValue| {
+        let event = event.unchecked_into::<web_sys::MessageEvent>();
+        let _ = sender.send(event.data());
+    }) as Box<dyn FnMut(JsValue)>);
+
+    target.set_message_handler(Some(handler.as_ref().unchecked_ref()));
+
+    MessageStream {
+        receiver,
+        target: target.clone(),
+        _handler: handler,
+    }
+}
+
+/// Stream returned by `messages`.
+pub struct MessageStream<T: MessageTarget> {
+    receiver: Receiver<JsValue>,
+    target: T,
+    // Kept alive until `target`'s handler is cleared
+    _handler: Closure<dyn FnMut(JsValue)>,
+}
+
+impl<T: MessageTarget> MessageStream<T> {
+    /// Posts `message` to the target, which passes it on to whatever is on
+    /// the other end, such as the port entangled with a `MessagePort` or
+    /// the worker's global scope.
+    ///
+    /// Returns the exception `postMessage` threw, if any, such as when
+    /// `message` can't be cloned.
+    pub fn send<M: Into<JsValue>>(&self, message: M) -> Result<(), JsValue> {
+        self.target.post(&message.into())
+    }
+}
+
+impl<T: MessageTarget> Stream for MessageStream<T> {
+    type Item = Result<JsValue, Overflowed>;
+
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context) -> Poll<Option<Self::Item>> {
+        // Safety: nothing in `self` is structurally pinned.
+        let this = unsafe { self.get_unchecked_mut() };
+        Pin::new(&mut this.receiver).poll_next(cx)
+    }
+}
+
+impl<T: MessageTarget> Drop for MessageStream<T> {
+    fn drop(&mut self) {
+        self.target.set_message_handler(None);
+    }
+}
+
+impl<T: MessageTarget> fmt::Debug for MessageStream<T> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "MessageStream {{ ... }}")
+    }
+}
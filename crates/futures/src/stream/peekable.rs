@@ -0,0 +1,117 @@
+use super::Stream;
+use std::fmt;
+use std::future::Future;
+use std::pin::Pin;
+use std::task::{Context, Poll};
+
+/// Wraps `stream` so that its next item can be looked at with `peek` before
+/// it's yielded.
+///
+/// A peeked item is buffered until the stream is next polled, which yields
+/// it as usual, so peeking never loses or reorders items.
+pub fn peekable<S>(stream: S) -> Peekable<S>
+where
+    S: Stream,
+{
+    Peekable {
+        stream,
+        peeked: None,
+        done: false,
+    }
+}
+
+/// Stream returned by `peekable`.
+#[derive(Debug)]
+pub struct Peekable<S: Stream> {
+    stream: S,
+    peeked: Option<S::Item>,
+    done: bool,
+}
+
+impl<S: Stream> Peekable<S> {
+    /// Returns a future which resolves to a reference to the next item,
+    /// without consuming it, or `None` if the stream has ended.
+    pub fn peek(&mut self) -> Peek<'_, S>
+    where
+        S: Unpin,
+    {
+        Peek {
+            peekable: Some(self),
+        }
+    }
+
+    /// Polls for a reference to the next item without consuming it, as with
+    /// `peek`, for streams which aren't `Unpin`.
+    pub fn poll_peek(self: Pin<&mut Self>, cx: &mut Context) -> Poll<Option<&S::Item>> {
+        // Safety: `stream` is structurally pinned and never moved out of
+        // `self`, while the peeked item is never pinned.
+        let this = unsafe { self.get_unchecked_mut() };
+        if this.fill(cx).is_pending() {
+            return Poll::Pending;
+        }
+        Poll::Ready(this.peeked.as_ref())
+    }
+
+    /// Makes sure the next item is buffered, unless the stream has ended.
+    fn fill(&mut self, cx: &mut Context) -> Poll<()> {
+        if self.peeked.is_some() || self.done {
+            return Poll::Ready(());
+        }
+
+        // Safety: `self` is only ever reached through a `Pin` or is `Unpin`.
+        let stream = unsafe { Pin::new_unchecked(&mut self.stream) };
+        match stream.poll_next(cx) {
+            Poll::Ready(Some(item)) => self.peeked = Some(item),
+            Poll::Ready(None) => self.done = true,
+            Poll::Pending => return Poll::Pending,
+        }
+        Poll::Ready(())
+    }
+}
+
+impl<S: Stream> Stream for Peekable<S> {
+    type Item = S::Item;
+
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context) -> Poll<Option<S::Item>> {
+        // Safety: as for `poll_peek`.
+        let this = unsafe { self.get_unchecked_mut() };
+        if this.fill(cx).is_pending() {
+            return Poll::Pending;
+        }
+        Poll::Ready(this.peeked.take())
+    }
+}
+
+/// Future returned by `Peekable::peek`.
+pub struct Peek<'a, S: Stream> {
+    // Taken once the future resolves, as the reference it resolves to
+    // borrows from it
+    peekable: Option<&'a mut Peekable<S>>,
+}
+
+impl<'a, S: Stream + Unpin> Future for Peek<'a, S> {
+    type Output = Option<&'a S::Item>;
+
+    fn poll(mut self: Pin<&mut Self>, cx: &mut Context) -> Poll<Self::Output> {
+        let peekable = self
+            .peekable
+            .take()
+            .expect("`Peek` polled after it resolved");
+        if peekable.fill(cx).is_pending() {
+            self.peekable = Some(peekable);
+            return Poll::Pending;
+        }
+        Poll::Ready(peekable.peeked.as_ref())
+    }
+}
+
+impl<S: Stream + fmt::Debug> fmt::Debug for Peek<'_, S>
+where
+    S::Item: fmt::Debug,
+{
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("Peek")
+            .field("peekable", &self.peekable)
+            .finish()
+    }
+}
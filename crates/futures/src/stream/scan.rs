@@ -0,0 +1,69 @@
+use super::Stream;
+use std::fmt;
+use std::pin::Pin;
+use std::task::{Context, Poll};
+
+/// Threads a state through the items of `stream`, starting from `init`.
+///
+/// `f` is called with the state and each item in turn, and can update the
+/// state in place before returning `Some` with the item to yield, or `None`
+/// to end the stream there, dropping `stream` without pulling from it
+/// again. This suits running totals, deduplication, and state machines
+/// over event streams.
+pub fn scan<S, St, F, U>(stream: S, init: St, f: F) -> Scan<S, St, F>
+where
+    S: Stream,
+    F: FnMut(&mut St, S::Item) -> Option<U>,
+{
+    Scan {
+        stream: Some(stream),
+        state: init,
+        f,
+    }
+}
+
+/// Stream returned by `scan`.
+pub struct Scan<S, St, F> {
+    // `None` once `f` has ended the stream
+    stream: Option<S>,
+    state: St,
+    f: F,
+}
+
+impl<S, St, F, U> Stream for Scan<S, St, F>
+where
+    S: Stream,
+    F: FnMut(&mut St, S::Item) -> Option<U>,
+{
+    type Item = U;
+
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context) -> Poll<Option<U>> {
+        // Safety: `stream` is structurally pinned, and is only ever dropped
+        // in place by overwriting it. The state isn't pinned.
+        let this = unsafe { self.get_unchecked_mut() };
+
+        let stream = match this.stream.as_mut() {
+            Some(stream) => unsafe { Pin::new_unchecked(stream) },
+            None => return Poll::Ready(None),
+        };
+        let item = match stream.poll_next(cx) {
+            Poll::Ready(Some(item)) => item,
+            Poll::Ready(None) => return Poll::Ready(None),
+            Poll::Pending => return Poll::Pending,
+        };
+        let output = (this.f)(&mut this.state, item);
+        if output.is_none() {
+            this.stream = None;
+        }
+        Poll::Ready(output)
+    }
+}
+
+impl<S: fmt::Debug, St: fmt::Debug, F> fmt::Debug for Scan<S, St, F> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("Scan")
+            .field("stream", &self.stream)
+            .field("state", &self.state)
+            .finish()
+    }
+}
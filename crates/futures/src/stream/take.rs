@@ -0,0 +1,138 @@
+use super::Stream;
+use std::pin::Pin;
+use std::task::{Context, Poll};
+
+/// Yields the first `n` items of `stream`, then ends.
+///
+/// `stream` is dropped as soon as the `n`th item is yielded, without being
+/// pulled from again, so it's fine for it to never end on its own.
+pub fn take<S>(stream: S, n: usize) -> Take<S>
+where
+    S: Stream,
+{
+    Take {
+        stream: if n > 0 { Some(stream) } else { None },
+        remaining: n,
+    }
+}
+
+/// Stream returned by `take`.
+#[derive(Debug)]
+pub struct Take<S> {
+    // `None` once `n` items have been yielded
+    stream: Option<S>,
+    remaining: usize,
+}
+
+impl<S: Stream> Stream for Take<S> {
+    type Item = S::Item;
+
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context) -> Poll<Option<S::Item>> {
+        // Safety: `stream` is structurally pinned, and is only ever dropped
+        // in place by overwriting it.
+        let this = unsafe { self.get_unchecked_mut() };
+
+        let stream = match this.stream.as_mut() {
+            Some(stream) => unsafe { Pin::new_unchecked(stream) },
+            None => return Poll::Ready(None),
+        };
+        let item = match stream.poll_next(cx) {
+            Poll::Ready(Some(item)) => item,
+            Poll::Ready(None) => {
+                this.stream = None;
+                return Poll::Ready(None);
+            }
+            Poll::Pending => return Poll::Pending,
+        };
+        this.remaining -= 1;
+        if this.remaining == 0 {
+            this.stream = None;
+        }
+        Poll::Ready(Some(item))
+    }
+}
+
+/// Drops the first `n` items of `stream` and yields the rest.
+pub fn skip<S>(stream: S, n: usize) -> Skip<S>
+where
+    S: Stream,
+{
+    Skip {
+        stream,
+        remaining: n,
+    }
+}
+
+/// Stream returned by `skip`.
+#[derive(Debug)]
+pub struct Skip<S> {
+    stream: S,
+    remaining: usize,
+}
+
+impl<S: Stream> Stream for Skip<S> {
+    type Item = S::Item;
+
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context) -> Poll<Option<S::Item>> {
+        // Safety: `stream` is structurally pinned and never moved out of `self`.
+        let this = unsafe { self.get_unchecked_mut() };
+
+        loop {
+            let stream = unsafe { Pin::new_unchecked(&mut this.stream) };
+            match stream.poll_next(cx) {
+                Poll::Ready(Some(_)) if this.remaining > 0 => this.remaining -= 1,
+                other => return other,
+            }
+        }
+    }
+}
+
+/// Yields the first item of `stream` and then every `n`th one after it.
+///
+/// The items in between are still pulled from `stream`, and dropped.
+///
+/// # Panics
+///
+/// Panics if `n` is zero.
+pub fn step_by<S>(stream: S, n: usize) -> StepBy<S>
+where
+    S: Stream,
+{
+    assert!(n > 0, "step_by needs a step of at least one");
+
+    StepBy {
+        stream,
+        step: n,
+        skip: 0,
+    }
+}
+
+/// Stream returned by `step_by`.
+#[derive(Debug)]
+pub struct StepBy<S> {
+    stream: S,
+    step: usize,
+    // How many more items to drop before yielding the next one
+    skip: usize,
+}
+
+impl<S: Stream> Stream for StepBy<S> {
+    type Item = S::Item;
+
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context) -> Poll<Option<S::Item>> {
+        // Safety: `stream` is structurally pinned and never moved out of `self`.
+        let this = unsafe { self.get_unchecked_mut() };
+
+        loop {
+            let stream = unsafe { Pin::new_unchecked(&mut this.stream) };
+            match stream.poll_next(cx) {
+                Poll::Ready(Some(_)) if this.skip > 0 => this.skip -= 1,
+                Poll::Ready(Some(item)) => {
+                    this.skip = this.step - 1;
+                    return Poll::Ready(Some(item));
+                }
+                other => return other,
+            }
+        }
+    }
+}
@@ -0,0 +1,168 @@
+use super::channel::{OverflowPolicy, Overflowed};
+use super::Stream;
+use crate::queue::performance_now;
+use crate::{sleep, Sleep};
+use std::collections::VecDeque;
+use std::fmt;
+use std::future::Future;
+use std::pin::Pin;
+use std::task::{Context, Poll};
+
+/// Rate limits `stream` to at most one item every `min_interval_ms`
+/// milliseconds, buffering up to `capacity` of the items it produces in the
+/// meantime.
+///
+/// The first item is yielded as soon as it's ready. Items produced while
+/// waiting for the interval to pass are buffered, and once the buffer is
+/// full `policy` decides which item gets dropped, as with `channel`: a
+/// capacity of 1 with `OverflowPolicy::DropOldest` always yields the most
+/// recent item, which suits scroll and resize events. The stream ends once
+/// `stream` has ended and the buffered items have been yielded.
+///
+/// `stream` is drained eagerly while waiting, so it should be one which
+/// becomes pending once it runs out of items, such as `events`. The timer
+/// used to wait is cleared if the stream is dropped.
+///
+/// # Panics
+///
+/// Panics if `capacity` is zero.
+pub fn throttle<S>(
+    stream: S,
+    min_interval_ms: i32,
+    capacity: usize,
+    policy: OverflowPolicy,
+) -> Throttle<S>
+where
+    S: Stream,
+{
+    assert!(capacity > 0, "a throttle needs room for at least one item");
+
+    Throttle {
+        stream,
+        done: false,
+        items: VecDeque::new(),
+        capacity,
+        policy,
+        overflow_after: None,
+        min_interval_ms: min_interval_ms.max(0),
+        next_at: None,
+        timer: None,
+    }
+}
+
+/// Stream returned by `throttle`.
+pub struct Throttle<S: Stream> {
+    stream: S,
+    done: bool,
+    items: VecDeque<S::Item>,
+    capacity: usize,
+    policy: OverflowPolicy,
+
+    // With `OverflowPolicy::Error`, the number of buffered items to yield
+    // before reporting that items were dropped
+    overflow_after: Option<usize>,
+
+    min_interval_ms: i32,
+    // The earliest `performance.now()` at which the next item may be
+    // yielded, if one was yielded before
+    next_at: Option<f64>,
+    // Wakes the stream once `next_at` has passed
+    timer: Option<Sleep>,
+}
+
+impl<S: Stream> Throttle<S> {
+    fn buffer(&mut self, item: S::Item) {
+        if self.items.len() < self.capacity {
+            self.items.push_back(item);
+            return;
+        }
+        match self.policy {
+            OverflowPolicy::DropOldest => {
+                self.items.pop_front();
+                self.items.push_back(item);
+            }
+            OverflowPolicy::DropNewest => {}
+            OverflowPolicy::Error => {
+                if self.overflow_after.is_none() {
+                    self.overflow_after = Some(self.items.len());
+                }
+            }
+        }
+    }
+
+    /// Whether the interval since the last item has passed, arranging to be
+    /// woken once it does if not.
+    fn interval_passed(&mut self, cx: &mut Context) -> bool {
+        let next_at = match self.next_at {
+            Some(next_at) => next_at,
+            None => return true,
+        };
+
+        let remaining = next_at - performance_now();
+        if remaining <= 0.0 {
+            self.timer = None;
+            return true;
+        }
+
+        let timer = self
+            .timer
+            .get_or_insert_with(|| sleep(remaining.ceil() as i32));
+        if Pin::new(timer).poll(cx).is_ready() {
+            // Timers don't fire early, even if `performance.now()` has yet
+            // to catch up.
+            self.timer = None;
+            return true;
+        }
+        false
+    }
+}
+
+impl<S: Stream> Stream for Throttle<S> {
+    type Item = Result<S::Item, Overflowed>;
+
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context) -> Poll<Option<Self::Item>> {
+        // Safety: `stream` is structurally pinned and never moved out of
+        // `self`, while the buffered items and the timer are never pinned.
+        let this = unsafe { self.get_unchecked_mut() };
+
+        loop {
+            if this.overflow_after == Some(0) {
+                this.overflow_after = None;
+                return Poll::Ready(Some(Err(Overflowed)));
+            }
+
+            if !this.items.is_empty() && this.interval_passed(cx) {
+                let item = this.items.pop_front().unwrap();
+                if let Some(remaining) = this.overflow_after.as_mut() {
+                    *remaining -= 1;
+                }
+                this.next_at = Some(performance_now() + f64::from(this.min_interval_ms));
+                return Poll::Ready(Some(Ok(item)));
+            }
+
+            if this.done {
+                return if this.items.is_empty() {
+                    Poll::Ready(None)
+                } else {
+                    Poll::Pending
+                };
+            }
+
+            let stream = unsafe { Pin::new_unchecked(&mut this.stream) };
+            match stream.poll_next(cx) {
+                Poll::Ready(Some(item)) => this.buffer(item),
+                Poll::Ready(None) => this.done = true,
+                Poll::Pending => return Poll::Pending,
+            }
+        }
+    }
+}
+
+impl<S: Stream + fmt::Debug> fmt::Debug for Throttle<S> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("Throttle")
+            .field("stream", &self.stream)
+            .field("buffered", &self.items.len())
+            .finish()
+    }
+}
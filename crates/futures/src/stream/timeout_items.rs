@@ -0,0 +1,89 @@
+use super::Stream;
+use crate::{sleep, Sleep};
+use std::error::Error;
+use std::fmt;
+use std::future::Future;
+use std::pin::Pin;
+use std::task::{Context, Poll};
+
+/// Yields the items of `stream`, ending it with an `Err(Timeout)` if no item
+/// arrives within `per_item_ms` milliseconds.
+///
+/// The deadline starts once the stream is first waited on and restarts with
+/// every item, so it bounds the gap between items rather than the stream as
+/// a whole, which suits heartbeats such as those of server-sent events. After
+/// the timeout the stream ends without polling `stream` again.
+///
+/// The timer is cleared whenever an item arrives and when the stream is
+/// dropped.
+pub fn timeout_items<S>(stream: S, per_item_ms: i32) -> TimeoutItems<S>
+where
+    S: Stream,
+{
+    TimeoutItems {
+        stream,
+        per_item_ms,
+        timer: None,
+        done: false,
+    }
+}
+
+/// Error item yielded by `timeout_items` when the gap between items exceeds
+/// its deadline.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Timeout;
+
+impl fmt::Display for Timeout {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str("no item arrived before the deadline")
+    }
+}
+
+impl Error for Timeout {}
+
+/// Stream returned by `timeout_items`.
+#[derive(Debug)]
+pub struct TimeoutItems<S> {
+    stream: S,
+    per_item_ms: i32,
+    // Started when waiting for an item, and dropped once one arrives
+    timer: Option<Sleep>,
+    done: bool,
+}
+
+impl<S: Stream> Stream for TimeoutItems<S> {
+    type Item = Result<S::Item, Timeout>;
+
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context) -> Poll<Option<Self::Item>> {
+        // Safety: `stream` is structurally pinned and never moved out of
+        // `self`, while the timer is never pinned.
+        let this = unsafe { self.get_unchecked_mut() };
+
+        if this.done {
+            return Poll::Ready(None);
+        }
+
+        let stream = unsafe { Pin::new_unchecked(&mut this.stream) };
+        match stream.poll_next(cx) {
+            Poll::Ready(Some(item)) => {
+                this.timer = None;
+                return Poll::Ready(Some(Ok(item)));
+            }
+            Poll::Ready(None) => {
+                this.timer = None;
+                this.done = true;
+                return Poll::Ready(None);
+            }
+            Poll::Pending => {}
+        }
+
+        let per_item_ms = this.per_item_ms;
+        let timer = this.timer.get_or_insert_with(|| sleep(per_item_ms));
+        if Pin::new(timer).poll(cx).is_ready() {
+            this.timer = None;
+            this.done = true;
+            return Poll::Ready(Some(Err(Timeout)));
+        }
+        Poll::Pending
+    }
+}
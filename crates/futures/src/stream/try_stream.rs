@@ -0,0 +1,180 @@
+use super::Stream;
+use std::cell::RefCell;
+use std::fmt;
+use std::future::Future;
+use std::pin::Pin;
+use std::rc::Rc;
+use std::task::{Context, Poll};
+
+/// Creates a fallible stream from an async body, which is handed a
+/// `Yielder` to produce items with.
+///
+/// The stream runs the future returned by `f`. Each
+/// `yielder.yield_item(item).await` suspends it and makes the stream yield
+/// `Ok(item)`. The stream ends once the future completes, and if it
+/// completes with an error that's yielded as a final `Err` item. This is
+/// what the `try_stream!` macro expands to. The macro is usually more
+/// convenient, since with `?` the body can stop at the first error.
+///
+/// Dropping the stream drops the body wherever it's suspended.
+pub fn try_stream<T, E, F, Fut>(f: F) -> AsyncTryStream<T, Fut>
+where
+    F: FnOnce(Yielder<T>) -> Fut,
+    Fut: Future<Output = Result<(), E>>,
+{
+    let slot = Rc::new(RefCell::new(None));
+    let future = f(Yielder {
+        slot: Rc::clone(&slot),
+    });
+    AsyncTryStream {
+        future: Some(future),
+        slot,
+    }
+}
+
+/// Builds a fallible stream from a block of async code, in which
+/// `yielder.yield_item(item).await` yields an item, and `?` ends the stream
+/// with an `Err` item.
+///
+/// The block names the `Yielder` it's given before a `=>`, and it runs
+/// until its last statement. The stream then ends:
+///
+/// ```no_run
+/// use js_sys::Promise;
+/// use wasm_bindgen::JsValue;
+/// use wasm_bindgen_futures::{stream::Stream, try_stream, JsFuture};
+///
+/// fn settled(promises: Vec<Promise>) -> impl Stream<Item = Result<JsValue, JsValue>> {
+///     try_stream!(yielder => {
+///         for promise in promises {
+///             let value = JsFuture::from(promise).await?;
+///             yielder.yield_item(value).await;
+///         }
+///     })
+/// }
+/// ```
+///
+/// The error type can usually be inferred from how the stream is used, and
+/// otherwise needs to be annotated on the stream's type. See
+/// `stream::try_stream` for the function behind this.
+#[macro_export]
+macro_rules! try_stream {
+    ($yielder:ident => { $($body:tt)* }) => {
+        $crate::stream::try_stream(move |$yielder| async move {
+            { $($body)* }
+            #[allow(unreachable_code)]
+            Ok(())
+        })
+    };
+}
+
+/// Produces the items of a `try_stream`, given to its body.
+pub struct Yielder<T> {
+    slot: Rc<RefCell<Option<T>>>,
+}
+
+impl<T> Yielder<T> {
+    /// Yields `item` from the stream, returning a future which resolves once
+    /// the stream is polled for the item after it.
+    pub fn yield_item(&self, item: T) -> YieldItem<'_, T> {
+        YieldItem {
+            yielder: self,
+            item: Some(item),
+        }
+    }
+}
+
+impl<T> fmt::Debug for Yielder<T> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "Yielder {{ ... }}")
+    }
+}
+
+/// Future returned by `Yielder::yield_item`.
+pub struct YieldItem<'a, T> {
+    yielder: &'a Yielder<T>,
+    // `None` once handed over to the stream
+    item: Option<T>,
+}
+
+impl<T> Unpin for YieldItem<'_, T> {}
+
+impl<T> fmt::Debug for YieldItem<'_, T> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "YieldItem {{ ... }}")
+    }
+}
+
+impl<T> Future for YieldItem<'_, T> {
+    type Output = ();
+
+    fn poll(mut self: Pin<&mut Self>, _cx: &mut Context) -> Poll<()> {
+        let item = match self.item.take() {
+            Some(item) => item,
+            // The stream only polls the body again once it took the item.
+            None => return Poll::Ready(()),
+        };
+
+        let mut slot = self.yielder.slot.borrow_mut();
+        if slot.is_some() {
+            // Another item is waiting to be yielded first. There's no need
+            // for a wakeup, as the stream polls the body again right after
+            // it yields that one.
+            drop(slot);
+            self.item = Some(item);
+            return Poll::Pending;
+        }
+
+        // Suspend the body without a wakeup. The stream yields the item
+        // right away, and polls the body again for the next one.
+        *slot = Some(item);
+        Poll::Pending
+    }
+}
+
+/// Stream returned by `try_stream` and the `try_stream!` macro.
+pub struct AsyncTryStream<T, Fut> {
+    // `None` once the body has completed
+    future: Option<Fut>,
+    slot: Rc<RefCell<Option<T>>>,
+}
+
+impl<T, Fut> fmt::Debug for AsyncTryStream<T, Fut> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "AsyncTryStream {{ ... }}")
+    }
+}
+
+impl<T, E, Fut> Stream for AsyncTryStream<T, Fut>
+where
+    Fut: Future<Output = Result<(), E>>,
+{
+    type Item = Result<T, E>;
+
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context) -> Poll<Option<Self::Item>> {
+        // Safety: `future` is structurally pinned, and is only ever dropped
+        // in place by overwriting it with `None`.
+        let this = unsafe { self.get_unchecked_mut() };
+
+        let future = match this.future.as_mut() {
+            Some(future) => unsafe { Pin::new_unchecked(future) },
+            None => return Poll::Ready(None),
+        };
+        let result = match future.poll(cx) {
+            Poll::Ready(result) => result,
+            Poll::Pending => {
+                let item = this.slot.borrow_mut().take();
+                return match item {
+                    Some(item) => Poll::Ready(Some(Ok(item))),
+                    None => Poll::Pending,
+                };
+            }
+        };
+
+        this.future = None;
+        match result {
+            Ok(()) => Poll::Ready(None),
+            Err(error) => Poll::Ready(Some(Err(error))),
+        }
+    }
+}
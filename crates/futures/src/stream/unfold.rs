@@ -0,0 +1,86 @@
+use super::Stream;
+use std::fmt;
+use std::future::Future;
+use std::mem;
+use std::pin::Pin;
+use std::task::{Context, Poll};
+
+/// Creates a stream by repeatedly awaiting `f` with the current state,
+/// starting from `init`.
+///
+/// Each step resolves to `Some((item, next_state))` to yield `item` and
+/// carry on with `next_state`, or `None` to end the stream. This fits
+/// paginated JS APIs well: the state is the cursor of the next page, and a
+/// step fetches that page and returns the cursor after it.
+///
+/// Only one step is in flight at a time. Dropping the stream drops the step
+/// it's waiting on, if any.
+pub fn unfold<T, F, Fut, Item>(init: T, f: F) -> Unfold<T, F, Fut>
+where
+    F: FnMut(T) -> Fut,
+    Fut: Future<Output = Option<(Item, T)>>,
+{
+    Unfold {
+        f,
+        state: State::Value(init),
+    }
+}
+
+enum State<T, Fut> {
+    // Waiting to start the next step with this state
+    Value(T),
+    // Waiting on a step
+    Step(Fut),
+    Done,
+}
+
+/// Stream returned by `unfold`.
+pub struct Unfold<T, F, Fut> {
+    f: F,
+    state: State<T, Fut>,
+}
+
+impl<T, F, Fut> fmt::Debug for Unfold<T, F, Fut> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "Unfold {{ ... }}")
+    }
+}
+
+impl<T, F, Fut, Item> Stream for Unfold<T, F, Fut>
+where
+    F: FnMut(T) -> Fut,
+    Fut: Future<Output = Option<(Item, T)>>,
+{
+    type Item = Item;
+
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context) -> Poll<Option<Item>> {
+        // Safety: a step in `state` is structurally pinned, and is only ever
+        // dropped in place by overwriting `state`. Only a `State::Value` is
+        // moved out.
+        let this = unsafe { self.get_unchecked_mut() };
+
+        if let State::Value(_) = this.state {
+            let value = match mem::replace(&mut this.state, State::Done) {
+                State::Value(value) => value,
+                _ => unreachable!(),
+            };
+            this.state = State::Step((this.f)(value));
+        }
+
+        let step = match &mut this.state {
+            State::Step(step) => unsafe { Pin::new_unchecked(step) },
+            _ => return Poll::Ready(None),
+        };
+        match step.poll(cx) {
+            Poll::Ready(Some((item, next))) => {
+                this.state = State::Value(next);
+                Poll::Ready(Some(item))
+            }
+            Poll::Ready(None) => {
+                this.state = State::Done;
+                Poll::Ready(None)
+            }
+            Poll::Pending => Poll::Pending,
+        }
+    }
+}
@@ -0,0 +1,80 @@
+use super::Stream;
+use std::pin::Pin;
+use std::task::{Context, Poll};
+
+/// Pairs up the items of two streams, yielding a pair once each stream has
+/// produced its next item.
+///
+/// Whichever stream is ahead has its item held until the other catches up.
+/// The zipped stream ends as soon as either stream ends, dropping an item
+/// the other one was holding.
+pub fn zip<A, B>(a: A, b: B) -> Zip<A, B>
+where
+    A: Stream,
+    B: Stream,
+{
+    Zip {
+        a,
+        b,
+        a_item: None,
+        b_item: None,
+        done: false,
+    }
+}
+
+/// Stream returned by `zip`.
+#[derive(Debug)]
+pub struct Zip<A: Stream, B: Stream> {
+    a: A,
+    b: B,
+    a_item: Option<A::Item>,
+    b_item: Option<B::Item>,
+    done: bool,
+}
+
+/// Polls one half of a zip unless it's already holding an item, returning
+/// whether it ended.
+fn poll_half<S: Stream>(stream: Pin<&mut S>, item: &mut Option<S::Item>, cx: &mut Context) -> bool {
+    if item.is_some() {
+        return false;
+    }
+    match stream.poll_next(cx) {
+        Poll::Ready(Some(next)) => {
+            *item = Some(next);
+            false
+        }
+        Poll::Ready(None) => true,
+        Poll::Pending => false,
+    }
+}
+
+impl<A: Stream, B: Stream> Stream for Zip<A, B> {
+    type Item = (A::Item, B::Item);
+
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context) -> Poll<Option<Self::Item>> {
+        // Safety: `a` and `b` are structurally pinned and never moved out of
+        // `self`, while the held items are never pinned.
+        let this = unsafe { self.get_unchecked_mut() };
+        if this.done {
+            return Poll::Ready(None);
+        }
+
+        let a = unsafe { Pin::new_unchecked(&mut this.a) };
+        let b = unsafe { Pin::new_unchecked(&mut this.b) };
+        if poll_half(a, &mut this.a_item, cx) || poll_half(b, &mut this.b_item, cx) {
+            this.done = true;
+            this.a_item = None;
+            this.b_item = None;
+            return Poll::Ready(None);
+        }
+
+        match (this.a_item.take(), this.b_item.take()) {
+            (Some(a), Some(b)) => Poll::Ready(Some((a, b))),
+            (a, b) => {
+                this.a_item = a;
+                this.b_item = b;
+                Poll::Pending
+            }
+        }
+    }
+}
@@ -0,0 +1,183 @@
+use std::cell::{Cell, Ref, RefCell, RefMut};
+use std::fmt;
+use std::ops::{Deref, DerefMut};
+use std::panic::Location;
+
+// A borrow taken with `strict_borrow` or `strict_borrow_mut` while strict
+// mode was on
+struct Borrow {
+    id: u64,
+    // The task being polled when the borrow was taken, if any
+    task_id: Option<u64>,
+    location: &'static Location<'static>,
+    reported: bool,
+}
+
+thread_local! {
+    static STRICT: Cell<bool> = Cell::new(false);
+    static BORROWS: RefCell<Vec<Borrow>> = RefCell::new(Vec::new());
+    static NEXT_BORROW_ID: Cell<u64> = Cell::new(0);
+}
+
+/// Turns strict mode on or off for the executor on the current thread.
+///
+/// Holding a `RefCell` borrow across an await point is a classic source of
+/// `BorrowMutError` panics, which then surface in whichever task tries to
+/// borrow the cell next rather than the one at fault. The type system can't
+/// catch it, so strict mode checks for it at runtime instead: borrows taken
+/// with `strict_borrow` and `strict_borrow_mut` are tracked, and each one
+/// still held when the poll of the task which took it returns is reported,
+/// to the queue observer (see `set_queue_observer`) or as a warning if there
+/// is none. Each borrow is only reported once.
+///
+/// This is a debugging aid, so borrows are only tracked in debug builds. In
+/// release builds this does nothing.
+pub fn set_strict_mode(enabled: bool) {
+    STRICT.with(|strict| strict.set(enabled));
+}
+
+fn is_strict() -> bool {
+    cfg!(debug_assertions) && STRICT.with(Cell::get)
+}
+
+/// Immutably borrows `cell` like `RefCell::borrow`, tracking the borrow in
+/// strict mode (see `set_strict_mode`).
+///
+/// # Panics
+///
+/// Panics if `cell` is currently mutably borrowed.
+#[track_caller]
+pub fn strict_borrow<T: ?Sized>(cell: &RefCell<T>) -> StrictRef<'_, T> {
+    StrictRef {
+        inner: cell.borrow(),
+        _tracker: Tracker::new(Location::caller()),
+    }
+}
+
+/// Mutably borrows `cell` like `RefCell::borrow_mut`, tracking the borrow
+/// in strict mode (see `set_strict_mode`).
+///
+/// # Panics
+///
+/// Panics if `cell` is currently borrowed.
+#[track_caller]
+pub fn strict_borrow_mut<T: ?Sized>(cell: &RefCell<T>) -> StrictRefMut<'_, T> {
+    StrictRefMut {
+        inner: cell.borrow_mut(),
+        _tracker: Tracker::new(Location::caller()),
+    }
+}
+
+/// Reports the borrows taken by the task `task_id` which it's still holding
+/// now that its poll has returned.
+pub(crate) fn check_held_borrows(task_id: u64) {
+    if !is_strict() {
+        return;
+    }
+
+    // Collect the borrows first, so none of the state is borrowed while the
+    // observer runs.
+    let held = BORROWS.with(|borrows| {
+        let mut borrows = borrows.borrow_mut();
+        borrows
+            .iter_mut()
+            .filter(|borrow| borrow.task_id == Some(task_id) && !borrow.reported)
+            .map(|borrow| {
+                borrow.reported = true;
+                borrow.location
+            })
+            .collect::<Vec<_>>()
+    });
+
+    for location in held {
+        let notified = crate::observer::notify(|observer| {
+            observer.borrow_held_across_await(task_id, location)
+        });
+        if !notified {
+            log::warn!(
+                "task {} is holding the borrow taken at {} across an await",
+                task_id,
+                location
+            );
+        }
+    }
+}
+
+// Registers a borrow while strict mode is on, and forgets it when dropped
+struct Tracker(Option<u64>);
+
+impl Tracker {
+    fn new(location: &'static Location<'static>) -> Self {
+        if !is_strict() {
+            return Tracker(None);
+        }
+
+        let id = NEXT_BORROW_ID.with(|next| next.replace(next.get().wrapping_add(1)));
+        let task_id = crate::queue::QUEUE
+            .with(|queue| queue.current_task())
+            .map(|task| task.id());
+        BORROWS.with(|borrows| {
+            borrows.borrow_mut().push(Borrow {
+                id,
+                task_id,
+                location,
+                reported: false,
+            })
+        });
+        Tracker(Some(id))
+    }
+}
+
+impl Drop for Tracker {
+    fn drop(&mut self) {
+        if let Some(id) = self.0 {
+            BORROWS.with(|borrows| borrows.borrow_mut().retain(|borrow| borrow.id != id));
+        }
+    }
+}
+
+/// Borrow returned by `strict_borrow`.
+pub struct StrictRef<'a, T: ?Sized> {
+    inner: Ref<'a, T>,
+    _tracker: Tracker,
+}
+
+impl<T: ?Sized> Deref for StrictRef<'_, T> {
+    type Target = T;
+
+    fn deref(&self) -> &T {
+        &self.inner
+    }
+}
+
+impl<T: ?Sized + fmt::Debug> fmt::Debug for StrictRef<'_, T> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        (**self).fmt(f)
+    }
+}
+
+/// Borrow returned by `strict_borrow_mut`.
+pub struct StrictRefMut<'a, T: ?Sized> {
+    inner: RefMut<'a, T>,
+    _tracker: Tracker,
+}
+
+impl<T: ?Sized> Deref for StrictRefMut<'_, T> {
+    type Target = T;
+
+    fn deref(&self) -> &T {
+        &self.inner
+    }
+}
+
+impl<T: ?Sized> DerefMut for StrictRefMut<'_, T> {
+    fn deref_mut(&mut self) -> &mut T {
+        &mut self.inner
+    }
+}
+
+impl<T: ?Sized + fmt::Debug> fmt::Debug for StrictRefMut<'_, T> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        (**self).fmt(f)
+    }
+}
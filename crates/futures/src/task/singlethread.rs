@@ -3,7 +3,12 @@ use std::future::Future;
 use std::mem::ManuallyDrop;
 use std::pin::Pin;
 use std::rc::Rc;
-use std::task::{Context, Poll, RawWaker, RawWakerVTable, Waker};
+use std::task::{Context, RawWaker, RawWakerVTable, Waker};
+use wasm_bindgen::JsValue;
+
+use crate::queue::{ExecutorError, WakeMode};
+
+type OnPanic = Box<dyn FnOnce(JsValue)>;
 
 struct Inner {
     future: Pin<Box<dyn Future<Output = ()> + 'static>>,
@@ -11,7 +16,14 @@ struct Inner {
 }
 
 pub(crate) struct Task {
+    // Unique (per thread) identifier used by diagnostics
+    id: u64,
     high_priority: bool,
+    // The lane of `spawn_local_lane`, if it was spawned with one
+    lane: Cell<Option<u32>>,
+    // Orders normal priority tasks when the `task-weights` feature is on
+    #[cfg(feature = "task-weights")]
+    weight: Cell<u32>,
     // The actual Future that we're executing as part of this task.
     //
     // This is an Option so that the Future can be immediately dropped when it's
@@ -20,38 +32,239 @@ pub(crate) struct Task {
 
     // This is used to ensure that the Task will only be queued once
     is_queued: Cell<bool>,
+    // The executor's tick count when the task was last queued
+    queued_at_tick: Cell<u64>,
+
+    // Whether a normal priority task is queued right away when woken, or
+    // on a macrotask
+    wake_mode: Cell<WakeMode>,
+
+    // Set by `boost_current_task_next_wake`, to queue the task with high
+    // priority the next time it's woken
+    boosted: Cell<bool>,
+
+    // Set by `abort_current_task`, to drop the future once it's done polling
+    aborted: Cell<bool>,
+
+    // Whether `shutdown` runs this task rather than dropping it
+    runs_on_shutdown: Cell<bool>,
+
+    // The values of `task_local!` keys, dropped along with the future
+    locals: crate::task_local::Locals,
+
+    // Invoked with the thrown value if polling the future ever throws
+    on_panic: Cell<Option<OnPanic>>,
 }
 
 impl Task {
     pub(crate) fn spawn(future: Pin<Box<dyn Future<Output = ()> + 'static>>, high_priority: bool) {
+        if !Task::admit() {
+            return;
+        }
+        Task::wake_by_ref(&Task::spawn_inner(future, high_priority, None));
+    }
+
+    /// Same as `spawn`, but the normal priority task takes turns with the
+    /// other lanes' tasks as part of `lane`.
+    pub(crate) fn spawn_in_lane(future: Pin<Box<dyn Future<Output = ()> + 'static>>, lane: u32) {
+        if !Task::admit() {
+            return;
+        }
+        let this = Task::spawn_inner(future, false, None);
+        this.lane.set(Some(lane));
+        Task::wake_by_ref(&this);
+    }
+
+    /// Same as `spawn`, but `shutdown` runs the task if it's still queued
+    /// instead of dropping it.
+    pub(crate) fn spawn_on_shutdown(future: Pin<Box<dyn Future<Output = ()> + 'static>>) {
+        if !Task::admit() {
+            return;
+        }
+        let this = Task::spawn_inner(future, false, None);
+        this.runs_on_shutdown.set(true);
+        Task::wake_by_ref(&this);
+    }
+
+    /// Same as `spawn`, but the task is ordered by `weight` among normal
+    /// priority tasks.
+    #[cfg(feature = "task-weights")]
+    pub(crate) fn spawn_weighted(future: Pin<Box<dyn Future<Output = ()> + 'static>>, weight: u32) {
+        if !Task::admit() {
+            return;
+        }
+        let this = Task::spawn_inner(future, false, None);
+        this.weight.set(weight);
+        Task::wake_by_ref(&this);
+    }
+
+    /// Same as `spawn`, but returns an error instead of throwing if the task
+    /// couldn't be scheduled, in which case it's spawned anyway, or if the
+    /// queue is full, in which case it isn't.
+    pub(crate) fn try_spawn(
+        future: Pin<Box<dyn Future<Output = ()> + 'static>>,
+        high_priority: bool,
+    ) -> Result<(), ExecutorError> {
+        crate::queue::QUEUE.with(|queue| queue.admit())?;
+        Task::try_wake_by_ref(&Task::spawn_inner(future, high_priority, None))
+    }
+
+    /// Same as `spawn`, but `on_panic` is called if polling the future throws,
+    /// which is how a panicking future surfaces in wasm.
+    pub(crate) fn spawn_catching(
+        future: Pin<Box<dyn Future<Output = ()> + 'static>>,
+        high_priority: bool,
+        on_panic: OnPanic,
+    ) {
+        // Settle whatever is waiting on the future rather than leave it
+        // hanging.
+        if let Err(error) = crate::queue::QUEUE.with(|queue| queue.admit()) {
+            on_panic(error.into());
+            return;
+        }
+        Task::wake_by_ref(&Task::spawn_inner(future, high_priority, Some(on_panic)));
+    }
+
+    /// Checks whether there's room in the queue for a new task, warning
+    /// that the task is dropped if not.
+    fn admit() -> bool {
+        match crate::queue::QUEUE.with(|queue| queue.admit()) {
+            Ok(()) => true,
+            Err(error) => {
+                log::warn!("dropping a spawned future: {}", error);
+                false
+            }
+        }
+    }
+
+    fn spawn_inner(
+        future: Pin<Box<dyn Future<Output = ()> + 'static>>,
+        high_priority: bool,
+        on_panic: Option<OnPanic>,
+    ) -> Rc<Self> {
+        let id = NEXT_TASK_ID.with(|next| next.replace(next.get().wrapping_add(1)));
+        let future = crate::apply_spawn_middleware(future);
+
+        #[cfg(feature = "tracing")]
+        tracing::trace!(task.id = id, high_priority, "spawned task");
+
         let this = Rc::new(Self {
+            id,
             high_priority,
+            lane: Cell::new(None),
+            #[cfg(feature = "task-weights")]
+            weight: Cell::new(0),
             inner: RefCell::new(None),
             is_queued: Cell::new(false),
+            queued_at_tick: Cell::new(0),
+            wake_mode: Cell::new(WakeMode::Microtask),
+            boosted: Cell::new(false),
+            aborted: Cell::new(false),
+            runs_on_shutdown: Cell::new(false),
+            locals: crate::task_local::Locals::new(),
+            on_panic: Cell::new(on_panic),
         });
 
         let waker = unsafe { Waker::from_raw(Task::into_raw_waker(Rc::clone(&this))) };
 
         *this.inner.borrow_mut() = Some(Inner { future, waker });
+        this
+    }
 
-        Task::wake_by_ref(&this);
+    /// Creates a waker which queues this task, like the one it's polled
+    /// with.
+    pub(crate) fn waker(this: &Rc<Self>) -> Waker {
+        unsafe { Waker::from_raw(Task::into_raw_waker(Rc::clone(this))) }
+    }
+
+    pub(crate) fn id(&self) -> u64 {
+        self.id
+    }
+
+    pub(crate) fn locals(&self) -> &crate::task_local::Locals {
+        &self.locals
+    }
+
+    pub(crate) fn lane(&self) -> Option<u32> {
+        self.lane.get()
+    }
+
+    #[cfg(feature = "task-weights")]
+    pub(crate) fn weight(&self) -> u32 {
+        self.weight.get()
+    }
+
+    pub(crate) fn is_high_priority(&self) -> bool {
+        self.high_priority
+    }
+
+    pub(crate) fn queued_at_tick(&self) -> u64 {
+        self.queued_at_tick.get()
+    }
+
+    pub(crate) fn set_queued_at_tick(&self, tick: u64) {
+        self.queued_at_tick.set(tick);
+    }
+
+    pub(crate) fn runs_on_shutdown(&self) -> bool {
+        self.runs_on_shutdown.get()
+    }
+
+    pub(crate) fn set_wake_mode(&self, mode: WakeMode) {
+        self.wake_mode.set(mode);
+    }
+
+    /// Queues the task with high priority the next time it's woken.
+    pub(crate) fn boost_next_wake(&self) {
+        self.boosted.set(true);
+    }
+
+    /// Marks the task to be dropped as soon as its current poll returns,
+    /// instead of ever being polled again.
+    pub(crate) fn abort(&self) {
+        self.aborted.set(true);
+    }
+
+    /// Drops the future without polling it again, ignoring wakeups from
+    /// then on.
+    pub(crate) fn discard(&self) {
+        self.is_queued.set(true);
+
+        // The future is dropped outside of the borrow in case its destructor
+        // wakes this task.
+        let inner = match self.inner.try_borrow_mut() {
+            Ok(mut borrow) => borrow.take(),
+            Err(_) => None,
+        };
+        drop(inner);
+        self.locals.clear();
     }
 
     fn wake_by_ref(this: &Rc<Self>) {
+        // Wakers have no way to report errors, so throw it like any other
+        // JS exception.
+        if let Err(error) = Task::try_wake_by_ref(this) {
+            wasm_bindgen::throw_val(error.into());
+        }
+    }
+
+    fn try_wake_by_ref(this: &Rc<Self>) -> Result<(), ExecutorError> {
         // If we've already been placed on the run queue then there's no need to
         // requeue ourselves since we're going to run at some point in the
         // future anyway.
         if this.is_queued.replace(true) {
-            return;
+            return Ok(());
         }
 
         crate::queue::QUEUE.with(|queue| {
-            if this.high_priority {
-                queue.push_high_priority_task(Rc::clone(this));
+            if this.high_priority || this.boosted.replace(false) {
+                queue.push_high_priority_task(Rc::clone(this))
+            } else if this.wake_mode.get() == WakeMode::Macrotask {
+                queue.push_task_on_macrotask(Rc::clone(this))
             } else {
-                queue.push_task(Rc::clone(this));
+                queue.push_task(Rc::clone(this))
             }
-        });
+        })
     }
 
     /// Creates a standard library `RawWaker` from an `Rc` of ourselves.
@@ -88,7 +301,12 @@ impl Task {
     }
 
     pub(crate) fn run(&self) {
-        let mut borrow = self.inner.borrow_mut();
+        // A task whose poll threw never released this borrow. Its future is in
+        // an unknown state, so it must never be polled again.
+        let mut borrow = match self.inner.try_borrow_mut() {
+            Ok(borrow) => borrow,
+            Err(_) => return,
+        };
 
         // Wakeups can come in after a Future has finished and been destroyed,
         // so handle this gracefully by just ignoring the request to run.
@@ -112,8 +330,35 @@ impl Task {
         // actually go away until all wakers referencing us go away, which may
         // take quite some time, so ensure that the heaviest of resources are
         // released early.
-        if let Poll::Ready(_) = poll {
-            *borrow = None;
+        //
+        // The future is dropped after releasing the borrow, so that its
+        // destructors are free to spawn or wake tasks, this one included.
+        // An aborted future is dropped the same way, even though it's
+        // pending.
+        if poll.is_ready() || self.aborted.get() {
+            let finished = borrow.take();
+            drop(borrow);
+            drop(finished);
+            self.locals.clear();
         }
     }
+
+    /// Called by the queue when polling this task threw, with the thrown
+    /// value (or the panic message, if one was captured).
+    pub(crate) fn poison(&self, error: JsValue) {
+        // Leave ourselves flagged as queued so wakeups are ignored from now on.
+        self.is_queued.set(true);
+
+        match self.on_panic.take() {
+            Some(on_panic) => on_panic(error),
+            // Nobody is waiting to hear about it, so let it reach the host
+            // as it would if the executor hadn't caught it.
+            None => crate::queue::rethrow_later(error),
+        }
+        self.locals.clear();
+    }
+}
+
+thread_local! {
+    static NEXT_TASK_ID: Cell<u64> = Cell::new(1);
 }
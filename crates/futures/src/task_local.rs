@@ -0,0 +1,145 @@
+use std::any::Any;
+use std::cell::RefCell;
+use std::error::Error;
+use std::fmt;
+use std::marker::PhantomData;
+use std::rc::Rc;
+
+/// Declares task-local values, which every task on the current thread has
+/// its own copy of.
+///
+/// Each declaration creates a `static` `TaskLocalKey`. A task's value is
+/// created with `Default::default()` the first time the task accesses it,
+/// keeps its state across the task's await points, and is dropped once the
+/// task finishes. Values are mutated through interior mutability, as with
+/// `thread_local!`:
+///
+/// ```no_run
+/// use std::cell::Cell;
+/// use wasm_bindgen_futures::{spawn_local, task_local};
+///
+/// task_local! {
+///     static TRACE_ID: Cell<u32>;
+/// }
+///
+/// spawn_local(async {
+///     TRACE_ID.with(|id| id.set(7));
+///     // ... awaits ...
+///     assert_eq!(TRACE_ID.with(|id| id.get()), 7);
+/// });
+/// ```
+#[macro_export]
+macro_rules! task_local {
+    () => {};
+    ($(#[$attr:meta])* $vis:vis static $name:ident: $t:ty; $($rest:tt)*) => {
+        $(#[$attr])*
+        $vis static $name: $crate::TaskLocalKey<$t> = $crate::TaskLocalKey::__new();
+        $crate::task_local!($($rest)*);
+    };
+}
+
+/// A key for task-local values, declared with `task_local!`.
+///
+/// Tasks don't inherit values from the task which spawned them, so context
+/// such as a trace id is propagated by reading it before spawning and
+/// setting it again in the spawned task.
+pub struct TaskLocalKey<T> {
+    // Keeps keys from being zero sized, so that every key's `static` has an
+    // address of its own to identify it by
+    _unique: u8,
+    _marker: PhantomData<fn() -> T>,
+}
+
+impl<T> TaskLocalKey<T> {
+    #[doc(hidden)]
+    pub const fn __new() -> Self {
+        TaskLocalKey {
+            _unique: 0,
+            _marker: PhantomData,
+        }
+    }
+}
+
+impl<T: Default + 'static> TaskLocalKey<T> {
+    /// Calls `f` with the current task's value, creating it first if it
+    /// doesn't exist yet.
+    ///
+    /// # Panics
+    ///
+    /// Panics if called outside of a task.
+    pub fn with<R>(&'static self, f: impl FnOnce(&T) -> R) -> R {
+        self.try_with(f)
+            .expect("task-local values can only be accessed from within a task")
+    }
+
+    /// Like `with`, but returns an error instead of panicking if called
+    /// outside of a task.
+    pub fn try_with<R>(&'static self, f: impl FnOnce(&T) -> R) -> Result<R, AccessError> {
+        let task = crate::queue::QUEUE
+            .with(|queue| queue.current_task())
+            .ok_or(AccessError)?;
+        let value = task.locals().get_or_insert(self as *const Self as usize);
+        Ok(f(&value))
+    }
+}
+
+impl<T> fmt::Debug for TaskLocalKey<T> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "TaskLocalKey {{ ... }}")
+    }
+}
+
+/// Error returned by `TaskLocalKey::try_with` when called outside of a
+/// task.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct AccessError;
+
+impl fmt::Display for AccessError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str("task-local values can only be accessed from within a task")
+    }
+}
+
+impl Error for AccessError {}
+
+/// The task-local values of a task, by the address of their key.
+pub(crate) struct Locals {
+    values: RefCell<Vec<(usize, Rc<dyn Any>)>>,
+}
+
+impl Locals {
+    pub(crate) fn new() -> Self {
+        Locals {
+            values: RefCell::new(Vec::new()),
+        }
+    }
+
+    fn get_or_insert<T: Default + 'static>(&self, key: usize) -> Rc<T> {
+        let existing = self
+            .values
+            .borrow()
+            .iter()
+            .find(|(k, _)| *k == key)
+            .map(|(_, value)| Rc::clone(value));
+
+        let value = match existing {
+            Some(value) => value,
+            None => {
+                // Created without the values borrowed, as `T::default` may
+                // well access other task-local values.
+                let value: Rc<dyn Any> = Rc::new(T::default());
+                self.values.borrow_mut().push((key, Rc::clone(&value)));
+                value
+            }
+        };
+        value
+            .downcast()
+            .unwrap_or_else(|_| unreachable!("a task-local key holds a single type"))
+    }
+
+    /// Drops every value, once the task is done.
+    pub(crate) fn clear(&self) {
+        let values = self.values.replace(Vec::new());
+        drop(values);
+    }
+}
@@ -0,0 +1,538 @@
+use crate::join::JoinHandle;
+use crate::queue::{find_timer_function, performance_now};
+use std::cell::{Cell, RefCell};
+use std::fmt;
+use std::future::Future;
+use std::pin::Pin;
+use std::rc::Rc;
+use std::task::{Context, Poll, Waker};
+use wasm_bindgen::prelude::*;
+use wasm_bindgen::JsCast;
+
+struct State {
+    // `Some(true)` once the timer fired, `Some(false)` once it was cancelled
+    outcome: Cell<Option<bool>>,
+    // The id `setTimeout` returned, until the timer fires or is cleared
+    timeout_id: RefCell<Option<JsValue>>,
+    waker: RefCell<Option<Waker>>,
+}
+
+impl State {
+    fn settle(&self, fired: bool) {
+        if self.outcome.get().is_some() {
+            return;
+        }
+        self.outcome.set(Some(fired));
+
+        let timeout_id = self.timeout_id.borrow_mut().take();
+        if let (false, Some(timeout_id)) = (fired, timeout_id) {
+            if let Some((scope, clear_timeout)) = find_timer_function("clearTimeout") {
+                let _ = clear_timeout.call1(&scope, &timeout_id);
+            }
+        }
+
+        let waker = self.waker.borrow_mut().take();
+        if let Some(waker) = waker {
+            waker.wake();
+        }
+    }
+}
+
+/// Returns a future which resolves after `ms` milliseconds, using the
+/// window's or global scope's `setTimeout`.
+///
+/// Dropping the future before then clears the timer.
+///
+/// # Panics
+///
+/// Panics if the host has no `setTimeout`.
+pub fn sleep(ms: i32) -> Sleep {
+    Sleep {
+        inner: sleep_handle(ms),
+    }
+}
+
+/// Future returned by `sleep`.
+#[derive(Debug)]
+pub struct Sleep {
+    inner: CancelableSleep,
+}
+
+impl Future for Sleep {
+    type Output = ();
+
+    fn poll(mut self: Pin<&mut Self>, cx: &mut Context) -> Poll<()> {
+        Pin::new(&mut self.inner).poll(cx).map(drop)
+    }
+}
+
+/// Like `sleep`, but returns a future which can also be cancelled, which
+/// resolves to `true` if the timer fired or `false` if it was cancelled
+/// first.
+///
+/// Cancelling clears the timer with `clearTimeout`. This is a shorthand for
+/// the common "time out unless cancelled" pattern, without racing the sleep
+/// against a `CancellationToken`.
+///
+/// # Panics
+///
+/// Panics if the host has no `setTimeout`.
+pub fn sleep_handle(ms: i32) -> CancelableSleep {
+    let state = Rc::new(State {
+        outcome: Cell::new(None),
+        timeout_id: RefCell::new(None),
+        waker: RefCell::new(None),
+    });
+
+    let on_timeout = {
+        let state = Rc::clone(&state);
+        Closure::wrap(Box::new(move || state.settle(true)) as Box<dyn FnMut()>)
+    };
+
+    let (scope, set_timeout) =
+        find_timer_function("setTimeout").expect_throw("no `setTimeout` to sleep with");
+    let timeout_id = set_timeout
+        .call2(&scope, on_timeout.as_ref(), &JsValue::from(ms))
+        .unwrap_throw();
+    *state.timeout_id.borrow_mut() = Some(timeout_id);
+
+    CancelableSleep {
+        state,
+        _on_timeout: on_timeout,
+    }
+}
+
+/// Future returned by `sleep_handle`.
+pub struct CancelableSleep {
+    state: Rc<State>,
+    // Kept alive until the timer can no longer fire
+    _on_timeout: Closure<dyn FnMut()>,
+}
+
+impl CancelableSleep {
+    /// Cancels the sleep and clears its timer, so that it resolves to
+    /// `false`. This has no effect once the timer has fired.
+    pub fn cancel(&self) {
+        self.state.settle(false);
+    }
+
+    /// Returns a handle which can cancel this sleep while it's being
+    /// awaited.
+    pub fn canceller(&self) -> SleepCanceller {
+        SleepCanceller {
+            state: Rc::clone(&self.state),
+        }
+    }
+}
+
+impl Future for CancelableSleep {
+    type Output = bool;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context) -> Poll<bool> {
+        if let Some(fired) = self.state.outcome.get() {
+            return Poll::Ready(fired);
+        }
+        drop(self.state.waker.replace(Some(cx.waker().clone())));
+        Poll::Pending
+    }
+}
+
+impl Drop for CancelableSleep {
+    fn drop(&mut self) {
+        // Nothing is waiting on the sleep any more, so forget the waker
+        // rather than waking a task for nothing.
+        drop(self.state.waker.borrow_mut().take());
+
+        // Clear the timer before its callback is dropped.
+        self.state.settle(false);
+    }
+}
+
+impl fmt::Debug for CancelableSleep {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("CancelableSleep")
+            .field("outcome", &self.state.outcome.get())
+            .finish()
+    }
+}
+
+/// A handle returned by `CancelableSleep::canceller`, which cancels the
+/// sleep it came from.
+#[derive(Clone)]
+pub struct SleepCanceller {
+    state: Rc<State>,
+}
+
+impl SleepCanceller {
+    /// Cancels the sleep, as with `CancelableSleep::cancel`.
+    pub fn cancel(&self) {
+        self.state.settle(false);
+    }
+}
+
+impl fmt::Debug for SleepCanceller {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "SleepCanceller {{ ... }}")
+    }
+}
+
+/// Resolves to the output of `future` if it completes within `ms`
+/// milliseconds, or to `default` otherwise.
+///
+/// This suits falling back on a cached value when the network is slow.
+/// `future` is dropped once the deadline passes, and the timer is cleared as
+/// soon as `future` completes or this future is dropped. If both are ready at
+/// once `future` wins.
+///
+/// # Panics
+///
+/// Panics if the host has no `setTimeout`.
+pub fn timeout_or<F: Future>(ms: i32, future: F, default: F::Output) -> TimeoutOr<F> {
+    TimeoutOr {
+        future: Some(future),
+        timer: sleep_handle(ms),
+        default: Some(default),
+    }
+}
+
+/// Future returned by `timeout_or`.
+pub struct TimeoutOr<F: Future> {
+    // `None` once the deadline has passed
+    future: Option<F>,
+    timer: CancelableSleep,
+    // `None` once resolved
+    default: Option<F::Output>,
+}
+
+impl<F: Future> Future for TimeoutOr<F> {
+    type Output = F::Output;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context) -> Poll<F::Output> {
+        // Safety: `future` is structurally pinned, and is only ever dropped
+        // in place by overwriting it. Neither the timer nor the default is
+        // pinned.
+        let this = unsafe { self.get_unchecked_mut() };
+
+        let future = this
+            .future
+            .as_mut()
+            .expect("`TimeoutOr` polled after it resolved");
+        if let Poll::Ready(output) = unsafe { Pin::new_unchecked(future) }.poll(cx) {
+            this.future = None;
+            this.default = None;
+            this.timer.cancel();
+            return Poll::Ready(output);
+        }
+        if Pin::new(&mut this.timer).poll(cx).is_ready() {
+            this.future = None;
+            return Poll::Ready(this.default.take().unwrap());
+        }
+        Poll::Pending
+    }
+}
+
+impl<F: Future> fmt::Debug for TimeoutOr<F> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("TimeoutOr")
+            .field("timer", &self.timer)
+            .finish()
+    }
+}
+
+/// A handle to a task spawned with `spawn_periodic`, which stops it when
+/// dropped.
+#[derive(Debug)]
+#[must_use = "the task stops as soon as the handle is dropped"]
+pub struct PeriodicHandle {
+    task: JoinHandle,
+}
+
+impl PeriodicHandle {
+    pub(crate) fn spawn<F, Fut>(period_ms: i32, mut f: F) -> Self
+    where
+        F: FnMut() -> Fut + 'static,
+        Fut: Future<Output = ()> + 'static,
+    {
+        let task = JoinHandle::spawn(async move {
+            let mut due = performance_now();
+            loop {
+                f().await;
+
+                // Runs are due a period after the last one was due rather
+                // than after it finished, so they don't drift. A run which
+                // overran skips the ones it missed and the next starts now.
+                due = (due + f64::from(period_ms)).max(performance_now());
+                sleep((due - performance_now()).ceil() as i32).await;
+            }
+        });
+        PeriodicHandle { task }
+    }
+
+    /// Stops the task, as with dropping the handle.
+    ///
+    /// A run in progress is dropped along with the task the next time the
+    /// executor gets to it, and no further runs start.
+    pub fn stop(&self) {
+        self.task.abort();
+    }
+
+    /// Returns whether the task has stopped.
+    ///
+    /// As with `JoinHandle::is_finished`, this only turns `true` once the
+    /// executor has dropped the task, some time after `stop`.
+    pub fn is_stopped(&self) -> bool {
+        self.task.is_finished()
+    }
+}
+
+impl Drop for PeriodicHandle {
+    fn drop(&mut self) {
+        self.task.abort();
+    }
+}
+
+// The animation frame `before_next_frame` waits for
+struct Frame {
+    fired: Cell<bool>,
+    // The id `requestAnimationFrame` returned, until the frame fires or is
+    // cancelled
+    request_id: RefCell<Option<JsValue>>,
+    waker: RefCell<Option<Waker>>,
+}
+
+impl Frame {
+    fn cancel(&self) {
+        let request_id = self.request_id.borrow_mut().take();
+        if let Some(request_id) = request_id {
+            if let Some((scope, cancel)) = find_timer_function("cancelAnimationFrame") {
+                let _ = cancel.call1(&scope, &request_id);
+            }
+        }
+    }
+}
+
+/// Races `future` against the next animation frame, resolving to
+/// `Some(output)` if it completes first or `None` once the frame comes.
+///
+/// This lets render-coordinated code opportunistically finish work within
+/// the current frame and otherwise defer it: on `None` the future has been
+/// dropped, and the caller can start it again later. If `future` is ready
+/// at the same time as the frame it still wins. The `requestAnimationFrame`
+/// callback is cancelled as soon as the race is decided, or if this future
+/// is dropped.
+///
+/// In hosts without `requestAnimationFrame`, such as most workers, no frame
+/// ever comes, so this always resolves to `Some`.
+pub fn before_next_frame<F: Future>(future: F) -> BeforeNextFrame<F> {
+    let frame = Rc::new(Frame {
+        fired: Cell::new(false),
+        request_id: RefCell::new(None),
+        waker: RefCell::new(None),
+    });
+
+    let on_frame = {
+        let frame = Rc::clone(&frame);
+        Closure::wrap(Box::new(move |_: JsValue| {
+            frame.fired.set(true);
+            frame.request_id.borrow_mut().take();
+            let waker = frame.waker.borrow_mut().take();
+            if let Some(waker) = waker {
+                waker.wake();
+            }
+        }) as Box<dyn FnMut(JsValue)>)
+    };
+
+    let request_id = find_timer_function("requestAnimationFrame")
+        .and_then(|(scope, request)| request.call1(&scope, on_frame.as_ref()).ok());
+    *frame.request_id.borrow_mut() = request_id;
+
+    BeforeNextFrame {
+        future: Some(future),
+        frame,
+        _on_frame: on_frame,
+    }
+}
+
+/// Future returned by `before_next_frame`.
+pub struct BeforeNextFrame<F> {
+    // `None` once the race is decided
+    future: Option<F>,
+    frame: Rc<Frame>,
+    // Kept alive until the frame can no longer fire
+    _on_frame: Closure<dyn FnMut(JsValue)>,
+}
+
+impl<F: Future> Future for BeforeNextFrame<F> {
+    type Output = Option<F::Output>;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context) -> Poll<Option<F::Output>> {
+        // Safety: `future` is structurally pinned, and is only ever dropped
+        // in place by overwriting it.
+        let this = unsafe { self.get_unchecked_mut() };
+
+        let future = match this.future.as_mut() {
+            Some(future) => unsafe { Pin::new_unchecked(future) },
+            None => return Poll::Ready(None),
+        };
+        if let Poll::Ready(output) = future.poll(cx) {
+            this.future = None;
+            this.frame.cancel();
+            return Poll::Ready(Some(output));
+        }
+        if this.frame.fired.get() {
+            this.future = None;
+            return Poll::Ready(None);
+        }
+        drop(this.frame.waker.replace(Some(cx.waker().clone())));
+        Poll::Pending
+    }
+}
+
+impl<F> Drop for BeforeNextFrame<F> {
+    fn drop(&mut self) {
+        // Cancel the frame before its callback is dropped.
+        self.frame.cancel();
+    }
+}
+
+impl<F> fmt::Debug for BeforeNextFrame<F> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("BeforeNextFrame")
+            .field("frame_fired", &self.frame.fired.get())
+            .finish()
+    }
+}
+
+#[wasm_bindgen]
+extern "C" {
+    type NativeIdleDeadline;
+
+    #[wasm_bindgen(method, js_name = timeRemaining)]
+    fn time_remaining(this: &NativeIdleDeadline) -> f64;
+
+    #[wasm_bindgen(method, getter, js_name = didTimeout)]
+    fn did_timeout(this: &NativeIdleDeadline) -> bool;
+}
+
+// How long an idle period lasts where `requestIdleCallback` is missing, which
+// is the longest one a browser hands out.
+const FALLBACK_IDLE_MS: f64 = 50.0;
+
+/// Returns a future which resolves at the start of the browser's next idle
+/// period, with the deadline of that period.
+///
+/// This uses `requestIdleCallback`, so a task can do chunks of background
+/// work while `IdleDeadline::time_remaining` says the period has time left,
+/// then await the next one:
+///
+/// ```no_run
+/// # async fn example(work: &mut Vec<u32>) {
+/// use wasm_bindgen_futures::next_idle_deadline;
+///
+/// while !work.is_empty() {
+///     let deadline = next_idle_deadline().await;
+///     while deadline.time_remaining() > 0.0 {
+///         match work.pop() {
+///             Some(_item) => { /* process it */ }
+///             None => break,
+///         }
+///     }
+/// }
+/// # }
+/// ```
+///
+/// Where `requestIdleCallback` is missing (as in Safari and workers) a
+/// `setTimeout` of 1 stands in for it, resolving with a deadline 50
+/// milliseconds out which never times out.
+pub fn next_idle_deadline() -> NextIdleDeadline {
+    let native = find_timer_function("requestIdleCallback");
+    let is_native = native.is_some();
+
+    let promise = js_sys::Promise::new(&mut |resolve, _reject| {
+        let _ = match &native {
+            Some((scope, request_idle_callback)) => request_idle_callback.call1(scope, &resolve),
+            None => match find_timer_function("setTimeout") {
+                Some((scope, set_timeout)) => {
+                    set_timeout.call2(&scope, &resolve, &JsValue::from(1))
+                }
+                None => resolve.call0(&JsValue::undefined()),
+            },
+        };
+    });
+
+    NextIdleDeadline {
+        future: crate::JsFuture::from(promise),
+        is_native,
+    }
+}
+
+/// Future returned by `next_idle_deadline`.
+#[derive(Debug)]
+pub struct NextIdleDeadline {
+    future: crate::JsFuture,
+    is_native: bool,
+}
+
+impl Future for NextIdleDeadline {
+    type Output = IdleDeadline;
+
+    fn poll(mut self: Pin<&mut Self>, cx: &mut Context) -> Poll<IdleDeadline> {
+        let result = match Pin::new(&mut self.future).poll(cx) {
+            Poll::Ready(result) => result,
+            Poll::Pending => return Poll::Pending,
+        };
+
+        // Requesting the callback only fails if it throws, in which case
+        // there's nothing better to do than make up the deadline.
+        let inner = match result {
+            Ok(deadline) if self.is_native => Deadline::Native(deadline.unchecked_into()),
+            _ => Deadline::Fallback {
+                ends_at: performance_now() + FALLBACK_IDLE_MS,
+            },
+        };
+        Poll::Ready(IdleDeadline { inner })
+    }
+}
+
+/// The deadline of an idle period, as resolved by `next_idle_deadline`.
+///
+/// This wraps the browser's `IdleDeadline`, or stands in for it where
+/// `requestIdleCallback` is missing.
+pub struct IdleDeadline {
+    inner: Deadline,
+}
+
+enum Deadline {
+    Native(NativeIdleDeadline),
+    // The `performance.now()` at which the made up idle period ends
+    Fallback { ends_at: f64 },
+}
+
+impl IdleDeadline {
+    /// Returns how many milliseconds are left in the idle period, which is
+    /// 0 once it's over.
+    pub fn time_remaining(&self) -> f64 {
+        match &self.inner {
+            Deadline::Native(deadline) => deadline.time_remaining(),
+            Deadline::Fallback { ends_at } => (ends_at - performance_now()).max(0.0),
+        }
+    }
+
+    /// Returns whether the idle period began because its timeout passed
+    /// rather than because the browser was idle.
+    pub fn did_timeout(&self) -> bool {
+        match &self.inner {
+            Deadline::Native(deadline) => deadline.did_timeout(),
+            Deadline::Fallback { .. } => false,
+        }
+    }
+}
+
+impl fmt::Debug for IdleDeadline {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("IdleDeadline")
+            .field("time_remaining", &self.time_remaining())
+            .field("did_timeout", &self.did_timeout())
+            .finish()
+    }
+}
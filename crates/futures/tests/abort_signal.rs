@@ -0,0 +1,123 @@
+#![cfg(target_arch = "wasm32")]
+
+wasm_bindgen_test::wasm_bindgen_test_configure!(run_in_browser);
+
+use wasm_bindgen::prelude::*;
+use wasm_bindgen_futures::{abort_signal_any, abort_signal_timeout, sleep, JsFuture};
+use wasm_bindgen_test::*;
+use web_sys::AbortController;
+
+#[wasm_bindgen(inline_js = "
+    let removed = 0;
+    const removeEventListener = AbortSignal.prototype.removeEventListener;
+
+    // Count the listeners removed from signals, while still removing them.
+    AbortSignal.prototype.removeEventListener = function (...args) {
+        removed += 1;
+        return removeEventListener.apply(this, args);
+    };
+
+    export function removed_listeners() {
+        return removed;
+    }
+
+    // Runs `f` as if `AbortSignal` had no static method `name`.
+    export function without_static(name, f) {
+        const method = AbortSignal[name];
+        AbortSignal[name] = undefined;
+        try {
+            return f();
+        } finally {
+            AbortSignal[name] = method;
+        }
+    }
+
+    export function reason_name(signal) {
+        return signal.reason && signal.reason.name;
+    }
+")]
+extern "C" {
+    fn removed_listeners() -> u32;
+    fn without_static(name: &str, f: &mut dyn FnMut() -> web_sys::AbortSignal)
+        -> web_sys::AbortSignal;
+    fn reason_name(signal: &web_sys::AbortSignal) -> Option<String>;
+}
+
+#[wasm_bindgen_test]
+async fn timeout_signal_aborts_after_its_delay() {
+    let signal = abort_signal_timeout(10);
+    assert!(!signal.aborted());
+
+    sleep(100).await;
+    assert!(signal.aborted());
+}
+
+#[wasm_bindgen_test]
+async fn polyfilled_timeout_signal_aborts_after_its_delay() {
+    let signal = without_static("timeout", &mut || abort_signal_timeout(10));
+    assert!(!signal.aborted());
+
+    sleep(100).await;
+    assert!(signal.aborted());
+    assert_eq!(reason_name(&signal).as_deref(), Some("TimeoutError"));
+}
+
+#[wasm_bindgen_test]
+fn any_signal_aborts_with_any_input() {
+    let a = AbortController::new().unwrap();
+    let b = AbortController::new().unwrap();
+    let signal = abort_signal_any(&[a.signal(), b.signal()]);
+    assert!(!signal.aborted());
+
+    b.abort();
+    assert!(signal.aborted());
+}
+
+#[wasm_bindgen_test]
+fn polyfilled_any_signal_aborts_with_any_input_and_cleans_up() {
+    let a = AbortController::new().unwrap();
+    let b = AbortController::new().unwrap();
+    let inputs = [a.signal(), b.signal()];
+    let signal = without_static("any", &mut || abort_signal_any(&inputs));
+    assert!(!signal.aborted());
+
+    let removed = removed_listeners();
+    b.abort();
+    assert!(signal.aborted());
+
+    // The listener came off both inputs, so aborting the other one is
+    // ignored.
+    assert_eq!(removed_listeners(), removed + 2);
+    a.abort();
+}
+
+#[wasm_bindgen_test]
+fn polyfilled_any_signal_of_an_aborted_input_is_aborted() {
+    let a = AbortController::new().unwrap();
+    a.abort();
+    let inputs = [a.signal()];
+    let signal = without_static("any", &mut || abort_signal_any(&inputs));
+    assert!(signal.aborted());
+}
+
+#[wasm_bindgen_test]
+fn dropping_a_pending_js_future_aborts_its_controller() {
+    let controller = AbortController::new().unwrap();
+    let future = JsFuture::from(js_sys::Promise::new(&mut |_, _| {}))
+        .with_abort_on_drop(controller.clone());
+
+    drop(future);
+    assert!(controller.signal().aborted());
+}
+
+#[wasm_bindgen_test]
+async fn settled_js_futures_leave_their_controller_alone() {
+    let controller = AbortController::new().unwrap();
+    let value = JsFuture::from(js_sys::Promise::resolve(&JsValue::from(1)))
+        .with_abort_on_drop(controller.clone())
+        .await
+        .unwrap();
+
+    assert_eq!(value, JsValue::from(1));
+    assert!(!controller.signal().aborted());
+}
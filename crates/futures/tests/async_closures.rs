@@ -0,0 +1,18 @@
+#![cfg(all(target_arch = "wasm32", feature = "async-closures"))]
+
+wasm_bindgen_test::wasm_bindgen_test_configure!(run_in_browser);
+
+use futures_channel::oneshot;
+use wasm_bindgen_futures::{sleep, spawn_local_async};
+use wasm_bindgen_test::*;
+
+#[wasm_bindgen_test]
+async fn async_closures_can_be_spawned() {
+    let (tx, rx) = oneshot::channel();
+    let label = String::from("done");
+    spawn_local_async(async move || {
+        sleep(1).await;
+        tx.send(label).unwrap();
+    });
+    assert_eq!(rx.await.unwrap(), "done");
+}
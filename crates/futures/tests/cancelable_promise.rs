@@ -0,0 +1,76 @@
+#![cfg(target_arch = "wasm32")]
+
+wasm_bindgen_test::wasm_bindgen_test_configure!(run_in_browser);
+
+use js_sys::Promise;
+use std::cell::Cell;
+use std::rc::Rc;
+use wasm_bindgen::prelude::*;
+use wasm_bindgen_futures::{future_to_cancelable_promise, sleep, JsFuture};
+use wasm_bindgen_test::*;
+
+#[wasm_bindgen(inline_js = "
+    export async function cancel_and_settle(handle) {
+        handle.cancel();
+        handle.cancel();
+        try {
+            return 'resolved with ' + (await handle.promise);
+        } catch (e) {
+            return e.name;
+        }
+    }
+
+    export async function settle(handle) {
+        const value = await handle.promise;
+        handle.cancel();
+        return value;
+    }
+")]
+extern "C" {
+    fn cancel_and_settle(handle: &JsValue) -> Promise;
+    fn settle(handle: &JsValue) -> Promise;
+}
+
+struct SetOnDrop(Rc<Cell<bool>>);
+
+impl Drop for SetOnDrop {
+    fn drop(&mut self) {
+        self.0.set(true);
+    }
+}
+
+#[wasm_bindgen_test]
+async fn cancel_drops_the_future_and_rejects() {
+    let started = Rc::new(Cell::new(false));
+    let dropped = Rc::new(Cell::new(false));
+    let finished = Rc::new(Cell::new(false));
+
+    let handle = {
+        let started = started.clone();
+        let guard = SetOnDrop(dropped.clone());
+        let finished = finished.clone();
+        future_to_cancelable_promise(async move {
+            let _guard = guard;
+            started.set(true);
+            sleep(60_000).await;
+            finished.set(true);
+            Ok(JsValue::from("done"))
+        })
+    };
+
+    sleep(0).await;
+    assert!(started.get());
+    assert!(!dropped.get());
+
+    let outcome = JsFuture::from(cancel_and_settle(&handle)).await.unwrap();
+    assert_eq!(outcome, "AbortError");
+    assert!(dropped.get());
+    assert!(!finished.get());
+}
+
+#[wasm_bindgen_test]
+async fn cancel_after_settling_does_nothing() {
+    let handle = future_to_cancelable_promise(async { Ok(JsValue::from(42)) });
+    let value = JsFuture::from(settle(&handle)).await.unwrap();
+    assert_eq!(value, 42);
+}
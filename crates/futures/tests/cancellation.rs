@@ -0,0 +1,34 @@
+#![cfg(target_arch = "wasm32")]
+
+wasm_bindgen_test::wasm_bindgen_test_configure!(run_in_browser);
+
+use std::future::Future;
+use std::pin::Pin;
+use std::sync::Arc;
+use std::task::{Context, Wake, Waker};
+use wasm_bindgen_futures::CancellationToken;
+use wasm_bindgen_test::*;
+
+struct NoopWake;
+
+impl Wake for NoopWake {
+    fn wake(self: Arc<Self>) {}
+}
+
+#[wasm_bindgen_test]
+fn dropped_waiters_release_their_wakers() {
+    let token = CancellationToken::new();
+    let wake = Arc::new(NoopWake);
+    let waker = Waker::from(wake.clone());
+    let mut cx = Context::from_waker(&waker);
+
+    for _ in 0..3 {
+        let mut cancelled = token.cancelled();
+        assert!(Pin::new(&mut cancelled).poll(&mut cx).is_pending());
+        assert!(Pin::new(&mut cancelled).poll(&mut cx).is_pending());
+    }
+
+    drop(waker);
+    assert_eq!(Arc::strong_count(&wake), 1);
+    assert!(!token.is_cancelled());
+}
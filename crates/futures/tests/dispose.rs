@@ -0,0 +1,54 @@
+#![cfg(target_arch = "wasm32")]
+
+wasm_bindgen_test::wasm_bindgen_test_configure!(run_in_browser);
+
+use js_sys::{Object, Promise};
+use std::cell::Cell;
+use std::rc::Rc;
+use wasm_bindgen::prelude::*;
+use wasm_bindgen_futures::{async_disposable, disposable, sleep, JsFuture};
+use wasm_bindgen_test::*;
+
+#[wasm_bindgen(inline_js = "
+    export function dispose(resource) {
+        resource[Symbol.dispose || Symbol.for('Symbol.dispose')]();
+    }
+
+    export function async_dispose(resource) {
+        return resource[Symbol.asyncDispose || Symbol.for('Symbol.asyncDispose')]();
+    }
+")]
+extern "C" {
+    fn dispose(resource: &Object);
+    fn async_dispose(resource: &Object) -> Promise;
+}
+
+#[wasm_bindgen_test]
+fn dispose_runs_the_cleanup() {
+    let cleaned_up = Rc::new(Cell::new(false));
+    let resource = {
+        let cleaned_up = cleaned_up.clone();
+        disposable(move || cleaned_up.set(true))
+    };
+    assert!(!cleaned_up.get());
+
+    dispose(&resource);
+    assert!(cleaned_up.get());
+}
+
+#[wasm_bindgen_test]
+async fn async_dispose_resolves_once_the_cleanup_finishes() {
+    let cleaned_up = Rc::new(Cell::new(false));
+    let resource = {
+        let cleaned_up = cleaned_up.clone();
+        async_disposable(move || async move {
+            sleep(1).await;
+            cleaned_up.set(true);
+        })
+    };
+
+    let disposed = async_dispose(&resource);
+    assert!(!cleaned_up.get());
+    JsFuture::from(disposed).await.unwrap();
+    assert!(cleaned_up.get());
+}
@@ -0,0 +1,168 @@
+#![cfg(target_arch = "wasm32")]
+
+wasm_bindgen_test::wasm_bindgen_test_configure!(run_in_browser);
+
+use futures_channel::oneshot;
+use wasm_bindgen::prelude::*;
+use js_sys::Function;
+use wasm_bindgen_futures::{
+    active_macrotask_scheduler, set_custom_scheduler, spawn_local, try_spawn_local,
+    ExecutorError, QueueBuilder, SchedulerKind,
+};
+use wasm_bindgen_test::*;
+
+// Yield after every task, and resume with a timer rather than `postMessage`
+// so that removing `setTimeout` breaks the executor.
+#[wasm_bindgen(start)]
+pub fn configure_queue() {
+    QueueBuilder::new()
+        .coop_budget(1)
+        .reschedule_delay_ms(1)
+        .install()
+        .unwrap_throw();
+}
+
+#[wasm_bindgen(inline_js = "
+    let saved = {};
+
+    export function hide_global(name) {
+        saved[name] = globalThis[name];
+        globalThis[name] = undefined;
+    }
+
+    export function make_global_throw(name, value) {
+        saved[name] = globalThis[name];
+        globalThis[name] = () => { throw value; };
+    }
+
+    export function restore_global(name) {
+        globalThis[name] = saved[name];
+    }
+
+    export function saved_global(name) {
+        return saved[name];
+    }
+")]
+extern "C" {
+    fn hide_global(name: &str);
+    fn make_global_throw(name: &str, value: &JsValue);
+    fn restore_global(name: &str);
+    fn saved_global(name: &str) -> Function;
+}
+
+#[wasm_bindgen]
+extern "C" {
+    fn setTimeout(c: &Closure<dyn FnMut()>);
+}
+
+/// Runs `f` from a fresh macrotask, outside of any executor tick.
+fn run_in_macrotask(f: impl FnOnce() + 'static) {
+    let mut f = Some(f);
+    let closure = Closure::wrap(Box::new(move || f.take().unwrap()()) as Box<dyn FnMut()>);
+    setTimeout(&closure);
+    closure.forget();
+}
+
+#[wasm_bindgen_test]
+async fn throwing_scheduler_is_scheduling_threw() {
+    let (result_tx, result_rx) = oneshot::channel();
+    let (ran_tx, ran_rx) = oneshot::channel();
+
+    run_in_macrotask(move || {
+        make_global_throw("queueMicrotask", &JsValue::from("no microtasks"));
+        let result = try_spawn_local(async move {
+            ran_tx.send(()).unwrap();
+        });
+        restore_global("queueMicrotask");
+        result_tx.send(result).unwrap();
+
+        // The task stays queued and runs with the next tick.
+        try_spawn_local(async {}).unwrap();
+    });
+
+    assert_eq!(
+        result_rx.await.unwrap(),
+        Err(ExecutorError::SchedulingThrew(JsValue::from("no microtasks")))
+    );
+    ran_rx.await.unwrap();
+}
+
+#[wasm_bindgen_test]
+async fn missing_set_timeout_is_no_scheduler() {
+    let (result_tx, result_rx) = oneshot::channel();
+    let (ran_tx, ran_rx) = oneshot::channel();
+
+    run_in_macrotask(move || {
+        hide_global("setTimeout");
+
+        // The first task uses up the tick's budget, and resuming the second
+        // one needs a timer.
+        spawn_local(async {});
+        spawn_local(async move {
+            ran_tx.send(()).unwrap();
+        });
+
+        let check = Closure::once(move |_: JsValue| {
+            let result = try_spawn_local(async {});
+            restore_global("setTimeout");
+            result_tx.send(result).unwrap();
+
+            // Now that timers are back the stalled queue resumes.
+            try_spawn_local(async {}).unwrap();
+        });
+        let _ = js_sys::Promise::resolve(&JsValue::undefined()).then(&check);
+        check.forget();
+    });
+
+    assert_eq!(result_rx.await.unwrap(), Err(ExecutorError::NoScheduler));
+    ran_rx.await.unwrap();
+}
+
+#[wasm_bindgen_test]
+async fn custom_scheduler_drains_without_set_timeout() {
+    let (tx, rx) = oneshot::channel();
+
+    run_in_macrotask(move || {
+        hide_global("setTimeout");
+
+        // Stand in for the host's own way of running a callback later.
+        let set_timeout = saved_global("setTimeout");
+        set_custom_scheduler(Some(Box::new(move |callback: &Function, delay: i32| {
+            let _ = set_timeout.call2(&JsValue::undefined(), callback, &JsValue::from(delay));
+        })));
+
+        // As above, the second task needs a macrotask.
+        spawn_local(async {});
+        spawn_local(async move {
+            restore_global("setTimeout");
+            set_custom_scheduler(None);
+            tx.send(()).unwrap();
+        });
+    });
+
+    rx.await.unwrap();
+}
+
+#[wasm_bindgen_test]
+async fn macrotask_scheduler_follows_what_is_available() {
+    let (tx, rx) = oneshot::channel();
+
+    run_in_macrotask(move || {
+        // A reschedule delay rules out the `MessageChannel`.
+        let mut kinds = vec![active_macrotask_scheduler()];
+
+        hide_global("setTimeout");
+        kinds.push(active_macrotask_scheduler());
+        set_custom_scheduler(Some(Box::new(|_: &Function, _: i32| {})));
+        kinds.push(active_macrotask_scheduler());
+        set_custom_scheduler(None);
+        restore_global("setTimeout");
+
+        tx.send(kinds).unwrap();
+    });
+
+    assert_eq!(
+        rx.await.unwrap(),
+        [Some(SchedulerKind::SetTimeout), None, Some(SchedulerKind::Custom)]
+    );
+}
@@ -0,0 +1,60 @@
+#![cfg(target_arch = "wasm32")]
+
+wasm_bindgen_test::wasm_bindgen_test_configure!(run_in_browser);
+
+use std::cell::{Cell, RefCell};
+use wasm_bindgen::prelude::*;
+use wasm_bindgen_futures::{active_scheduler, try_spawn_local, ExecutorError, SchedulerKind};
+use wasm_bindgen_test::*;
+
+#[wasm_bindgen(inline_js = "
+    let saved = {};
+
+    export function break_microtasks(error) {
+        saved.queueMicrotask = globalThis.queueMicrotask;
+        saved.resolve = Promise.resolve;
+        globalThis.queueMicrotask = undefined;
+        Promise.resolve = () => { throw error; };
+    }
+
+    export function restore_microtasks() {
+        globalThis.queueMicrotask = saved.queueMicrotask;
+        Promise.resolve = saved.resolve;
+    }
+")]
+extern "C" {
+    fn break_microtasks(error: &JsValue);
+    fn restore_microtasks();
+}
+
+thread_local! {
+    static SPAWN_RESULT: RefCell<Option<Result<(), ExecutorError>>> = RefCell::new(None);
+    static STRANDED_TASK_RAN: Cell<bool> = Cell::new(false);
+}
+
+// Create the executor while neither `queueMicrotask` nor `Promise.resolve`
+// work, as if memory had run out, and try spawning then.
+#[wasm_bindgen(start)]
+pub fn spawn_without_microtasks() {
+    break_microtasks(&JsValue::from("out of memory"));
+    let result = try_spawn_local(async {
+        STRANDED_TASK_RAN.with(|ran| ran.set(true));
+    });
+    restore_microtasks();
+
+    SPAWN_RESULT.with(|slot| *slot.borrow_mut() = Some(result));
+}
+
+#[wasm_bindgen_test]
+async fn spawning_without_microtasks_is_a_typed_error() {
+    let result = SPAWN_RESULT.with(|slot| slot.borrow_mut().take());
+    assert_eq!(
+        result,
+        Some(Err(ExecutorError::Unavailable(JsValue::from("out of memory"))))
+    );
+
+    // Running this test needed a tick, which the executor could schedule
+    // once `Promise.resolve` worked again. That tick ran the stranded task.
+    assert_eq!(active_scheduler(), Some(SchedulerKind::PromiseThen));
+    assert!(STRANDED_TASK_RAN.with(|ran| ran.get()));
+}
@@ -0,0 +1,28 @@
+#![cfg(target_arch = "wasm32")]
+
+wasm_bindgen_test::wasm_bindgen_test_configure!(run_in_browser);
+
+use wasm_bindgen::prelude::*;
+use wasm_bindgen_futures::next_idle_deadline;
+use wasm_bindgen_test::*;
+
+#[wasm_bindgen_test]
+async fn idle_deadline_has_time_left() {
+    let deadline = next_idle_deadline().await;
+    assert!(deadline.time_remaining() >= 0.0);
+}
+
+#[wasm_bindgen_test]
+async fn idle_deadline_falls_back_to_set_timeout() {
+    let window = js_sys::global();
+    let name = JsValue::from("requestIdleCallback");
+    let native = js_sys::Reflect::get(&window, &name).unwrap();
+    js_sys::Reflect::set(&window, &name, &JsValue::undefined()).unwrap();
+
+    let deadline = next_idle_deadline().await;
+    js_sys::Reflect::set(&window, &name, &native).unwrap();
+
+    let remaining = deadline.time_remaining();
+    assert!(remaining > 0.0 && remaining <= 50.0, "{}ms remaining", remaining);
+    assert!(!deadline.did_timeout());
+}
@@ -0,0 +1,66 @@
+#![cfg(target_arch = "wasm32")]
+
+wasm_bindgen_test::wasm_bindgen_test_configure!(run_in_browser);
+
+use std::cell::RefCell;
+use std::rc::Rc;
+use wasm_bindgen::prelude::*;
+use wasm_bindgen_futures::{set_yield_on_input_pending, spawn_local, until_idle};
+use wasm_bindgen_test::*;
+
+#[wasm_bindgen(inline_js = "
+    export function mock_input_pending(pending) {
+        Object.defineProperty(navigator, 'scheduling', {
+            value: { isInputPending: () => pending },
+            configurable: true,
+        });
+    }
+
+    export function on_microtask(f) {
+        Promise.resolve().then(f);
+    }
+")]
+extern "C" {
+    fn mock_input_pending(pending: bool);
+    fn on_microtask(f: &JsValue);
+}
+
+async fn run_three_tasks() -> Vec<&'static str> {
+    let order = Rc::new(RefCell::new(Vec::new()));
+
+    // The first task queues a microtask, which only runs before the other
+    // tasks if the tick yields after the first one.
+    let log = order.clone();
+    let microtask = Closure::once_into_js(move || log.borrow_mut().push("microtask"));
+    let log = order.clone();
+    spawn_local(async move {
+        on_microtask(&microtask);
+        log.borrow_mut().push("first");
+    });
+    for &name in &["second", "third"] {
+        let log = order.clone();
+        spawn_local(async move { log.borrow_mut().push(name) });
+    }
+    until_idle().await;
+    order.replace(Vec::new())
+}
+
+#[wasm_bindgen_test]
+async fn pending_input_ends_the_tick_early() {
+    mock_input_pending(true);
+    assert!(set_yield_on_input_pending(true));
+    let order = run_three_tasks().await;
+    set_yield_on_input_pending(false);
+
+    assert_eq!(order, ["first", "microtask", "second", "third"]);
+}
+
+#[wasm_bindgen_test]
+async fn without_pending_input_the_tick_keeps_going() {
+    mock_input_pending(false);
+    assert!(set_yield_on_input_pending(true));
+    let order = run_three_tasks().await;
+    set_yield_on_input_pending(false);
+
+    assert_eq!(order, ["first", "second", "third", "microtask"]);
+}
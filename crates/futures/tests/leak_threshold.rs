@@ -0,0 +1,40 @@
+#![cfg(target_arch = "wasm32")]
+
+wasm_bindgen_test::wasm_bindgen_test_configure!(run_in_browser);
+
+use std::cell::Cell;
+use std::rc::Rc;
+use wasm_bindgen_futures::{
+    set_jsfuture_leak_threshold, set_queue_observer, JsFuture, QueueObserver,
+};
+use wasm_bindgen_test::*;
+
+struct CountReports(Rc<Cell<usize>>);
+
+impl QueueObserver for CountReports {
+    fn jsfuture_leak_threshold_exceeded(&self, _live: usize) {
+        self.0.set(self.0.get() + 1);
+    }
+}
+
+#[wasm_bindgen_test]
+fn leak_threshold_is_reported_once() {
+    let reports = Rc::new(Cell::new(0));
+    set_queue_observer(Some(Box::new(CountReports(reports.clone()))));
+    set_jsfuture_leak_threshold(Some(3));
+
+    let pending = (0..6)
+        .map(|_| JsFuture::from(js_sys::Promise::new(&mut |_, _| {})))
+        .collect::<Vec<_>>();
+    assert_eq!(reports.get(), 1);
+
+    // Dropping them brings the count back down and re-arms the report.
+    drop(pending);
+    let _pending = (0..6)
+        .map(|_| JsFuture::from(js_sys::Promise::new(&mut |_, _| {})))
+        .collect::<Vec<_>>();
+    assert_eq!(reports.get(), 2);
+
+    set_jsfuture_leak_threshold(None);
+    set_queue_observer(None);
+}
@@ -0,0 +1,74 @@
+#![cfg(target_arch = "wasm32")]
+
+wasm_bindgen_test::wasm_bindgen_test_configure!(run_in_browser);
+
+use futures_channel::oneshot;
+use std::cell::RefCell;
+use std::rc::Rc;
+use wasm_bindgen_futures::{
+    add_queue_observer, remove_queue_observer, set_long_task_threshold_ms, set_queue_observer,
+    spawn_local, QueueObserver,
+};
+use wasm_bindgen_test::*;
+
+struct RecordLongTasks(Rc<RefCell<Vec<f64>>>);
+
+impl QueueObserver for RecordLongTasks {
+    fn long_task(&self, _task_id: u64, duration_ms: f64) {
+        self.0.borrow_mut().push(duration_ms);
+    }
+}
+
+/// Blocks the thread for `ms` milliseconds.
+fn busy_wait(ms: f64) {
+    let start = js_sys::Date::now();
+    while js_sys::Date::now() - start < ms {}
+}
+
+#[wasm_bindgen_test]
+async fn only_slow_tasks_are_reported() {
+    let reports = Rc::new(RefCell::new(Vec::new()));
+    set_queue_observer(Some(Box::new(RecordLongTasks(reports.clone()))));
+    set_long_task_threshold_ms(Some(20.0));
+
+    let (tx, rx) = oneshot::channel();
+    spawn_local(async { busy_wait(40.0) });
+    spawn_local(async move { tx.send(()).unwrap() });
+    rx.await.unwrap();
+
+    set_long_task_threshold_ms(None);
+    set_queue_observer(None);
+
+    let reports = reports.borrow();
+    assert_eq!(reports.len(), 1);
+    // `Date.now()` is coarser than the `performance.now()` the executor
+    // goes by, so allow for a millisecond of rounding.
+    assert!(reports[0] >= 39.0, "reported {}ms", reports[0]);
+}
+
+async fn run_slow_task() {
+    let (tx, rx) = oneshot::channel();
+    spawn_local(async { busy_wait(40.0) });
+    spawn_local(async move { tx.send(()).unwrap() });
+    rx.await.unwrap();
+}
+
+#[wasm_bindgen_test]
+async fn every_added_observer_is_notified() {
+    let first = Rc::new(RefCell::new(Vec::new()));
+    let second = Rc::new(RefCell::new(Vec::new()));
+    let first_id = add_queue_observer(Box::new(RecordLongTasks(first.clone())));
+    let second_id = add_queue_observer(Box::new(RecordLongTasks(second.clone())));
+    set_long_task_threshold_ms(Some(20.0));
+
+    run_slow_task().await;
+    assert!(remove_queue_observer(second_id));
+    assert!(!remove_queue_observer(second_id));
+    run_slow_task().await;
+
+    set_long_task_threshold_ms(None);
+    assert!(remove_queue_observer(first_id));
+
+    assert_eq!(first.borrow().len(), 2);
+    assert_eq!(second.borrow().len(), 1);
+}
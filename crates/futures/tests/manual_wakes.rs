@@ -0,0 +1,68 @@
+#![cfg(target_arch = "wasm32")]
+
+wasm_bindgen_test::wasm_bindgen_test_configure!(run_in_browser);
+
+use std::cell::Cell;
+use std::future::Future;
+use std::pin::Pin;
+use std::rc::Rc;
+use std::task::{Context, Poll};
+use wasm_bindgen_futures::{spawn_local, with_manual_wakes};
+use wasm_bindgen_test::*;
+
+// Returns `Pending` once, waking the task right away.
+struct YieldOnce(bool);
+
+impl Future for YieldOnce {
+    type Output = ();
+
+    fn poll(mut self: Pin<&mut Self>, cx: &mut Context) -> Poll<()> {
+        if self.0 {
+            return Poll::Ready(());
+        }
+        self.0 = true;
+        cx.waker().wake_by_ref();
+        Poll::Pending
+    }
+}
+
+#[wasm_bindgen_test]
+fn manual_wakes_step_a_task_one_poll_at_a_time() {
+    let step = Rc::new(Cell::new(0));
+
+    with_manual_wakes(|driver| {
+        let s = step.clone();
+        spawn_local(async move {
+            s.set(1);
+            YieldOnce(false).await;
+            s.set(2);
+            YieldOnce(false).await;
+            s.set(3);
+        });
+        assert_eq!(step.get(), 0);
+
+        assert!(driver.run_one());
+        assert_eq!(step.get(), 1);
+        assert!(driver.run_one());
+        assert_eq!(step.get(), 2);
+        assert!(driver.run_one());
+        assert_eq!(step.get(), 3);
+        assert!(!driver.run_one());
+    });
+}
+
+#[wasm_bindgen_test]
+fn manual_wakes_run_ready_drains_the_queue() {
+    let done = Rc::new(Cell::new(false));
+
+    with_manual_wakes(|driver| {
+        let d = done.clone();
+        spawn_local(async move {
+            YieldOnce(false).await;
+            d.set(true);
+        });
+
+        assert_eq!(driver.run_ready(), 2);
+        assert!(done.get());
+    });
+}
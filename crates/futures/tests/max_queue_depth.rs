@@ -0,0 +1,53 @@
+#![cfg(target_arch = "wasm32")]
+
+wasm_bindgen_test::wasm_bindgen_test_configure!(run_in_browser);
+
+use futures_channel::oneshot;
+use wasm_bindgen::prelude::*;
+use wasm_bindgen_futures::{
+    queue_depths, set_max_queue_depth, set_wake_mode, spawn_local, try_spawn_local,
+    try_spawn_local_high_priority, ExecutorError, JsFuture, WakeMode,
+};
+use wasm_bindgen_test::*;
+
+#[wasm_bindgen_test]
+fn spawning_past_the_max_depth_is_rejected() {
+    let before = queue_depths();
+    set_max_queue_depth(Some(before.high + before.normal + 2));
+
+    // Nothing runs until this test returns.
+    assert_eq!(try_spawn_local(async {}), Ok(()));
+    assert_eq!(try_spawn_local_high_priority(async {}), Ok(()));
+    assert_eq!(try_spawn_local(async {}), Err(ExecutorError::QueueFull));
+    assert_eq!(try_spawn_local_high_priority(async {}), Err(ExecutorError::QueueFull));
+
+    set_max_queue_depth(None);
+    let after = queue_depths();
+    assert_eq!(after.high, before.high + 1);
+    assert_eq!(after.normal, before.normal + 1);
+}
+
+#[wasm_bindgen_test]
+async fn tasks_waiting_for_a_macrotask_count_towards_the_depth() {
+    let (wake, woken) = oneshot::channel::<()>();
+    let (done_tx, done) = oneshot::channel();
+    spawn_local(async move {
+        assert!(set_wake_mode(WakeMode::Macrotask));
+        woken.await.unwrap();
+        done_tx.send(()).unwrap();
+    });
+    JsFuture::from(js_sys::Promise::resolve(&JsValue::undefined()))
+        .await
+        .unwrap();
+
+    // The woken task waits for its macrotask until this test yields.
+    let before = queue_depths();
+    wake.send(()).unwrap();
+    assert_eq!(queue_depths().normal, before.normal + 1);
+
+    set_max_queue_depth(Some(before.high + before.normal + 1));
+    assert_eq!(try_spawn_local(async {}), Err(ExecutorError::QueueFull));
+    set_max_queue_depth(None);
+
+    done.await.unwrap();
+}
@@ -0,0 +1,142 @@
+#![cfg(target_arch = "wasm32")]
+
+wasm_bindgen_test::wasm_bindgen_test_configure!(run_in_browser);
+
+use futures_channel::oneshot;
+use std::cell::{Cell, RefCell};
+use std::rc::Rc;
+use wasm_bindgen::prelude::*;
+use wasm_bindgen_futures::{
+    set_long_task_threshold_ms, spawn_local, spawn_local_high_priority, JsFuture, QueueBuilder,
+    QueueInUse, QueueObserver,
+};
+use wasm_bindgen_test::*;
+
+thread_local! {
+    static LONG_TASKS: Cell<usize> = Cell::new(0);
+}
+
+struct CountLongTasks;
+
+impl QueueObserver for CountLongTasks {
+    fn long_task(&self, _task_id: u64, _duration_ms: f64) {
+        LONG_TASKS.with(|count| count.set(count.get() + 1));
+    }
+}
+
+// The executor is created lazily by the first spawn, and the test harness
+// itself spawns the tests, so configure it during instantiation instead.
+#[wasm_bindgen(start)]
+pub fn configure_queue() {
+    QueueBuilder::new()
+        .coop_budget(2)
+        .observer(Box::new(CountLongTasks))
+        .install()
+        .unwrap_throw();
+}
+
+#[wasm_bindgen]
+extern "C" {
+    fn setTimeout(c: &Closure<dyn FnMut()>);
+}
+
+#[wasm_bindgen_test]
+fn install_after_use_is_an_error() {
+    assert_eq!(QueueBuilder::new().install(), Err(QueueInUse));
+}
+
+#[wasm_bindgen_test]
+async fn spawns_honor_configured_budget() {
+    let ran = Rc::new(Cell::new(0));
+    let ran_in_first_tick = Rc::new(Cell::new(0));
+    let (tx, rx) = oneshot::channel();
+
+    // Spawn from a macrotask so the batch starts a fresh tick, then record
+    // progress from a microtask queued right behind that tick.
+    let closure = {
+        let ran = ran.clone();
+        let ran_in_first_tick = ran_in_first_tick.clone();
+        let mut tx = Some(tx);
+        Closure::wrap(Box::new(move || {
+            for _ in 0..5 {
+                let ran = ran.clone();
+                spawn_local(async move {
+                    ran.set(ran.get() + 1);
+                });
+            }
+
+            let tx = tx.take().unwrap();
+            let ran = ran.clone();
+            let ran_in_first_tick = ran_in_first_tick.clone();
+            spawn_local(async move {
+                tx.send(()).unwrap();
+            });
+
+            let record = Closure::wrap(Box::new(move |_| {
+                ran_in_first_tick.set(ran.get());
+            }) as Box<dyn FnMut(JsValue)>);
+            let _ = js_sys::Promise::resolve(&JsValue::undefined()).then(&record);
+            record.forget();
+        }) as Box<dyn FnMut()>)
+    };
+    setTimeout(&closure);
+    closure.forget();
+
+    rx.await.unwrap();
+    assert_eq!(ran_in_first_tick.get(), 2);
+    assert_eq!(ran.get(), 5);
+}
+
+#[wasm_bindgen_test]
+async fn high_priority_continuation_runs_before_normal_tasks() {
+    let order = Rc::new(RefCell::new(Vec::new()));
+    let (tx, rx) = oneshot::channel();
+
+    let closure = {
+        let order = order.clone();
+        let mut tx = Some(tx);
+        Closure::wrap(Box::new(move || {
+            let log = order.clone();
+            spawn_local_high_priority(async move {
+                let settled = js_sys::Promise::resolve(&JsValue::undefined());
+                JsFuture::from(settled).await.unwrap();
+                log.borrow_mut().push("high");
+            });
+
+            // The first tick runs two of these, and the promise settles
+            // before the next one.
+            for name in &["a", "b", "c", "d"] {
+                let log = order.clone();
+                spawn_local(async move {
+                    log.borrow_mut().push(name);
+                });
+            }
+
+            let tx = tx.take().unwrap();
+            spawn_local(async move {
+                tx.send(()).unwrap();
+            });
+        }) as Box<dyn FnMut()>)
+    };
+    setTimeout(&closure);
+    closure.forget();
+
+    rx.await.unwrap();
+    assert_eq!(*order.borrow(), ["a", "b", "high", "c", "d"]);
+}
+
+#[wasm_bindgen_test]
+async fn configured_observer_is_notified() {
+    set_long_task_threshold_ms(Some(10.0));
+
+    let (tx, rx) = oneshot::channel();
+    spawn_local(async move {
+        let start = js_sys::Date::now();
+        while js_sys::Date::now() - start < 20.0 {}
+        tx.send(()).unwrap();
+    });
+    rx.await.unwrap();
+
+    set_long_task_threshold_ms(None);
+    assert_eq!(LONG_TASKS.with(Cell::get), 1);
+}
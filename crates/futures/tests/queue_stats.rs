@@ -0,0 +1,125 @@
+#![cfg(target_arch = "wasm32")]
+
+wasm_bindgen_test::wasm_bindgen_test_configure!(run_in_browser);
+
+use futures_channel::oneshot;
+use js_sys::{Array, Promise, Reflect};
+use wasm_bindgen::prelude::*;
+use wasm_bindgen::JsCast;
+use wasm_bindgen_futures::{
+    pending_tasks_snapshot, queue_stats, set_coalesce_window_ms, set_coop_budget, spawn_local,
+    spawn_local_high_priority, until_idle, JsFuture,
+};
+use wasm_bindgen_test::*;
+
+#[wasm_bindgen(inline_js = "
+    export function staggered_promises(count) {
+        // Each promise settles a microtask after the one before it.
+        const promises = [];
+        let promise = Promise.resolve();
+        for (let i = 0; i < count; i++) {
+            promise = promise.then(() => {});
+            promises.push(promise);
+        }
+        return promises;
+    }
+")]
+extern "C" {
+    fn staggered_promises(count: u32) -> Array;
+}
+
+fn spawn_noops(count: usize) {
+    for _ in 0..count {
+        spawn_local(async {});
+    }
+}
+
+#[wasm_bindgen_test]
+async fn exceeding_the_budget_counts_macrotask_drains() {
+    let before = queue_stats();
+
+    set_coop_budget(1);
+    spawn_noops(10);
+    until_idle().await;
+    set_coop_budget(u32::MAX);
+
+    assert!(queue_stats().macrotask_drains > before.macrotask_drains);
+}
+
+#[wasm_bindgen_test]
+async fn an_unlimited_budget_only_drains_on_microtasks() {
+    set_coop_budget(u32::MAX);
+    let before = queue_stats();
+
+    spawn_noops(10);
+    until_idle().await;
+
+    let after = queue_stats();
+    assert!(after.microtask_drains > before.microtask_drains);
+    assert_eq!(after.macrotask_drains, before.macrotask_drains);
+}
+
+/// Wakes a task from each of `count` microtasks in a row, returning how
+/// many drains that took.
+async fn drains_for_burst(count: u32) -> u64 {
+    let (tx, rx) = oneshot::channel();
+    let before = queue_stats();
+
+    let mut tx = Some(tx);
+    for (i, promise) in staggered_promises(count).iter().enumerate() {
+        let future = JsFuture::from(promise.unchecked_into::<Promise>());
+        let tx = if i as u32 == count - 1 { tx.take() } else { None };
+        spawn_local(async move {
+            future.await.unwrap();
+            if let Some(tx) = tx {
+                tx.send(()).unwrap();
+            }
+        });
+    }
+    rx.await.unwrap();
+
+    let after = queue_stats();
+    (after.microtask_drains + after.macrotask_drains)
+        - (before.microtask_drains + before.macrotask_drains)
+}
+
+#[wasm_bindgen_test]
+async fn coalescing_merges_bursty_wakes() {
+    let separate = drains_for_burst(5).await;
+
+    set_coalesce_window_ms(Some(50.0));
+    let coalesced = drains_for_burst(5).await;
+    set_coalesce_window_ms(None);
+
+    assert!(coalesced < separate, "{} >= {}", coalesced, separate);
+    assert!(coalesced <= 2);
+}
+
+#[wasm_bindgen_test]
+async fn snapshot_describes_the_queued_tasks() {
+    until_idle().await;
+
+    // Nothing else runs until this poll returns, so these stay queued.
+    spawn_noops(2);
+    spawn_local_high_priority(async {});
+
+    let snapshot = Array::from(&pending_tasks_snapshot());
+    assert_eq!(snapshot.length(), 3);
+    let field = |index, key| Reflect::get(&snapshot.get(index), &JsValue::from(key)).unwrap();
+
+    // The high priority task comes first, despite being spawned last.
+    let priorities = (0..3).map(|i| field(i, "priority").as_string().unwrap());
+    assert_eq!(priorities.collect::<Vec<_>>(), ["high", "normal", "normal"]);
+    let high_id = field(0, "id").as_f64().unwrap();
+    for i in 1..3 {
+        assert!(field(i, "id").as_f64().unwrap() < high_id);
+    }
+    for i in 0..3 {
+        assert_eq!(field(i, "age_ticks").as_f64(), Some(0.0));
+        // Tasks have no names to report.
+        assert!(!Reflect::has(&snapshot.get(i), &JsValue::from("name")).unwrap());
+    }
+
+    until_idle().await;
+    assert_eq!(Array::from(&pending_tasks_snapshot()).length(), 0);
+}
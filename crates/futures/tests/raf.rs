@@ -0,0 +1,86 @@
+#![cfg(target_arch = "wasm32")]
+
+wasm_bindgen_test::wasm_bindgen_test_configure!(run_in_browser);
+
+use std::cell::RefCell;
+use std::rc::Rc;
+use wasm_bindgen::prelude::*;
+use wasm_bindgen_futures::{
+    active_scheduler, before_next_frame, run_budgeted_on_raf, sleep, spawn_local, until_idle,
+    SchedulerKind,
+};
+use wasm_bindgen_test::*;
+
+#[wasm_bindgen(inline_js = "
+    let frames = 0;
+    function count() {
+        frames += 1;
+        requestAnimationFrame(count);
+    }
+    requestAnimationFrame(count);
+
+    export function frames_so_far() {
+        return frames;
+    }
+")]
+extern "C" {
+    fn frames_so_far() -> u32;
+}
+
+fn busy_wait(ms: f64) {
+    let start = js_sys::Date::now();
+    while js_sys::Date::now() - start < ms {}
+}
+
+#[wasm_bindgen_test]
+async fn raf_ticks_spread_work_across_frames() {
+    run_budgeted_on_raf(Some(8.0));
+    assert_eq!(active_scheduler(), Some(SchedulerKind::AnimationFrame));
+    // Get the test itself onto a frame's tick, where the budget applies.
+    sleep(0).await;
+
+    // Each task records the frame it ran in, and when it started and ended.
+    let runs = Rc::new(RefCell::new(Vec::new()));
+    for _ in 0..20 {
+        let runs = runs.clone();
+        spawn_local(async move {
+            let frame = frames_so_far();
+            let start = js_sys::Date::now();
+            busy_wait(2.0);
+            runs.borrow_mut().push((frame, start, js_sys::Date::now()));
+        });
+    }
+    until_idle().await;
+    run_budgeted_on_raf(None);
+
+    let runs = runs.borrow();
+    assert_eq!(runs.len(), 20);
+    let mut frames: Vec<u32> = runs.iter().map(|run| run.0).collect();
+    frames.dedup();
+    assert!(frames.len() > 1, "everything ran in a single frame");
+
+    for frame in frames {
+        let in_frame = runs.iter().filter(|run| run.0 == frame);
+        let start = in_frame.clone().map(|run| run.1).fold(f64::INFINITY, f64::min);
+        let end = in_frame.map(|run| run.2).fold(0.0, f64::max);
+        // The budget, plus the task that overran it, plus some slack for
+        // the clock's coarseness.
+        assert!(end - start <= 8.0 + 2.0 + 3.0, "frame {} ran for {}ms", frame, end - start);
+    }
+}
+
+#[wasm_bindgen_test]
+async fn quick_futures_finish_before_the_next_frame() {
+    assert_eq!(before_next_frame(async { 1 }).await, Some(1));
+}
+
+#[wasm_bindgen_test]
+async fn slow_futures_give_way_to_the_next_frame() {
+    let frame = frames_so_far();
+    let slow = async {
+        sleep(1000).await;
+        1
+    };
+    assert_eq!(before_next_frame(slow).await, None);
+    assert!(frames_so_far() > frame);
+}
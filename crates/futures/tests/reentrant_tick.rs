@@ -0,0 +1,61 @@
+#![cfg(target_arch = "wasm32")]
+
+wasm_bindgen_test::wasm_bindgen_test_configure!(run_in_browser);
+
+use futures_channel::oneshot;
+use std::cell::RefCell;
+use std::rc::Rc;
+use wasm_bindgen::prelude::*;
+use wasm_bindgen_futures::{spawn_local, JsFuture};
+use wasm_bindgen_test::*;
+
+#[wasm_bindgen(inline_js = "
+    let tick = null;
+
+    export function capture_ticks() {
+        const queueMicrotask = globalThis.queueMicrotask;
+        globalThis.queueMicrotask = f => {
+            tick = f;
+            return queueMicrotask(f);
+        };
+    }
+
+    export function run_tick_now() {
+        tick();
+    }
+")]
+extern "C" {
+    fn capture_ticks();
+    fn run_tick_now();
+}
+
+#[wasm_bindgen_test]
+async fn nested_ticks_do_not_run_tasks() {
+    capture_ticks();
+    // Come back on a tick scheduled through the wrapper, so it's captured.
+    JsFuture::from(js_sys::Promise::resolve(&JsValue::undefined())).await.unwrap();
+
+    let order = Rc::new(RefCell::new(Vec::new()));
+    let (tx, rx) = oneshot::channel();
+
+    let log = order.clone();
+    spawn_local(async move {
+        log.borrow_mut().push("before");
+        run_tick_now();
+        log.borrow_mut().push("after");
+    });
+    for &name in &["a", "b"] {
+        let log = order.clone();
+        spawn_local(async move { log.borrow_mut().push(name) });
+    }
+    spawn_local(async move { tx.send(()).unwrap() });
+    rx.await.unwrap();
+
+    // The queued tasks run once each, in order, on the outer tick. Debug
+    // builds catch the nested tick instead, and drop the task which started it.
+    if cfg!(debug_assertions) {
+        assert_eq!(*order.borrow(), ["before", "a", "b"]);
+    } else {
+        assert_eq!(*order.borrow(), ["before", "after", "a", "b"]);
+    }
+}
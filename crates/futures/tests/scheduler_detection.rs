@@ -0,0 +1,51 @@
+#![cfg(target_arch = "wasm32")]
+
+wasm_bindgen_test::wasm_bindgen_test_configure!(run_in_browser);
+
+use wasm_bindgen::prelude::*;
+use wasm_bindgen_futures::{active_macrotask_scheduler, active_scheduler, SchedulerKind};
+use wasm_bindgen_test::*;
+
+#[wasm_bindgen(inline_js = "
+    let saved = {};
+
+    export function hide_globals(names) {
+        for (const name of names) {
+            saved[name] = globalThis[name];
+            globalThis[name] = undefined;
+        }
+    }
+
+    export function restore_globals() {
+        Object.assign(globalThis, saved);
+    }
+")]
+extern "C" {
+    fn hide_globals(names: Vec<JsValue>);
+    fn restore_globals();
+}
+
+// Create the executor while `queueMicrotask` and `MessageChannel` look
+// unavailable, as in older hosts.
+#[wasm_bindgen(start)]
+pub fn create_queue_without_modern_apis() {
+    hide_globals(vec!["queueMicrotask".into(), "MessageChannel".into()]);
+    active_scheduler();
+    restore_globals();
+}
+
+#[wasm_bindgen_test]
+fn falls_back_when_apis_were_missing_at_creation() {
+    // Detection happened up front, so restoring the APIs changes nothing.
+    assert_eq!(active_scheduler(), Some(SchedulerKind::PromiseThen));
+    assert_eq!(active_macrotask_scheduler(), Some(SchedulerKind::SetTimeout));
+}
+
+#[wasm_bindgen_test]
+async fn fallback_schedulers_run_tasks() {
+    let (tx, rx) = futures_channel::oneshot::channel();
+    wasm_bindgen_futures::spawn_local(async move {
+        tx.send(()).unwrap();
+    });
+    rx.await.unwrap();
+}
@@ -0,0 +1,59 @@
+#![cfg(target_arch = "wasm32")]
+
+wasm_bindgen_test::wasm_bindgen_test_configure!(run_in_browser);
+
+use js_sys::{Function, Promise};
+use wasm_bindgen::prelude::*;
+use wasm_bindgen_futures::JsFuture;
+use wasm_bindgen_test::*;
+
+#[wasm_bindgen(inline_js = "
+    let microtasks = 0;
+    let thens = 0;
+
+    export function install_counters() {
+        const queueMicrotask = globalThis.queueMicrotask;
+        globalThis.queueMicrotask = f => {
+            microtasks += 1;
+            return queueMicrotask(f);
+        };
+        const then = Promise.prototype.then;
+        Promise.prototype.then = function(...args) {
+            thens += 1;
+            return then.apply(this, args);
+        };
+    }
+
+    export function microtask_count() { return microtasks; }
+    export function then_count() { return thens; }
+")]
+extern "C" {
+    fn install_counters();
+    fn microtask_count() -> u32;
+    fn then_count() -> u32;
+}
+
+#[wasm_bindgen]
+extern "C" {
+    fn setTimeout(f: &Function);
+}
+
+#[wasm_bindgen_test]
+async fn spins_do_not_register_promise_callbacks() {
+    const SPINS: u32 = 100;
+
+    install_counters();
+    let microtasks = microtask_count();
+    let thens = then_count();
+
+    for _ in 0..SPINS {
+        // Resolve from a macrotask so that every iteration needs a new spin.
+        let promise = Promise::new(&mut |resolve, _reject| setTimeout(&resolve));
+        JsFuture::from(promise).await.unwrap();
+    }
+
+    // Every spin is scheduled with `queueMicrotask`, and the only `then` calls
+    // left are the ones each `JsFuture` makes to observe its promise.
+    assert!(microtask_count() - microtasks >= SPINS);
+    assert!(then_count() - thens <= SPINS);
+}
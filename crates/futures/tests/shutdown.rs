@@ -0,0 +1,39 @@
+#![cfg(target_arch = "wasm32")]
+
+wasm_bindgen_test::wasm_bindgen_test_configure!(run_in_browser);
+
+use std::cell::Cell;
+use std::rc::Rc;
+use wasm_bindgen_futures::{shutdown, spawn_local, spawn_local_on_shutdown};
+use wasm_bindgen_test::*;
+
+struct SetOnDrop(Rc<Cell<bool>>);
+
+impl Drop for SetOnDrop {
+    fn drop(&mut self) {
+        self.0.set(true);
+    }
+}
+
+// This is synchronous so that it runs outside of any task, with nothing
+// else queued.
+#[wasm_bindgen_test]
+fn shutdown_runs_cleanup_and_drops_other_tasks() {
+    let ran = Rc::new(Cell::new(false));
+    let dropped = Rc::new(Cell::new(false));
+    let cleaned_up = Rc::new(Cell::new(false));
+
+    let guard = SetOnDrop(dropped.clone());
+    let r = ran.clone();
+    spawn_local(async move {
+        let _guard = guard;
+        r.set(true);
+    });
+    let c = cleaned_up.clone();
+    spawn_local_on_shutdown(async move { c.set(true) });
+
+    assert!(shutdown());
+    assert!(cleaned_up.get());
+    assert!(dropped.get());
+    assert!(!ran.get());
+}
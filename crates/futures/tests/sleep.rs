@@ -0,0 +1,104 @@
+#![cfg(target_arch = "wasm32")]
+
+wasm_bindgen_test::wasm_bindgen_test_configure!(run_in_browser);
+
+use std::cell::Cell;
+use std::future::Future;
+use std::rc::Rc;
+use std::task::Poll;
+use wasm_bindgen::prelude::*;
+use wasm_bindgen_futures::{poll_fn, queue_depths, sleep, sleep_handle, spawn_periodic, timeout_or};
+use wasm_bindgen_test::*;
+
+#[wasm_bindgen(inline_js = "
+    let cleared = [];
+    const clearTimeout = globalThis.clearTimeout;
+
+    // Record which timers get cleared, while still clearing them.
+    globalThis.clearTimeout = (id) => {
+        cleared.push(id);
+        clearTimeout(id);
+    };
+
+    export function cleared_timers() {
+        return cleared.length;
+    }
+")]
+extern "C" {
+    fn cleared_timers() -> u32;
+}
+
+#[wasm_bindgen_test]
+async fn sleep_resolves() {
+    sleep(1).await;
+}
+
+#[wasm_bindgen_test]
+async fn fired_sleep_resolves_to_true() {
+    let cleared = cleared_timers();
+    assert!(sleep_handle(1).await);
+    assert_eq!(cleared_timers(), cleared);
+}
+
+#[wasm_bindgen_test]
+async fn cancelled_sleep_resolves_to_false_and_clears_its_timer() {
+    let cleared = cleared_timers();
+    let sleep = sleep_handle(60_000);
+    let canceller = sleep.canceller();
+
+    wasm_bindgen_futures::spawn_local(async move { canceller.cancel() });
+
+    assert!(!sleep.await);
+    assert_eq!(cleared_timers(), cleared + 1);
+}
+
+#[wasm_bindgen_test]
+async fn timeout_or_resolves_with_a_timely_output() {
+    let cleared = cleared_timers();
+    let output = timeout_or(60_000, async { sleep(1).await; "fresh" }, "cached").await;
+    assert_eq!(output, "fresh");
+    // The inner sleep fired, while the deadline's timer was cleared.
+    assert_eq!(cleared_timers(), cleared + 1);
+}
+
+#[wasm_bindgen_test]
+async fn timeout_or_falls_back_once_the_deadline_passes() {
+    let output = timeout_or(1, async { sleep(60_000).await; "fresh" }, "cached").await;
+    assert_eq!(output, "cached");
+}
+
+#[wasm_bindgen_test]
+async fn periodic_tasks_run_until_stopped() {
+    let runs = Rc::new(Cell::new(0));
+    let handle = {
+        let runs = runs.clone();
+        spawn_periodic(5, move || {
+            let runs = runs.clone();
+            async move { runs.set(runs.get() + 1) }
+        })
+    };
+
+    while runs.get() < 3 {
+        sleep(5).await;
+    }
+    handle.stop();
+    sleep(0).await;
+    assert!(handle.is_stopped());
+
+    let stopped_at = runs.get();
+    sleep(30).await;
+    assert_eq!(runs.get(), stopped_at);
+}
+
+#[wasm_bindgen_test]
+async fn dropping_a_pending_sleep_does_not_wake_the_task() {
+    let before = queue_depths();
+    let mut pending = Some(Box::pin(sleep(60_000)));
+    poll_fn(|cx| {
+        assert!(pending.as_mut().unwrap().as_mut().poll(cx).is_pending());
+        drop(pending.take());
+        Poll::Ready(())
+    })
+    .await;
+    assert_eq!(queue_depths(), before);
+}
@@ -0,0 +1,40 @@
+#![cfg(target_arch = "wasm32")]
+
+wasm_bindgen_test::wasm_bindgen_test_configure!(run_in_browser);
+
+use std::cell::Cell;
+use std::rc::Rc;
+use wasm_bindgen_futures::{
+    set_spawn_middleware, spawn_local, spawn_local_with_handle, until_idle, LocalBoxFuture,
+};
+use wasm_bindgen_test::*;
+
+#[wasm_bindgen_test]
+async fn middleware_wraps_each_spawn_once() {
+    let wrapped = Rc::new(Cell::new(0));
+    let ran = Rc::new(Cell::new(0));
+    {
+        let wrapped = wrapped.clone();
+        let ran = ran.clone();
+        set_spawn_middleware(Some(Box::new(move |future: LocalBoxFuture| {
+            wrapped.set(wrapped.get() + 1);
+            let ran = ran.clone();
+            Box::pin(async move {
+                future.await;
+                ran.set(ran.get() + 1);
+            }) as LocalBoxFuture
+        })));
+    }
+
+    for _ in 0..3 {
+        spawn_local(async {});
+    }
+    let handle = spawn_local_with_handle(async {});
+    set_spawn_middleware(None);
+    spawn_local(async {});
+
+    until_idle().await;
+    assert_eq!(wrapped.get(), 4);
+    assert_eq!(ran.get(), 4);
+    assert!(handle.is_finished());
+}
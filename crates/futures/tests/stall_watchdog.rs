@@ -0,0 +1,68 @@
+#![cfg(target_arch = "wasm32")]
+
+wasm_bindgen_test::wasm_bindgen_test_configure!(run_in_browser);
+
+use futures_channel::oneshot;
+use std::cell::{Cell, RefCell};
+use std::rc::Rc;
+use wasm_bindgen::prelude::*;
+use wasm_bindgen_futures::{
+    set_queue_observer, set_stall_threshold_ms, try_spawn_local, QueueDepths, QueueObserver,
+};
+use wasm_bindgen_test::*;
+
+#[wasm_bindgen(inline_js = "
+    // Calls `spawn` from a macrotask, outside of any tick, while
+    // `queueMicrotask` is broken so that the executor can't schedule one.
+    export function spawn_stranded(spawn) {
+        setTimeout(() => {
+            const queueMicrotask = globalThis.queueMicrotask;
+            globalThis.queueMicrotask = () => { throw new Error('broken'); };
+            try {
+                spawn();
+            } finally {
+                globalThis.queueMicrotask = queueMicrotask;
+            }
+        }, 0);
+    }
+")]
+extern "C" {
+    fn spawn_stranded(spawn: &JsValue);
+}
+
+struct ReportStall(RefCell<Option<oneshot::Sender<QueueDepths>>>);
+
+impl QueueObserver for ReportStall {
+    fn queue_stalled(&self, _stalled_ms: f64, pending: QueueDepths) {
+        if let Some(tx) = self.0.borrow_mut().take() {
+            // Waking the test schedules a tick again, unsticking the queue.
+            tx.send(pending).unwrap();
+        }
+    }
+}
+
+#[wasm_bindgen_test]
+async fn watchdog_reports_a_stuck_queue() {
+    let (tx, rx) = oneshot::channel();
+    set_queue_observer(Some(Box::new(ReportStall(RefCell::new(Some(tx))))));
+    set_stall_threshold_ms(Some(50.0));
+
+    let ran = Rc::new(Cell::new(false));
+    let spawned = Rc::new(Cell::new(None));
+    let spawn = {
+        let ran = ran.clone();
+        let spawned = spawned.clone();
+        Closure::once_into_js(move || {
+            spawned.set(Some(try_spawn_local(async move { ran.set(true) }).is_ok()));
+        })
+    };
+    spawn_stranded(&spawn);
+
+    let pending = rx.await.unwrap();
+    set_stall_threshold_ms(None);
+    set_queue_observer(None);
+
+    assert_eq!(spawned.get(), Some(false));
+    assert_eq!(pending.normal, 1);
+    assert!(ran.get());
+}
@@ -0,0 +1,658 @@
+#![cfg(target_arch = "wasm32")]
+
+wasm_bindgen_test::wasm_bindgen_test_configure!(run_in_browser);
+
+use std::cell::Cell;
+use std::future::poll_fn;
+use std::pin::Pin;
+use std::rc::Rc;
+use std::task::{Context, Poll};
+use wasm_bindgen::prelude::*;
+use wasm_bindgen::JsCast;
+use wasm_bindgen_futures::stream::{
+    buffer_unordered, buffered, callback_stream, channel, chunks, collect_to_array, collect_to_map,
+    dedup, dedup_by_key, events, flat_map, flat_map_unordered, into_readable_stream, last, latest,
+    merge, messages, once_event, peekable, scan, skip, step_by, take, take_until, throttle,
+    timeout_items, unfold, with_interval_timing, zip, OverflowPolicy, Overflowed, Stream, Timeout,
+    Unregister,
+};
+use wasm_bindgen_futures::{
+    queue_depths, set_max_queue_depth, sleep, spawn_local, spawn_local_batch, CancellationToken,
+    JsFuture,
+};
+use wasm_bindgen_test::*;
+
+/// An endless stream of `0, 1, 2, ...` which is always ready.
+struct Counter {
+    next: u32,
+}
+
+impl Stream for Counter {
+    type Item = u32;
+
+    fn poll_next(mut self: Pin<&mut Self>, _cx: &mut Context) -> Poll<Option<u32>> {
+        let item = self.next;
+        self.next += 1;
+        Poll::Ready(Some(item))
+    }
+}
+
+/// A stream of the items of an iterator, which is always ready.
+struct Iter<I>(I);
+
+impl<I: Iterator + Unpin> Stream for Iter<I> {
+    type Item = I::Item;
+
+    fn poll_next(mut self: Pin<&mut Self>, _cx: &mut Context) -> Poll<Option<I::Item>> {
+        Poll::Ready(self.0.next())
+    }
+}
+
+/// A stream of the given chunks as `Uint8Array`s.
+struct Chunks(std::vec::IntoIter<&'static str>);
+
+impl Stream for Chunks {
+    type Item = JsValue;
+
+    fn poll_next(mut self: Pin<&mut Self>, _cx: &mut Context) -> Poll<Option<JsValue>> {
+        let chunk = self
+            .0
+            .next()
+            .map(|s| js_sys::Uint8Array::from(s.as_bytes()).into());
+        Poll::Ready(chunk)
+    }
+}
+
+async fn next<S: Stream + Unpin>(stream: &mut S) -> Option<S::Item> {
+    poll_fn(|cx| Pin::new(&mut *stream).poll_next(cx)).await
+}
+
+#[wasm_bindgen_test]
+async fn take_until_ends_once_cancelled() {
+    let token = CancellationToken::new();
+    let mut stream = take_until(Counter { next: 0 }, &token);
+
+    assert_eq!(next(&mut stream).await, Some(0));
+    assert_eq!(next(&mut stream).await, Some(1));
+    token.cancel();
+    assert_eq!(next(&mut stream).await, None);
+    assert_eq!(next(&mut stream).await, None);
+}
+
+#[wasm_bindgen_test]
+async fn readable_stream_feeds_a_response() {
+    let chunks = Chunks(vec!["hello", ", ", "world"].into_iter());
+    let body = into_readable_stream(chunks);
+    let response = web_sys::Response::new_with_opt_readable_stream(Some(&body)).unwrap();
+
+    let text = JsFuture::from(response.text().unwrap()).await.unwrap();
+    assert_eq!(text.as_string().unwrap(), "hello, world");
+}
+
+/// Sends `0..5` through a channel with room for 3 items, then collects what
+/// comes out.
+async fn overflow_with(policy: OverflowPolicy) -> Vec<Result<u32, Overflowed>> {
+    let (tx, mut rx) = channel(3, policy);
+    for i in 0..5 {
+        tx.send(i).unwrap();
+    }
+    drop(tx);
+
+    let mut items = Vec::new();
+    while let Some(item) = next(&mut rx).await {
+        items.push(item);
+    }
+    items
+}
+
+#[wasm_bindgen_test]
+async fn drop_oldest_keeps_newest_items() {
+    let items = overflow_with(OverflowPolicy::DropOldest).await;
+    assert_eq!(items, [Ok(2), Ok(3), Ok(4)]);
+}
+
+#[wasm_bindgen_test]
+async fn drop_newest_keeps_oldest_items() {
+    let items = overflow_with(OverflowPolicy::DropNewest).await;
+    assert_eq!(items, [Ok(0), Ok(1), Ok(2)]);
+}
+
+#[wasm_bindgen_test]
+async fn error_policy_yields_overflow_error() {
+    let items = overflow_with(OverflowPolicy::Error).await;
+    assert_eq!(items, [Ok(0), Ok(1), Ok(2), Err(Overflowed)]);
+}
+
+#[wasm_bindgen_test]
+async fn event_stream_applies_overflow_policy() {
+    let target = web_sys::EventTarget::new().unwrap();
+    let mut stream = events(&target, "ping", 2, OverflowPolicy::DropOldest);
+
+    let dispatched = ["ping", "pong", "ping", "ping"]
+        .iter()
+        .map(|name| {
+            let event = web_sys::Event::new(name).unwrap();
+            target.dispatch_event(&event).unwrap();
+            event
+        })
+        .collect::<Vec<_>>();
+
+    // The pong isn't listened to, and the first ping was dropped to make room.
+    assert_eq!(next(&mut stream).await, Some(Ok(dispatched[2].clone())));
+    assert_eq!(next(&mut stream).await, Some(Ok(dispatched[3].clone())));
+}
+
+#[wasm_bindgen_test]
+async fn once_event_resolves_with_the_first_event() {
+    let target = web_sys::EventTarget::new().unwrap();
+    let future = once_event(&target, "ping");
+
+    let first = web_sys::Event::new("ping").unwrap();
+    target.dispatch_event(&first).unwrap();
+    target
+        .dispatch_event(&web_sys::Event::new("ping").unwrap())
+        .unwrap();
+    assert_eq!(future.await, first);
+}
+
+#[wasm_bindgen_test]
+async fn message_streams_round_trip_through_a_channel() {
+    let channel = web_sys::MessageChannel::new().unwrap();
+    let mut left = messages(&channel.port1(), 4, OverflowPolicy::Error);
+    let mut right = messages(&channel.port2(), 4, OverflowPolicy::Error);
+
+    left.send("ping").unwrap();
+    left.send(1).unwrap();
+    assert_eq!(next(&mut right).await, Some(Ok(JsValue::from("ping"))));
+    assert_eq!(next(&mut right).await, Some(Ok(JsValue::from(1))));
+
+    right.send("pong").unwrap();
+    assert_eq!(next(&mut left).await, Some(Ok(JsValue::from("pong"))));
+}
+
+#[wasm_bindgen_test]
+async fn merge_alternates_between_ready_streams() {
+    let mut merged = merge(
+        Iter(vec![1, 2].into_iter()),
+        Iter(vec![10, 20, 30].into_iter()),
+    );
+
+    let mut items = Vec::new();
+    while let Some(item) = next(&mut merged).await {
+        items.push(item);
+    }
+    assert_eq!(items, [1, 10, 2, 20, 30]);
+}
+
+#[wasm_bindgen_test]
+async fn callback_stream_buffers_values_until_dropped() {
+    let unregistered = Rc::new(Cell::new(false));
+
+    // A stand-in for an API which calls back synchronously a few times.
+    let mut stream = {
+        let unregistered = Rc::clone(&unregistered);
+        callback_stream(2, OverflowPolicy::DropOldest, move |callback| {
+            let callback: &js_sys::Function = callback.as_ref().unchecked_ref();
+            for i in 0..3 {
+                callback.call1(&JsValue::NULL, &JsValue::from(i)).unwrap();
+            }
+            Unregister::new(move || unregistered.set(true))
+        })
+    };
+
+    let mut values = Vec::new();
+    for _ in 0..2 {
+        values.push(next(&mut stream).await.unwrap().unwrap().as_f64().unwrap());
+    }
+    assert_eq!(values, [1.0, 2.0]);
+
+    assert!(!unregistered.get());
+    drop(stream);
+    assert!(unregistered.get());
+}
+
+#[wasm_bindgen_test]
+async fn unfold_fetches_pages_until_the_cursor_ends() {
+    // Each page holds two items and the cursor of the next page, if any.
+    async fn fetch_page(cursor: u32) -> (Vec<u32>, Option<u32>) {
+        let next = if cursor < 2 { Some(cursor + 1) } else { None };
+        let page = JsFuture::from(js_sys::Promise::resolve(&JsValue::from(cursor)))
+            .await
+            .unwrap()
+            .as_f64()
+            .unwrap() as u32;
+        (vec![page * 2, page * 2 + 1], next)
+    }
+
+    let mut pages = Box::pin(unfold(Some(0), |cursor| async move {
+        let (items, next) = fetch_page(cursor?).await;
+        Some((items, next))
+    }));
+
+    let mut items = Vec::new();
+    while let Some(page) = next(&mut pages).await {
+        items.extend(page);
+    }
+    assert_eq!(items, [0, 1, 2, 3, 4, 5]);
+}
+
+#[wasm_bindgen_test]
+async fn try_stream_yields_each_item() {
+    let mut stream: Pin<Box<dyn Stream<Item = Result<JsValue, JsValue>>>> =
+        Box::pin(wasm_bindgen_futures::try_stream!(yielder => {
+            for i in 0..3 {
+                let value = JsFuture::from(js_sys::Promise::resolve(&JsValue::from(i))).await?;
+                yielder.yield_item(value).await;
+            }
+        }));
+
+    let mut items = Vec::new();
+    while let Some(item) = next(&mut stream).await {
+        items.push(item.unwrap().as_f64().unwrap());
+    }
+    assert_eq!(items, [0.0, 1.0, 2.0]);
+}
+
+#[wasm_bindgen_test]
+async fn try_stream_ends_with_the_first_error() {
+    let mut stream = Box::pin(wasm_bindgen_futures::try_stream!(yielder => {
+        yielder.yield_item(1).await;
+        JsFuture::from(js_sys::Promise::reject(&JsValue::from("broken"))).await?;
+        yielder.yield_item(2).await;
+    }));
+
+    assert_eq!(next(&mut stream).await, Some(Ok(1)));
+    assert_eq!(next(&mut stream).await, Some(Err(JsValue::from("broken"))));
+    assert_eq!(next(&mut stream).await, None);
+}
+
+#[wasm_bindgen_test]
+async fn throttle_spaces_out_rapid_items() {
+    let (tx, rx) = channel(8, OverflowPolicy::Error);
+    for i in 0..4 {
+        tx.send(i).unwrap();
+    }
+    drop(tx);
+
+    let mut stream = throttle(rx, 20, 8, OverflowPolicy::Error);
+    let mut items = Vec::new();
+    let mut times = Vec::new();
+    while let Some(item) = next(&mut stream).await {
+        items.push(item.unwrap().unwrap());
+        times.push(js_sys::Date::now());
+    }
+
+    assert_eq!(items, [0, 1, 2, 3]);
+    for pair in times.windows(2) {
+        // `Date.now()` is coarser than the `performance.now()` the throttle
+        // goes by, so allow for a millisecond of rounding.
+        assert!(
+            pair[1] - pair[0] >= 19.0,
+            "items came {}ms apart",
+            pair[1] - pair[0]
+        );
+    }
+}
+
+#[wasm_bindgen_test]
+async fn throttle_keeps_the_latest_item() {
+    let (tx, rx) = channel(8, OverflowPolicy::Error);
+    for i in 0..5 {
+        tx.send(i).unwrap();
+    }
+    drop(tx);
+
+    let mut stream = throttle(rx, 10, 1, OverflowPolicy::DropOldest);
+    let mut items = Vec::new();
+    while let Some(item) = next(&mut stream).await {
+        items.push(item.unwrap().unwrap());
+    }
+
+    // The first item goes out right away, and the rest were replaced by the
+    // one after them while waiting.
+    assert_eq!(items, [0, 4]);
+}
+
+#[wasm_bindgen_test]
+async fn chunks_group_items_by_count() {
+    let mut stream = chunks(Iter(0..7), 3);
+    let mut sizes = Vec::new();
+    while let Some(chunk) = next(&mut stream).await {
+        sizes.push(chunk.len());
+    }
+    assert_eq!(sizes, [3, 3, 1]);
+
+    let mut empty = chunks(Iter(0..0), 3);
+    assert_eq!(next(&mut empty).await, None);
+}
+
+#[wasm_bindgen_test]
+async fn timeout_items_ends_a_stalled_stream() {
+    let (tx, rx) = channel(8, OverflowPolicy::Error);
+    tx.send(1).unwrap();
+
+    // The sender is kept alive, so after the first item the stream stalls.
+    let mut stream = timeout_items(rx, 20);
+    assert_eq!(next(&mut stream).await, Some(Ok(Ok(1))));
+    assert_eq!(next(&mut stream).await, Some(Err(Timeout)));
+    assert_eq!(next(&mut stream).await, None);
+    drop(tx);
+}
+
+#[wasm_bindgen_test]
+async fn timeout_items_restarts_the_deadline_with_each_item() {
+    let (tx, rx) = channel(8, OverflowPolicy::Error);
+    spawn_local(async move {
+        for i in 0..5 {
+            sleep(10).await;
+            tx.send(i).unwrap();
+        }
+    });
+
+    // Together the items take longer than the deadline, but none of the
+    // gaps between them do.
+    let mut stream = timeout_items(rx, 40);
+    let mut items = Vec::new();
+    while let Some(item) = next(&mut stream).await {
+        items.push(item.unwrap().unwrap());
+    }
+    assert_eq!(items, [0, 1, 2, 3, 4]);
+}
+
+#[wasm_bindgen_test]
+async fn collect_to_array_keeps_the_order() {
+    let items = vec![JsValue::from("a"), JsValue::from(2), JsValue::NULL];
+    let array = collect_to_array(Iter(items.clone().into_iter())).await;
+
+    assert_eq!(array.length(), 3);
+    assert_eq!(array.to_vec(), items);
+}
+
+#[wasm_bindgen_test]
+async fn dedup_skips_repeated_items() {
+    let mut deduped = dedup(Iter(vec![1, 1, 2, 2, 3].into_iter()));
+    let mut items = Vec::new();
+    while let Some(item) = next(&mut deduped).await {
+        items.push(item);
+    }
+    assert_eq!(items, [1, 2, 3]);
+
+    let mut empty = dedup(Iter(Vec::<u32>::new().into_iter()));
+    assert_eq!(next(&mut empty).await, None);
+}
+
+#[wasm_bindgen_test]
+async fn dedup_by_key_compares_keys() {
+    let words = vec!["apple", "avocado", "banana", "apricot"];
+    let mut deduped = dedup_by_key(Iter(words.into_iter()), |word| word.chars().next());
+    let mut items = Vec::new();
+    while let Some(item) = next(&mut deduped).await {
+        items.push(item);
+    }
+    assert_eq!(items, ["apple", "banana", "apricot"]);
+}
+
+#[wasm_bindgen_test]
+async fn last_resolves_to_the_final_item() {
+    assert_eq!(last(Iter(vec![1, 2, 3].into_iter())).await, Some(3));
+    assert_eq!(last(Iter(Vec::<u32>::new().into_iter())).await, None);
+}
+
+#[wasm_bindgen_test]
+async fn collect_to_map_keeps_the_last_value_of_each_key() {
+    let pairs = vec![("a", 1), ("b", 2), ("a", 3)];
+    let map = collect_to_map(Iter(pairs.into_iter())).await;
+
+    assert_eq!(map.size(), 2);
+    assert_eq!(map.get(&JsValue::from("a")), JsValue::from(3));
+    assert_eq!(map.get(&JsValue::from("b")), JsValue::from(2));
+}
+
+#[wasm_bindgen_test]
+async fn peeking_doesnt_consume_items() {
+    let mut stream = peekable(Iter(vec![1, 2].into_iter()));
+
+    assert_eq!(stream.peek().await, Some(&1));
+    assert_eq!(stream.peek().await, Some(&1));
+    assert_eq!(next(&mut stream).await, Some(1));
+
+    assert_eq!(stream.peek().await, Some(&2));
+    assert_eq!(next(&mut stream).await, Some(2));
+
+    assert_eq!(stream.peek().await, None);
+    assert_eq!(next(&mut stream).await, None);
+}
+
+#[wasm_bindgen_test]
+async fn zip_pairs_items_in_lockstep() {
+    let mut zipped = zip(
+        Iter(vec![1, 2, 3].into_iter()),
+        Iter(vec!["a", "b", "c"].into_iter()),
+    );
+
+    let mut pairs = Vec::new();
+    while let Some(pair) = next(&mut zipped).await {
+        pairs.push(pair);
+    }
+    assert_eq!(pairs, [(1, "a"), (2, "b"), (3, "c")]);
+}
+
+#[wasm_bindgen_test]
+async fn zip_ends_with_the_shorter_stream() {
+    let mut zipped = zip(Counter { next: 0 }, Iter(vec!["a", "b"].into_iter()));
+
+    assert_eq!(next(&mut zipped).await, Some((0, "a")));
+    assert_eq!(next(&mut zipped).await, Some((1, "b")));
+    assert_eq!(next(&mut zipped).await, None);
+    assert_eq!(next(&mut zipped).await, None);
+}
+
+#[wasm_bindgen_test]
+async fn flat_map_drains_each_sub_stream_in_order() {
+    let mut flattened = flat_map(Iter(0..3), |i| Iter(vec![i; 2].into_iter()));
+
+    let mut items = Vec::new();
+    while let Some(item) = next(&mut flattened).await {
+        items.push(item);
+    }
+    assert_eq!(items, [0, 0, 1, 1, 2, 2]);
+}
+
+#[wasm_bindgen_test]
+async fn flat_map_unordered_interleaves_sub_streams() {
+    let (slow_tx, slow_rx) = channel(4, OverflowPolicy::Error);
+    let (fast_tx, fast_rx) = channel(4, OverflowPolicy::Error);
+    fast_tx.send(10).unwrap();
+    fast_tx.send(20).unwrap();
+    drop(fast_tx);
+
+    let mut flattened = flat_map_unordered(Iter(vec![slow_rx, fast_rx].into_iter()), |rx| rx);
+
+    // The first sub-stream has nothing yet, which doesn't hold up the second.
+    assert_eq!(next(&mut flattened).await, Some(Ok(10)));
+    assert_eq!(next(&mut flattened).await, Some(Ok(20)));
+
+    spawn_local(async move {
+        sleep(10).await;
+        slow_tx.send(1).unwrap();
+    });
+    assert_eq!(next(&mut flattened).await, Some(Ok(1)));
+    assert_eq!(next(&mut flattened).await, None);
+}
+
+#[wasm_bindgen_test]
+async fn buffered_keeps_the_order_of_the_stream() {
+    let max_in_flight = Rc::new(Cell::new(0));
+    let in_flight = Rc::new(Cell::new(0));
+    let (m, n) = (max_in_flight.clone(), in_flight.clone());
+    // Later items finish sooner, so they'd come first if completion decided.
+    let mut outputs = buffered(Iter(vec![30, 20, 10].into_iter()), 2, move |ms| {
+        let (m, n) = (m.clone(), n.clone());
+        async move {
+            n.set(n.get() + 1);
+            m.set(m.get().max(n.get()));
+            sleep(ms).await;
+            n.set(n.get() - 1);
+            ms
+        }
+    });
+
+    let mut items = Vec::new();
+    while let Some(item) = next(&mut outputs).await {
+        items.push(item);
+    }
+    assert_eq!(items, [30, 20, 10]);
+    assert_eq!(max_in_flight.get(), 2);
+}
+
+#[wasm_bindgen_test]
+async fn buffer_unordered_yields_as_futures_finish() {
+    let mut outputs = buffer_unordered(Iter(vec![30, 20, 10].into_iter()), 3, |ms| async move {
+        sleep(ms).await;
+        ms
+    });
+
+    let mut items = Vec::new();
+    while let Some(item) = next(&mut outputs).await {
+        items.push(item);
+    }
+    assert_eq!(items, [10, 20, 30]);
+}
+
+/// Collects `stream` with room in the executor's queue for one more task
+/// only, so the spawns of buffering streams after the first are refused.
+async fn collect_with_room_for_one<S: Stream + Unpin>(mut stream: S) -> Vec<S::Item> {
+    let depths = queue_depths();
+    set_max_queue_depth(Some(depths.high + depths.normal + 1));
+    let mut items = Vec::new();
+    while let Some(item) = next(&mut stream).await {
+        items.push(item);
+    }
+    set_max_queue_depth(None);
+    items
+}
+
+#[wasm_bindgen_test]
+async fn buffered_skips_items_whose_spawn_was_refused() {
+    let outputs = buffered(Iter(vec![1, 2].into_iter()), 2, |n| async move { n });
+    assert_eq!(collect_with_room_for_one(outputs).await, [1]);
+}
+
+#[wasm_bindgen_test]
+async fn buffer_unordered_skips_items_whose_spawn_was_refused() {
+    let outputs = buffer_unordered(Iter(vec![1, 2].into_iter()), 2, |n| async move { n });
+    assert_eq!(collect_with_room_for_one(outputs).await, [1]);
+}
+
+#[wasm_bindgen_test]
+async fn scan_yields_running_totals() {
+    let mut totals = scan(Iter(1..=4), 0, |total, item| {
+        *total += item;
+        Some(*total)
+    });
+
+    let mut items = Vec::new();
+    while let Some(item) = next(&mut totals).await {
+        items.push(item);
+    }
+    assert_eq!(items, [1, 3, 6, 10]);
+}
+
+#[wasm_bindgen_test]
+async fn scan_ends_once_f_returns_none() {
+    // Yield items until they've added up to more than 5.
+    let mut items = scan(Counter { next: 1 }, 0, |total, item| {
+        *total += item;
+        if *total > 5 {
+            None
+        } else {
+            Some(item)
+        }
+    });
+
+    assert_eq!(next(&mut items).await, Some(1));
+    assert_eq!(next(&mut items).await, Some(2));
+    assert_eq!(next(&mut items).await, None);
+    assert_eq!(next(&mut items).await, None);
+}
+
+#[wasm_bindgen_test]
+async fn latest_yields_only_the_newest_item() {
+    let (tx, rx) = channel(16, OverflowPolicy::Error);
+    let mut items = latest(rx);
+    for i in 0..10 {
+        tx.send(i).unwrap();
+    }
+    assert_eq!(next(&mut items).await, Some(Ok(9)));
+
+    tx.send(10).unwrap();
+    drop(tx);
+    assert_eq!(next(&mut items).await, Some(Ok(10)));
+    assert_eq!(next(&mut items).await, None);
+}
+
+#[wasm_bindgen_test]
+async fn interval_timing_measures_the_gaps_between_items() {
+    let (tx, rx) = channel(4, OverflowPolicy::Error);
+    spawn_local(async move {
+        for &delay in &[0, 20, 40] {
+            sleep(delay).await;
+            tx.send(delay).unwrap();
+        }
+    });
+
+    let mut timed = with_interval_timing(rx);
+    assert_eq!(next(&mut timed).await, Some((Ok(0), 0.0)));
+    for &delay in &[20, 40] {
+        let (item, elapsed) = next(&mut timed).await.unwrap();
+        assert_eq!(item, Ok(delay));
+        // Timers can fire late, but never early.
+        assert!(elapsed >= f64::from(delay) - 1.0, "took {}ms", elapsed);
+    }
+    assert_eq!(next(&mut timed).await, None);
+}
+
+#[wasm_bindgen_test]
+async fn take_ends_after_n_items() {
+    // `Counter` never ends, so this only finishes if `take` stops pulling.
+    let mut items = take(Counter { next: 0 }, 3);
+    assert_eq!(next(&mut items).await, Some(0));
+    assert_eq!(next(&mut items).await, Some(1));
+    assert_eq!(next(&mut items).await, Some(2));
+    assert_eq!(next(&mut items).await, None);
+    assert_eq!(next(&mut items).await, None);
+}
+
+#[wasm_bindgen_test]
+async fn skip_drops_the_first_n_items() {
+    let mut items = skip(Iter(0..5), 2);
+    assert_eq!(next(&mut items).await, Some(2));
+    assert_eq!(next(&mut items).await, Some(3));
+    assert_eq!(next(&mut items).await, Some(4));
+    assert_eq!(next(&mut items).await, None);
+
+    let mut short = skip(Iter(0..2), 3);
+    assert_eq!(next(&mut short).await, None);
+}
+
+#[wasm_bindgen_test]
+async fn step_by_yields_every_nth_item() {
+    let mut items = step_by(Iter(0..6), 2);
+    assert_eq!(next(&mut items).await, Some(0));
+    assert_eq!(next(&mut items).await, Some(2));
+    assert_eq!(next(&mut items).await, Some(4));
+    assert_eq!(next(&mut items).await, None);
+}
+
+#[wasm_bindgen_test]
+async fn batches_yield_in_completion_order() {
+    let mut completions = spawn_local_batch(vec![30, 10, 20].into_iter().map(|ms| async move {
+        sleep(ms).await;
+        ms
+    }));
+
+    assert_eq!(next(&mut completions).await, Some((1, 10)));
+    assert_eq!(next(&mut completions).await, Some((2, 20)));
+    assert_eq!(next(&mut completions).await, Some((0, 30)));
+    assert_eq!(next(&mut completions).await, None);
+}
@@ -0,0 +1,55 @@
+// Borrows are only tracked in debug builds.
+#![cfg(all(target_arch = "wasm32", debug_assertions))]
+
+wasm_bindgen_test::wasm_bindgen_test_configure!(run_in_browser);
+
+use futures_channel::oneshot;
+use std::cell::RefCell;
+use std::panic::Location;
+use std::rc::Rc;
+use wasm_bindgen::prelude::*;
+use wasm_bindgen_futures::{
+    set_queue_observer, set_strict_mode, spawn_local, strict_borrow, strict_borrow_mut, JsFuture,
+    QueueObserver,
+};
+use wasm_bindgen_test::*;
+
+struct RecordHeldBorrows(Rc<RefCell<Vec<u32>>>);
+
+impl QueueObserver for RecordHeldBorrows {
+    fn borrow_held_across_await(&self, _task_id: u64, location: &'static Location<'static>) {
+        self.0.borrow_mut().push(location.line());
+    }
+}
+
+async fn yield_now() {
+    JsFuture::from(js_sys::Promise::resolve(&JsValue::undefined())).await.unwrap();
+}
+
+#[wasm_bindgen_test]
+async fn borrows_held_across_an_await_are_reported() {
+    let reports = Rc::new(RefCell::new(Vec::new()));
+    set_queue_observer(Some(Box::new(RecordHeldBorrows(reports.clone()))));
+    set_strict_mode(true);
+
+    let cell = Rc::new(RefCell::new(0));
+    let (tx, rx) = oneshot::channel();
+    spawn_local(async move {
+        // Released before the await, which is fine.
+        *strict_borrow_mut(&cell) += 1;
+        yield_now().await;
+
+        let (held, line) = (strict_borrow(&cell), line!());
+        yield_now().await;
+        yield_now().await;
+        drop(held);
+        tx.send(line).unwrap();
+    });
+    let line = rx.await.unwrap();
+
+    set_strict_mode(false);
+    set_queue_observer(None);
+
+    // Reported once, even though it was held across two awaits.
+    assert_eq!(*reports.borrow(), [line]);
+}
@@ -0,0 +1,55 @@
+#![cfg(target_arch = "wasm32")]
+
+wasm_bindgen_test::wasm_bindgen_test_configure!(run_in_browser);
+
+use futures_channel::oneshot;
+use js_sys::{Array, Function, Promise};
+use wasm_bindgen::prelude::*;
+use wasm_bindgen::JsCast;
+use wasm_bindgen_futures::{spawn_local, JsFuture};
+use wasm_bindgen_test::*;
+
+#[wasm_bindgen(inline_js = "
+    export function catch_microtask_errors() {
+        const errors = [];
+        const queueMicrotask = globalThis.queueMicrotask;
+
+        // Record what microtasks throw rather than letting it fail the test.
+        globalThis.queueMicrotask = (callback) => queueMicrotask(() => {
+            try {
+                callback();
+            } catch (e) {
+                errors.push(e);
+            }
+        });
+
+        return () => {
+            globalThis.queueMicrotask = queueMicrotask;
+            return errors;
+        };
+    }
+")]
+extern "C" {
+    fn catch_microtask_errors() -> Function;
+}
+
+#[wasm_bindgen_test]
+async fn errors_from_plain_tasks_are_rethrown() {
+    let stop_catching = catch_microtask_errors();
+
+    spawn_local(async { wasm_bindgen::throw_str("task failed") });
+    let (tx, rx) = oneshot::channel();
+    spawn_local(async move { tx.send(()).unwrap() });
+    rx.await.unwrap();
+
+    // The error is rethrown from a microtask queued by the tick, which runs
+    // before this one.
+    JsFuture::from(Promise::resolve(&JsValue::undefined()))
+        .await
+        .unwrap();
+
+    let errors = Array::from(&stop_catching.call0(&JsValue::undefined()).unwrap());
+    assert_eq!(errors.length(), 1);
+    let error = errors.get(0).dyn_into::<js_sys::Error>().unwrap();
+    assert_eq!(error.message(), "task failed");
+}
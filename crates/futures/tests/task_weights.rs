@@ -0,0 +1,64 @@
+#![cfg(all(target_arch = "wasm32", feature = "task-weights"))]
+
+wasm_bindgen_test::wasm_bindgen_test_configure!(run_in_browser);
+
+use futures_channel::oneshot;
+use std::cell::RefCell;
+use std::rc::Rc;
+use wasm_bindgen::prelude::*;
+use wasm_bindgen_futures::{__set_scheduling_counters, spawn_local, spawn_local_priority, JsFuture};
+use wasm_bindgen_test::*;
+
+#[wasm_bindgen]
+extern "C" {
+    fn setTimeout(c: &Closure<dyn FnMut()>);
+}
+
+#[wasm_bindgen_test]
+async fn heavier_tasks_run_first() {
+    let order = Rc::new(RefCell::new(Vec::new()));
+    let (tx, rx) = oneshot::channel();
+
+    // Spawn from a macrotask so that they all start out queued together.
+    let log = order.clone();
+    let mut tx = Some(tx);
+    let closure = Closure::wrap(Box::new(move || {
+        for &(name, weight) in &[("a", 1), ("b", 5), ("c", 3), ("d", 5)] {
+            let log = log.clone();
+            spawn_local_priority(async move { log.borrow_mut().push(name) }, weight);
+        }
+
+        let log = log.clone();
+        let tx = tx.take().unwrap();
+        spawn_local(async move {
+            log.borrow_mut().push("unweighted");
+            tx.send(()).unwrap();
+        });
+    }) as Box<dyn FnMut()>);
+    setTimeout(&closure);
+    closure.forget();
+
+    rx.await.unwrap();
+    assert_eq!(*order.borrow(), ["b", "d", "c", "a", "unweighted"]);
+}
+
+#[wasm_bindgen_test]
+async fn equal_weights_keep_their_order_across_wraparound() {
+    let order = Rc::new(RefCell::new(Vec::new()));
+    let (tx, rx) = oneshot::channel();
+
+    // The tasks' sequence numbers straddle the point where they wrap.
+    __set_scheduling_counters(u64::MAX - 1);
+    for i in 0..4 {
+        let log = order.clone();
+        spawn_local_priority(async move { log.borrow_mut().push(i) }, 1);
+    }
+    spawn_local(async move { tx.send(()).unwrap() });
+    rx.await.unwrap();
+    assert_eq!(*order.borrow(), [0, 1, 2, 3]);
+
+    // Ticks go on counting past the wraparound too.
+    for _ in 0..3 {
+        JsFuture::from(js_sys::Promise::resolve(&JsValue::undefined())).await.unwrap();
+    }
+}
@@ -3,8 +3,22 @@
 wasm_bindgen_test::wasm_bindgen_test_configure!(run_in_browser);
 
 use futures_channel::oneshot;
+use std::cell::Cell;
+use std::rc::Rc;
+use std::task::{Poll, Waker};
 use wasm_bindgen::prelude::*;
-use wasm_bindgen_futures::{future_to_promise, spawn_local, JsFuture};
+use wasm_bindgen::JsCast;
+use wasm_bindgen_futures::{
+    __set_scheduling_counters, abort_current_task, active_macrotask_scheduler, active_scheduler,
+    boost_current_task_next_wake, drain_high_priority_only, drain_on_page_hide, drain_stale_tasks,
+    drive_n, finally, flush_microtasks, future_to_promise, inspect, pause_normal, poll_fn,
+    promise_with_resolvers, queue_depths, resume_normal, scoped_coop_budget,
+    set_default_spawn_priority, set_jsfuture_coop, set_tick_epilogue, set_tick_prologue,
+    set_unhandled_rejection_hook, set_wake_mode, spawn_local, spawn_local_high_priority,
+    spawn_local_lane, spawn_local_with_first_yield, spawn_local_with_handle,
+    spawn_local_with_signal, swap_coop_budget, until_idle, with_manual_wakes, Barrier, FirstYield,
+    JsFuture, Priority, SchedulerKind, WakeMode,
+};
 use wasm_bindgen_test::*;
 
 #[wasm_bindgen_test]
@@ -21,6 +35,12 @@ async fn promise_reject_is_error_future() {
     assert_eq!(e, 42);
 }
 
+#[wasm_bindgen_test]
+async fn ready_futures_skip_the_promise() {
+    assert_eq!(JsFuture::resolved(JsValue::from(42)).await, Ok(JsValue::from(42)));
+    assert_eq!(JsFuture::rejected(JsValue::from(42)).await, Err(JsValue::from(42)));
+}
+
 #[wasm_bindgen_test]
 async fn ok_future_is_resolved_promise() {
     let p = future_to_promise(async { Ok(JsValue::from(42)) });
@@ -35,6 +55,36 @@ async fn error_future_is_rejected_promise() {
     assert_eq!(e, 42);
 }
 
+/// Returns how JS sees `promise` settle, through `then` and `catch`
+/// callbacks.
+async fn observe_from_js(promise: js_sys::Promise) -> Result<JsValue, JsValue> {
+    let (tx, rx) = oneshot::channel();
+    let tx = Rc::new(std::cell::RefCell::new(Some(tx)));
+    let on_fulfilled = {
+        let tx = Rc::clone(&tx);
+        Closure::once(move |value: JsValue| {
+            tx.borrow_mut().take().unwrap().send(Ok(value)).unwrap();
+        })
+    };
+    let on_rejected = Closure::once(move |error: JsValue| {
+        tx.borrow_mut().take().unwrap().send(Err(error)).unwrap();
+    });
+    let _ = promise.then(&on_fulfilled).catch(&on_rejected);
+
+    let result = rx.await.unwrap();
+    drop((on_fulfilled, on_rejected));
+    result
+}
+
+#[wasm_bindgen_test]
+async fn promise_callbacks_see_the_future_result() {
+    let ok = future_to_promise(async { Ok(JsValue::from("done")) });
+    assert_eq!(observe_from_js(ok).await, Ok(JsValue::from("done")));
+
+    let err = future_to_promise(async { Err(JsValue::from("failed")) });
+    assert_eq!(observe_from_js(err).await, Err(JsValue::from("failed")));
+}
+
 #[wasm_bindgen_test]
 fn debug_jsfuture() {
     let p = js_sys::Promise::resolve(&JsValue::from(42));
@@ -88,3 +138,1014 @@ async fn can_create_multiple_futures_from_same_promise() {
     a.await.unwrap();
     b.await.unwrap();
 }
+
+#[wasm_bindgen_test]
+async fn poll_fn_is_ready_on_second_poll() {
+    let mut polls = 0;
+    let value = poll_fn(|cx| {
+        polls += 1;
+        if polls == 2 {
+            Poll::Ready(42)
+        } else {
+            cx.waker().wake_by_ref();
+            Poll::Pending
+        }
+    })
+    .await;
+    assert_eq!(value, 42);
+    assert_eq!(polls, 2);
+}
+
+#[wasm_bindgen_test]
+async fn poll_fn_sees_the_same_waker_on_every_poll() {
+    let mut stored: Option<Waker> = None;
+    let mut stores = 0;
+    let mut polls = 0;
+    poll_fn(|cx| {
+        match &stored {
+            Some(waker) if waker.will_wake(cx.waker()) => {}
+            _ => {
+                stored = Some(cx.waker().clone());
+                stores += 1;
+            }
+        }
+        polls += 1;
+        if polls == 3 {
+            Poll::Ready(())
+        } else {
+            stored.as_ref().unwrap().wake_by_ref();
+            Poll::Pending
+        }
+    })
+    .await;
+    assert_eq!(stores, 1);
+}
+
+#[wasm_bindgen_test]
+fn poll_fn_is_send_and_sync_when_its_closure_is() {
+    fn assert_send_sync<T: Send + Sync>(_: &T) {}
+    assert_send_sync(&poll_fn(|_| Poll::Ready(())));
+}
+
+#[wasm_bindgen_test]
+async fn drive_n_runs_bounded_batches() {
+    let (tx, rx) = oneshot::channel();
+    let mut tx = Some(tx);
+
+    // Drive from a timeout, as no tasks can be driven from inside a task.
+    let closure = Closure::wrap(Box::new(move || {
+        let ran = Rc::new(Cell::new(0));
+        for _ in 0..5 {
+            let ran = ran.clone();
+            spawn_local(async move {
+                ran.set(ran.get() + 1);
+            });
+        }
+
+        let first = drive_n(2);
+        let after_first = ran.get();
+        let second = drive_n(2);
+        let after_second = ran.get();
+
+        let tx = tx.take().unwrap();
+        spawn_local(async move {
+            tx.send((first, after_first, second, after_second, ran.get()))
+                .unwrap();
+        });
+    }) as Box<dyn FnMut()>);
+    setTimeout(&closure);
+    closure.forget();
+
+    assert_eq!(rx.await.unwrap(), (2, 2, 2, 4, 5));
+}
+
+#[wasm_bindgen_test]
+async fn drive_n_survives_a_throwing_task() {
+    let (tx, rx) = oneshot::channel();
+    let mut tx = Some(tx);
+
+    let closure = Closure::wrap(Box::new(move || {
+        let ran = Rc::new(Cell::new(0));
+        let failed = future_to_promise(async { wasm_bindgen::throw_str("task failed") });
+        {
+            let ran = ran.clone();
+            spawn_local(async move {
+                ran.set(ran.get() + 1);
+            });
+        }
+
+        let driven = drive_n(5);
+
+        // Tasks still get ticks afterwards.
+        let tx = tx.take().unwrap();
+        spawn_local(async move {
+            tx.send((driven, ran.get(), failed)).unwrap();
+        });
+    }) as Box<dyn FnMut()>);
+    setTimeout(&closure);
+    closure.forget();
+
+    let (driven, ran, failed) = rx.await.unwrap();
+    assert_eq!((driven, ran), (2, 1));
+    let error = JsFuture::from(failed).await.unwrap_err();
+    assert_eq!(error.unchecked_into::<js_sys::Error>().message(), "task failed");
+}
+
+#[wasm_bindgen_test]
+async fn abort_signal_drops_spawned_future() {
+    struct SetOnDrop(Rc<Cell<bool>>);
+
+    impl Drop for SetOnDrop {
+        fn drop(&mut self) {
+            self.0.set(true);
+        }
+    }
+
+    let controller = web_sys::AbortController::new().unwrap();
+    let polls = Rc::new(Cell::new(0));
+    let dropped = Rc::new(Cell::new(false));
+
+    let guard = SetOnDrop(dropped.clone());
+    let counter = polls.clone();
+    spawn_local_with_signal(
+        poll_fn(move |_| {
+            let _guard = &guard;
+            counter.set(counter.get() + 1);
+            Poll::<()>::Pending
+        }),
+        controller.signal(),
+    );
+
+    let tick = || JsFuture::from(js_sys::Promise::resolve(&JsValue::undefined()));
+    tick().await.unwrap();
+    assert_eq!(polls.get(), 1);
+    assert!(!dropped.get());
+
+    controller.abort();
+    tick().await.unwrap();
+    assert_eq!(polls.get(), 1);
+    assert!(dropped.get());
+}
+
+#[wasm_bindgen_test]
+async fn flush_from_a_task_is_a_no_op() {
+    let order = Rc::new(std::cell::RefCell::new(Vec::new()));
+    let (tx, rx) = oneshot::channel();
+
+    let log = order.clone();
+    spawn_local(async move {
+        log.borrow_mut().push(1);
+        let flushed = flush_microtasks();
+        log.borrow_mut().push(2);
+        tx.send(flushed).unwrap();
+    });
+    for i in 3..5 {
+        let log = order.clone();
+        spawn_local(async move {
+            log.borrow_mut().push(i);
+        });
+    }
+
+    assert!(!rx.await.unwrap());
+    JsFuture::from(js_sys::Promise::resolve(&JsValue::undefined()))
+        .await
+        .unwrap();
+    assert_eq!(*order.borrow(), [1, 2, 3, 4]);
+}
+
+#[wasm_bindgen_test]
+fn coop_budget_guard_restores_previous_budget() {
+    let original = swap_coop_budget(10);
+    {
+        let _outer = scoped_coop_budget(100);
+        {
+            let _inner = scoped_coop_budget(1000);
+            assert_eq!(swap_coop_budget(1000), 1000);
+        }
+        assert_eq!(swap_coop_budget(100), 100);
+    }
+    assert_eq!(swap_coop_budget(original), 10);
+}
+
+#[wasm_bindgen_test]
+async fn tick_hooks_run_once_per_tick() {
+    let prologues = Rc::new(Cell::new(0));
+    let epilogues = Rc::new(Cell::new(0));
+    let (tx, rx) = oneshot::channel();
+
+    let counter = prologues.clone();
+    set_tick_prologue(Some(Box::new(move || counter.set(counter.get() + 1))));
+    let counter = epilogues.clone();
+    set_tick_epilogue(Some(Box::new(move || counter.set(counter.get() + 1))));
+
+    // Spawn a batch from one macrotask, which runs in a single tick, and
+    // read the counts from the next one.
+    let counts = (prologues.clone(), epilogues.clone());
+    let start = Closure::wrap(Box::new(move || {
+        counts.0.set(0);
+        counts.1.set(0);
+        for _ in 0..5 {
+            spawn_local(async {});
+        }
+    }) as Box<dyn FnMut()>);
+
+    let mut tx = Some(tx);
+    let check = Closure::wrap(Box::new(move || {
+        let counts = (prologues.get(), epilogues.get());
+        tx.take().unwrap().send(counts).unwrap();
+    }) as Box<dyn FnMut()>);
+
+    setTimeout(&start);
+    setTimeout(&check);
+    start.forget();
+    check.forget();
+
+    let counts = rx.await.unwrap();
+    set_tick_prologue(None);
+    set_tick_epilogue(None);
+    assert_eq!(counts, (1, 1));
+}
+
+#[wasm_bindgen_test]
+async fn dropped_rejections_are_reported() {
+    let reported = Rc::new(std::cell::RefCell::new(Vec::new()));
+    let log = reported.clone();
+    set_unhandled_rejection_hook(Some(Box::new(move |error| {
+        log.borrow_mut().push(error.clone());
+    })));
+
+    // Observed rejections aren't reported.
+    JsFuture::rejected(JsValue::from(1)).await.unwrap_err();
+
+    // Dropped holding a rejection is reported, dropped before the rejection
+    // arrives isn't.
+    drop(JsFuture::rejected(JsValue::from(2)));
+    drop(JsFuture::from(js_sys::Promise::reject(&JsValue::from(3))));
+    JsFuture::from(js_sys::Promise::resolve(&JsValue::undefined()))
+        .await
+        .unwrap();
+
+    set_unhandled_rejection_hook(None);
+    assert_eq!(*reported.borrow(), [JsValue::from(2)]);
+}
+
+#[wasm_bindgen_test]
+async fn aborting_on_drop_is_not_an_unhandled_rejection() {
+    let reported = Rc::new(Cell::new(0));
+    let count = reported.clone();
+    set_unhandled_rejection_hook(Some(Box::new(move |_| count.set(count.get() + 1))));
+
+    // Rejects as soon as the controller is aborted, like `fetch` does.
+    let controller = web_sys::AbortController::new().unwrap();
+    let signal = controller.signal();
+    let promise = js_sys::Promise::new(&mut |_, reject| {
+        let on_abort = Closure::once_into_js(move || {
+            let _ = reject.call0(&JsValue::undefined());
+        });
+        signal
+            .add_event_listener_with_callback("abort", on_abort.unchecked_ref())
+            .unwrap();
+    });
+    drop(JsFuture::from(promise).with_abort_on_drop(controller.clone()));
+    assert!(controller.signal().aborted());
+    JsFuture::from(js_sys::Promise::resolve(&JsValue::undefined()))
+        .await
+        .unwrap();
+
+    set_unhandled_rejection_hook(None);
+    assert_eq!(reported.get(), 0);
+}
+
+#[wasm_bindgen_test]
+async fn barrier_releases_tasks_together() {
+    let barrier = Barrier::new(3);
+    let done = Barrier::new(4);
+    let arrived = Rc::new(Cell::new(0));
+    let leaders = Rc::new(Cell::new(0));
+    let seen = Rc::new(std::cell::RefCell::new(Vec::new()));
+
+    for i in 0..3 {
+        let barrier = barrier.clone();
+        let done = done.clone();
+        let arrived = arrived.clone();
+        let leaders = leaders.clone();
+        let seen = seen.clone();
+        spawn_local(async move {
+            // Two generations, with the tasks arriving in a different order
+            // each time.
+            for generation in 1..=2 {
+                for _ in 0..(i + generation) % 3 {
+                    JsFuture::from(js_sys::Promise::resolve(&JsValue::undefined()))
+                        .await
+                        .unwrap();
+                }
+                arrived.set(arrived.get() + 1);
+                if barrier.wait().await.is_leader() {
+                    leaders.set(leaders.get() + 1);
+                }
+                seen.borrow_mut().push(arrived.get());
+            }
+            done.wait().await;
+        });
+    }
+    done.wait().await;
+
+    // Nobody gets past the barrier before all three tasks have reached it.
+    let seen = seen.borrow();
+    assert_eq!(seen.len(), 6);
+    assert!(seen[..3].iter().all(|&n| n >= 3));
+    assert!(seen[3..].iter().all(|&n| n == 6));
+    assert_eq!(leaders.get(), 2);
+}
+
+#[wasm_bindgen_test]
+async fn spawning_from_drop_runs_the_cleanup() {
+    struct CleanupOnDrop(Option<oneshot::Sender<bool>>);
+
+    impl Drop for CleanupOnDrop {
+        fn drop(&mut self) {
+            // Still inside the finished task, so there's nothing to flush.
+            let flushed = flush_microtasks();
+            let tx = self.0.take().unwrap();
+            spawn_local(async move {
+                tx.send(flushed).unwrap();
+            });
+        }
+    }
+
+    let (tx, rx) = oneshot::channel();
+    let cleanup = CleanupOnDrop(Some(tx));
+    spawn_local(async move {
+        let _cleanup = cleanup;
+    });
+
+    assert!(!rx.await.unwrap());
+}
+
+#[wasm_bindgen_test]
+async fn inspect_passes_the_result_through() {
+    let seen = Rc::new(Cell::new(None));
+    let promise = js_sys::Promise::resolve(&JsValue::from(7));
+
+    let result = {
+        let seen = Rc::clone(&seen);
+        inspect(JsFuture::from(promise), move |result| {
+            seen.set(result.as_ref().ok().and_then(|value| value.as_f64()));
+        })
+        .await
+    };
+
+    assert_eq!(seen.get(), Some(7.0));
+    assert_eq!(result, Ok(JsValue::from(7)));
+}
+
+#[wasm_bindgen_test]
+async fn finally_runs_once_on_completion() {
+    let runs = Rc::new(Cell::new(0));
+
+    let mut future = {
+        let runs = Rc::clone(&runs);
+        Box::pin(finally(JsFuture::resolved(JsValue::from(1)), move || {
+            runs.set(runs.get() + 1)
+        }))
+    };
+    assert_eq!((&mut future).await, Ok(JsValue::from(1)));
+    assert_eq!(runs.get(), 1);
+
+    // Dropping the completed future doesn't run it again.
+    drop(future);
+    assert_eq!(runs.get(), 1);
+}
+
+#[wasm_bindgen_test]
+fn finally_runs_when_dropped_early() {
+    let runs = Rc::new(Cell::new(0));
+
+    let future = {
+        let runs = Rc::clone(&runs);
+        finally(JsFuture::from(js_sys::Promise::new(&mut |_, _| {})), move || {
+            runs.set(runs.get() + 1)
+        })
+    };
+    assert_eq!(runs.get(), 0);
+    drop(future);
+    assert_eq!(runs.get(), 1);
+}
+
+#[wasm_bindgen_test]
+fn browsers_use_the_cheapest_schedulers() {
+    assert_eq!(active_scheduler(), Some(SchedulerKind::QueueMicrotask));
+    assert_eq!(active_macrotask_scheduler(), Some(SchedulerKind::MessageChannel));
+}
+
+#[wasm_bindgen_test]
+async fn pausing_normal_tasks_keeps_high_priority_running() {
+    let normal_ran = Rc::new(Cell::new(false));
+    let (normal_tx, normal_rx) = oneshot::channel();
+    let (high_tx, high_rx) = oneshot::channel();
+
+    pause_normal();
+    {
+        let normal_ran = Rc::clone(&normal_ran);
+        spawn_local(async move {
+            normal_ran.set(true);
+            normal_tx.send(()).unwrap();
+        });
+    }
+    {
+        let normal_ran = Rc::clone(&normal_ran);
+        spawn_local_high_priority(async move {
+            high_tx.send(normal_ran.get()).unwrap();
+            resume_normal().unwrap();
+        });
+    }
+
+    // The high priority task ran while the normal one waited, and resuming
+    // let the normal one (and this test) continue.
+    assert!(!high_rx.await.unwrap());
+    normal_rx.await.unwrap();
+    assert!(normal_ran.get());
+}
+
+#[wasm_bindgen_test]
+async fn promise_callbacks_see_the_effects_of_the_tick() {
+    let effects = Rc::new(Cell::new(0));
+
+    let promise = {
+        let effects = Rc::clone(&effects);
+        future_to_promise(async move {
+            JsFuture::from(js_sys::Promise::resolve(&JsValue::UNDEFINED)).await?;
+            effects.set(effects.get() + 1);
+
+            let spawned = Rc::clone(&effects);
+            spawn_local(async move { spawned.set(spawned.get() + 1) });
+            Ok(JsValue::UNDEFINED)
+        })
+    };
+
+    let (tx, rx) = oneshot::channel();
+    let on_resolve = Closure::once(move |_: JsValue| tx.send(effects.get()).unwrap());
+    let _ = promise.then(&on_resolve);
+
+    // Both the continuation and the task it spawned ran first.
+    assert_eq!(rx.await.unwrap(), 2);
+}
+
+#[wasm_bindgen_test]
+async fn single_task_ticks_run_each_task_once() {
+    const TICKS: usize = 1000;
+    let runs = Rc::new((0..TICKS).map(|_| Cell::new(0)).collect::<Vec<_>>());
+
+    let start = js_sys::Date::now();
+    for i in 0..TICKS {
+        let runs = Rc::clone(&runs);
+        spawn_local(async move { runs[i].set(runs[i].get() + 1) });
+
+        // The spawned task gets a tick to itself, as does this one once the
+        // promise settles.
+        JsFuture::from(js_sys::Promise::resolve(&JsValue::UNDEFINED))
+            .await
+            .unwrap();
+    }
+    console_log!(
+        "{} single task ticks took {}ms",
+        TICKS,
+        js_sys::Date::now() - start
+    );
+
+    assert!(runs.iter().all(|runs| runs.get() == 1));
+}
+
+#[wasm_bindgen_test]
+fn queue_depths_count_each_priority() {
+    let before = queue_depths();
+
+    // Nothing runs until this test yields.
+    for _ in 0..2 {
+        spawn_local_high_priority(async {});
+    }
+    for _ in 0..3 {
+        spawn_local(async {});
+    }
+
+    let after = queue_depths();
+    assert_eq!(after.high, before.high + 2);
+    assert_eq!(after.normal, before.normal + 3);
+}
+
+#[wasm_bindgen_test]
+fn draining_high_priority_leaves_normal_tasks_queued() {
+    let ran = Rc::new(std::cell::RefCell::new(Vec::new()));
+    for name in &["normal", "high", "also high"] {
+        let ran = ran.clone();
+        let task = async move { ran.borrow_mut().push(*name) };
+        if name.contains("high") {
+            spawn_local_high_priority(task);
+        } else {
+            spawn_local(task);
+        }
+    }
+    let before = queue_depths();
+
+    assert_eq!(drain_high_priority_only(), 2);
+    assert_eq!(*ran.borrow(), ["high", "also high"]);
+    assert_eq!(queue_depths().high, 0);
+    assert_eq!(queue_depths().normal, before.normal);
+}
+
+#[wasm_bindgen_test]
+fn draining_stale_tasks_only_runs_old_ones() {
+    let ran = Rc::new(std::cell::RefCell::new(Vec::new()));
+
+    with_manual_wakes(|driver| {
+        driver.run_ready();
+
+        // Queue each task on a different tick.
+        for &(name, tick) in &[("old", 100), ("older", 90), ("new", 108)] {
+            __set_scheduling_counters(tick);
+            let ran = ran.clone();
+            spawn_local(async move { ran.borrow_mut().push(name) });
+        }
+        __set_scheduling_counters(110);
+
+        assert_eq!(drain_stale_tasks(5), 2);
+        assert_eq!(*ran.borrow(), ["older", "old"]);
+        assert_eq!(drain_stale_tasks(5), 0);
+        assert_eq!(driver.run_ready(), 1);
+        assert_eq!(*ran.borrow(), ["older", "old", "new"]);
+    });
+}
+
+#[wasm_bindgen_test]
+fn default_spawn_priority_applies_to_spawn_local() {
+    let before = queue_depths();
+
+    set_default_spawn_priority(Priority::High);
+    spawn_local(async {});
+    set_default_spawn_priority(Priority::Normal);
+    spawn_local(async {});
+
+    let after = queue_depths();
+    assert_eq!(after.high, before.high + 1);
+    assert_eq!(after.normal, before.normal + 1);
+}
+
+// Weighted tasks are never shuffled.
+#[cfg(not(feature = "task-weights"))]
+#[wasm_bindgen_test]
+fn shuffled_order_is_reproducible() {
+    use wasm_bindgen_futures::set_shuffle_seed;
+
+    fn run_order(seed: u64) -> Vec<usize> {
+        let order = Rc::new(std::cell::RefCell::new(Vec::new()));
+        set_shuffle_seed(Some(seed));
+        for i in 0..8 {
+            let order = Rc::clone(&order);
+            spawn_local(async move { order.borrow_mut().push(i) });
+        }
+        assert!(flush_microtasks());
+        set_shuffle_seed(None);
+
+        let order = order.borrow().clone();
+        order
+    }
+
+    let first = run_order(7);
+    assert_eq!(run_order(7), first);
+
+    let mut sorted = first.clone();
+    sorted.sort();
+    assert_eq!(sorted, (0..8).collect::<Vec<_>>());
+    assert_ne!(first, sorted);
+}
+
+#[wasm_bindgen_test]
+async fn first_yield_fires_at_the_first_await() {
+    let events = Rc::new(std::cell::RefCell::new(Vec::new()));
+    let (tx, rx) = oneshot::channel();
+
+    let future = {
+        let events = Rc::clone(&events);
+        async move {
+            events.borrow_mut().push("started");
+            JsFuture::from(js_sys::Promise::resolve(&JsValue::UNDEFINED))
+                .await
+                .unwrap();
+            events.borrow_mut().push("finished");
+            tx.send(()).unwrap();
+        }
+    };
+    let on_first_yield = {
+        let events = Rc::clone(&events);
+        move |first: FirstYield| {
+            assert_eq!(first, FirstYield::Suspended);
+            events.borrow_mut().push("yielded");
+        }
+    };
+    spawn_local_with_first_yield(future, on_first_yield);
+
+    rx.await.unwrap();
+    assert_eq!(*events.borrow(), ["started", "yielded", "finished"]);
+}
+
+#[wasm_bindgen_test]
+async fn first_yield_reports_synchronous_completion() {
+    let (tx, rx) = oneshot::channel();
+    spawn_local_with_first_yield(async {}, move |first| tx.send(first).unwrap());
+    assert_eq!(rx.await.unwrap(), FirstYield::CompletedSynchronously);
+}
+
+#[wasm_bindgen_test]
+fn page_hide_runs_pending_tasks() {
+    fn spawn_and_hide_page() -> bool {
+        let ran = Rc::new(Cell::new(false));
+        let task_ran = Rc::clone(&ran);
+        spawn_local(async move { task_ran.set(true) });
+
+        let window = js_sys::global().unchecked_into::<web_sys::EventTarget>();
+        let event = web_sys::Event::new("pagehide").unwrap();
+        window.dispatch_event(&event).unwrap();
+        ran.get()
+    }
+
+    // This test isn't a task, so nothing else runs the spawned tasks before
+    // it returns.
+    drain_on_page_hide(true);
+    assert!(spawn_and_hide_page());
+
+    drain_on_page_hide(false);
+    assert!(!spawn_and_hide_page());
+}
+
+#[cfg(debug_assertions)]
+#[wasm_bindgen_test]
+async fn bounded_polls_trips_on_a_spinning_future() {
+    use wasm_bindgen_futures::{bounded_polls, PollLimitExceeded};
+
+    let polls = Rc::new(Cell::new(0));
+    let spinning = {
+        let polls = Rc::clone(&polls);
+        poll_fn(move |cx| {
+            polls.set(polls.get() + 1);
+            cx.waker().wake_by_ref();
+            Poll::<()>::Pending
+        })
+    };
+
+    let result = bounded_polls(spinning, 100).await;
+    assert_eq!(result, Err(PollLimitExceeded { max: 100 }));
+    assert_eq!(polls.get(), 100);
+
+    assert_eq!(bounded_polls(async { 1 }, 1).await, Ok(1));
+}
+
+#[wasm_bindgen_test]
+async fn biased_races_prefer_the_primary() {
+    use wasm_bindgen_futures::{race2_biased, RaceWinner};
+
+    assert_eq!(race2_biased(async { 1 }, async { 2 }).await, RaceWinner::Primary(1));
+
+    let pending = std::future::pending::<u32>();
+    assert_eq!(race2_biased(pending, async { 2 }).await, RaceWinner::Secondary(2));
+}
+
+#[wasm_bindgen_test]
+async fn cooperative_jsfutures_let_other_tasks_interleave() {
+    let events = Rc::new(std::cell::RefCell::new(Vec::new()));
+    let (tx, rx) = oneshot::channel();
+
+    set_jsfuture_coop(true);
+    let _budget = scoped_coop_budget(2);
+    {
+        let events = Rc::clone(&events);
+        spawn_local(async move {
+            for _ in 0..10 {
+                JsFuture::resolved(JsValue::UNDEFINED).await.unwrap();
+                events.borrow_mut().push("chain");
+            }
+            tx.send(()).unwrap();
+        });
+    }
+    {
+        let events = Rc::clone(&events);
+        spawn_local(async move { events.borrow_mut().push("other") });
+    }
+
+    rx.await.unwrap();
+    set_jsfuture_coop(false);
+
+    // The chain yielded after two steps, letting the other task run.
+    let events = events.borrow();
+    assert_eq!(events.len(), 11);
+    assert_eq!(events.iter().position(|e| *e == "other"), Some(2));
+}
+
+#[wasm_bindgen_test]
+async fn weak_join_handles_see_the_task_finish() {
+    let (tx, rx) = oneshot::channel::<()>();
+    let handle = spawn_local_with_handle(async move {
+        rx.await.unwrap();
+    });
+    let weak = handle.downgrade();
+    drop(handle);
+
+    assert!(!weak.is_finished());
+    assert!(weak.upgrade().is_some());
+
+    tx.send(()).unwrap();
+    JsFuture::from(js_sys::Promise::resolve(&JsValue::undefined())).await.unwrap();
+
+    // There's nothing left to abort.
+    assert!(weak.is_finished());
+    assert!(weak.upgrade().is_none());
+}
+
+#[wasm_bindgen_test]
+async fn aborting_a_join_handle_drops_the_future() {
+    struct SetOnDrop(Rc<Cell<bool>>);
+
+    impl Drop for SetOnDrop {
+        fn drop(&mut self) {
+            self.0.set(true);
+        }
+    }
+
+    let dropped = Rc::new(Cell::new(false));
+    let guard = SetOnDrop(dropped.clone());
+    let handle = spawn_local_with_handle(poll_fn(move |_| {
+        let _guard = &guard;
+        Poll::<()>::Pending
+    }));
+
+    handle.abort();
+    assert!(!handle.is_finished());
+    JsFuture::from(js_sys::Promise::resolve(&JsValue::undefined())).await.unwrap();
+
+    assert!(dropped.get());
+    assert!(handle.is_finished());
+}
+
+#[wasm_bindgen_test]
+async fn macrotask_wakes_resume_after_microtask_ones() {
+    let order = Rc::new(std::cell::RefCell::new(Vec::new()));
+    let (wake_macrotask, macrotask_woken) = oneshot::channel::<()>();
+    let (wake_microtask, microtask_woken) = oneshot::channel::<()>();
+    let (done_tx, done) = oneshot::channel();
+
+    {
+        let order = order.clone();
+        spawn_local(async move {
+            assert!(set_wake_mode(WakeMode::Macrotask));
+            macrotask_woken.await.unwrap();
+            order.borrow_mut().push("macrotask");
+            done_tx.send(()).unwrap();
+        });
+    }
+    {
+        let order = order.clone();
+        spawn_local(async move {
+            microtask_woken.await.unwrap();
+            order.borrow_mut().push("microtask");
+        });
+    }
+    JsFuture::from(js_sys::Promise::resolve(&JsValue::undefined())).await.unwrap();
+
+    // Woken first, but the macrotask mode task has to wait for a timer.
+    wake_macrotask.send(()).unwrap();
+    wake_microtask.send(()).unwrap();
+    done.await.unwrap();
+
+    assert_eq!(*order.borrow(), ["microtask", "macrotask"]);
+}
+
+#[wasm_bindgen_test]
+async fn boosted_wakes_run_ahead_of_normal_tasks() {
+    let order = Rc::new(std::cell::RefCell::new(Vec::new()));
+    let (wake, woken) = oneshot::channel::<()>();
+    let (done_tx, done) = oneshot::channel();
+
+    {
+        let order = order.clone();
+        spawn_local(async move {
+            assert!(boost_current_task_next_wake());
+            woken.await.unwrap();
+            order.borrow_mut().push("boosted");
+        });
+    }
+    JsFuture::from(js_sys::Promise::resolve(&JsValue::undefined())).await.unwrap();
+
+    for name in &["first", "second"] {
+        let order = order.clone();
+        spawn_local(async move { order.borrow_mut().push(*name) });
+    }
+    {
+        let order = order.clone();
+        spawn_local(async move {
+            order.borrow_mut().push("last");
+            done_tx.send(()).unwrap();
+        });
+    }
+    // Woken after the others were queued, but it still goes first.
+    wake.send(()).unwrap();
+    done.await.unwrap();
+
+    assert_eq!(*order.borrow(), ["boosted", "first", "second", "last"]);
+}
+
+#[wasm_bindgen_test]
+async fn lanes_take_turns() {
+    let order = Rc::new(std::cell::RefCell::new(Vec::new()));
+    let (tx, rx) = oneshot::channel();
+
+    for i in 0..20 {
+        let order = order.clone();
+        spawn_local_lane(1, async move { order.borrow_mut().push((1, i)) });
+    }
+    {
+        let order = order.clone();
+        spawn_local_lane(2, async move {
+            order.borrow_mut().push((2, 0));
+            tx.send(()).unwrap();
+        });
+    }
+    rx.await.unwrap();
+
+    // The quiet lane only waited for the chatty lane's first task.
+    let order = order.borrow();
+    assert_eq!(order[..2], [(1, 0), (2, 0)]);
+}
+
+#[wasm_bindgen_test]
+async fn aborted_tasks_arent_polled_again() {
+    let polls = Rc::new(Cell::new(0));
+    {
+        let polls = polls.clone();
+        spawn_local(poll_fn(move |cx| {
+            polls.set(polls.get() + 1);
+            assert!(abort_current_task());
+            // Waking itself would normally get it polled again right away.
+            cx.waker().wake_by_ref();
+            Poll::<()>::Pending
+        }));
+    }
+
+    for _ in 0..3 {
+        JsFuture::from(js_sys::Promise::resolve(&JsValue::undefined())).await.unwrap();
+    }
+    assert_eq!(polls.get(), 1);
+}
+
+#[wasm_bindgen_test]
+fn abort_current_task_needs_a_task() {
+    assert!(!abort_current_task());
+}
+
+#[wasm_bindgen_test]
+async fn promise_with_resolvers_settles_from_outside() {
+    let (promise, resolve, _reject) = promise_with_resolvers();
+    resolve(JsValue::from(1));
+    assert_eq!(JsFuture::from(promise).await, Ok(JsValue::from(1)));
+
+    let (promise, _resolve, reject) = promise_with_resolvers();
+    let future = JsFuture::from(promise);
+    reject(JsValue::from("nope"));
+    assert_eq!(future.await, Err(JsValue::from("nope")));
+}
+
+wasm_bindgen_futures::task_local! {
+    static TRACE_ID: Cell<u32>;
+}
+
+#[wasm_bindgen_test]
+async fn task_locals_are_separate_for_each_task() {
+    let (tx1, rx1) = oneshot::channel();
+    let (tx2, rx2) = oneshot::channel();
+
+    for (id, tx) in vec![(1, tx1), (2, tx2)] {
+        spawn_local(async move {
+            assert_eq!(TRACE_ID.with(|trace_id| trace_id.get()), 0);
+            TRACE_ID.with(|trace_id| trace_id.set(id));
+            JsFuture::from(js_sys::Promise::resolve(&JsValue::undefined())).await.unwrap();
+            tx.send(TRACE_ID.with(|trace_id| trace_id.get())).unwrap();
+        });
+    }
+
+    assert_eq!(rx1.await.unwrap(), 1);
+    assert_eq!(rx2.await.unwrap(), 2);
+    assert_eq!(TRACE_ID.with(|trace_id| trace_id.get()), 0);
+}
+
+#[wasm_bindgen_test]
+fn task_locals_need_a_task() {
+    assert!(TRACE_ID.try_with(|trace_id| trace_id.get()).is_err());
+}
+
+#[wasm_bindgen_test]
+async fn until_idle_waits_for_a_chain_of_tasks() {
+    fn spawn_chain(remaining: u32, ran: Rc<Cell<u32>>) {
+        spawn_local(async move {
+            ran.set(ran.get() + 1);
+            if remaining > 1 {
+                spawn_chain(remaining - 1, ran);
+            }
+        });
+    }
+
+    let ran = Rc::new(Cell::new(0));
+    spawn_chain(5, ran.clone());
+    assert_eq!(ran.get(), 0);
+
+    until_idle().await;
+    assert_eq!(ran.get(), 5);
+}
+
+#[wasm_bindgen_test]
+async fn single_flight_runs_concurrent_requests_once() {
+    use wasm_bindgen_futures::SingleFlight;
+
+    let runs = Rc::new(Cell::new(0));
+    let flights = SingleFlight::new();
+    let fetch = || {
+        let runs = runs.clone();
+        async move {
+            runs.set(runs.get() + 1);
+            JsFuture::from(js_sys::Promise::resolve(&JsValue::from(7))).await.unwrap();
+            String::from("resource")
+        }
+    };
+
+    let first = flights.get_or_spawn("key", fetch);
+    let second = flights.get_or_spawn("key", fetch);
+    assert_eq!(flights.in_flight(), 1);
+
+    assert_eq!(first.await.as_deref(), Some("resource"));
+    assert_eq!(second.await.as_deref(), Some("resource"));
+    assert_eq!(runs.get(), 1);
+    assert_eq!(flights.in_flight(), 0);
+}
+
+#[wasm_bindgen_test]
+async fn single_flight_forgets_operations_the_executor_refused() {
+    use wasm_bindgen_futures::{set_max_queue_depth, SingleFlight};
+
+    let flights = SingleFlight::new();
+    let depths = queue_depths();
+    set_max_queue_depth(Some(depths.high + depths.normal));
+    let refused = flights.get_or_spawn("key", || async { 1 });
+    set_max_queue_depth(None);
+
+    assert_eq!(flights.in_flight(), 0);
+    assert_eq!(refused.await, None);
+    assert_eq!(flights.get_or_spawn("key", || async { 2 }).await, Some(2));
+}
+
+#[wasm_bindgen_test]
+async fn poll_once_steps_a_future() {
+    use wasm_bindgen_futures::poll_once;
+
+    let mut polls = 0;
+    let mut two_steps = Box::pin(poll_fn(move |cx| {
+        polls += 1;
+        if polls == 1 {
+            cx.waker().wake_by_ref();
+            return Poll::Pending;
+        }
+        Poll::Ready(polls)
+    }));
+
+    assert_eq!(poll_once(two_steps.as_mut()), Poll::Pending);
+    // The wakeup queued this task again, so let the queue get to it.
+    JsFuture::from(js_sys::Promise::resolve(&JsValue::undefined())).await.unwrap();
+    assert_eq!(poll_once(two_steps.as_mut()), Poll::Ready(2));
+}
+
+#[wasm_bindgen_test]
+async fn registries_abort_their_tasks_only() {
+    use wasm_bindgen_futures::{spawn_local_registered, TaskRegistry};
+
+    let registry = TaskRegistry::new();
+    let resumed = Rc::new(Cell::new(0));
+    let mut wakes = Vec::new();
+    for _ in 0..3 {
+        let (tx, rx) = oneshot::channel::<()>();
+        let resumed = resumed.clone();
+        spawn_local_registered(&registry, async move {
+            let _ = rx.await;
+            resumed.set(resumed.get() + 1);
+        });
+        wakes.push(tx);
+    }
+    let (done_tx, done) = oneshot::channel();
+    spawn_local(async move { done_tx.send(()).unwrap() });
+    assert_eq!(registry.len(), 3);
+
+    registry.abort_all();
+    for tx in wakes {
+        let _ = tx.send(());
+    }
+    done.await.unwrap();
+    JsFuture::from(js_sys::Promise::resolve(&JsValue::undefined())).await.unwrap();
+
+    assert_eq!(resumed.get(), 0);
+    assert!(registry.is_empty());
+}
@@ -0,0 +1,69 @@
+#![cfg(all(target_arch = "wasm32", feature = "tracing"))]
+
+wasm_bindgen_test::wasm_bindgen_test_configure!(run_in_browser);
+
+use futures_channel::oneshot;
+use std::sync::atomic::{AtomicUsize, Ordering::SeqCst};
+use tracing::span::{Attributes, Id, Record};
+use tracing::{Event, Metadata, Subscriber};
+use wasm_bindgen_futures::spawn_local;
+use wasm_bindgen_test::*;
+
+static TASK_SPANS_ENTERED: AtomicUsize = AtomicUsize::new(0);
+static EVENTS: AtomicUsize = AtomicUsize::new(0);
+
+const TASK_SPAN: u64 = 1;
+const OTHER_SPAN: u64 = 2;
+
+/// Counts entries into the executor's `task` spans and all events.
+struct CountingSubscriber;
+
+impl Subscriber for CountingSubscriber {
+    fn enabled(&self, _metadata: &Metadata<'_>) -> bool {
+        true
+    }
+
+    fn new_span(&self, span: &Attributes<'_>) -> Id {
+        if span.metadata().name() == "task" {
+            Id::from_u64(TASK_SPAN)
+        } else {
+            Id::from_u64(OTHER_SPAN)
+        }
+    }
+
+    fn record(&self, _span: &Id, _values: &Record<'_>) {}
+
+    fn record_follows_from(&self, _span: &Id, _follows: &Id) {}
+
+    fn event(&self, _event: &Event<'_>) {
+        EVENTS.fetch_add(1, SeqCst);
+    }
+
+    fn enter(&self, span: &Id) {
+        if span.into_u64() == TASK_SPAN {
+            TASK_SPANS_ENTERED.fetch_add(1, SeqCst);
+        }
+    }
+
+    fn exit(&self, _span: &Id) {}
+}
+
+#[wasm_bindgen_test]
+async fn span_is_entered_per_task() {
+    tracing::subscriber::set_global_default(CountingSubscriber).unwrap();
+    let entered = TASK_SPANS_ENTERED.load(SeqCst);
+    let events = EVENTS.load(SeqCst);
+
+    let (tx, rx) = oneshot::channel();
+    spawn_local(async {});
+    spawn_local(async {});
+    spawn_local(async move {
+        tx.send(()).unwrap();
+    });
+    rx.await.unwrap();
+
+    // One spawn event per task, and one span entered per poll of each task
+    // (plus polls of the test itself).
+    assert_eq!(EVENTS.load(SeqCst) - events, 3);
+    assert!(TASK_SPANS_ENTERED.load(SeqCst) - entered >= 3);
+}
@@ -13,4 +13,11 @@ exports.call_exports = async function() {
   assert.strictEqual(7, (await wasm.async_return_7()).val);
   assert.strictEqual(8, (await wasm.async_return_8()).val);
   await assert.rejects(wasm.async_throw(), /async message/);
+  await wasm.async_return_err().then(
+    () => assert.fail('expected a rejection'),
+    e => assert.strictEqual(e, 'async error'),
+  );
+  await assert.rejects(wasm.async_panic(), /async panic message/);
+  // the executor keeps running other futures after one panics
+  assert.strictEqual(3, await wasm.async_return_3());
 };
@@ -70,3 +70,13 @@ pub async fn async_return_8() -> Result<AsyncCustomReturn, AsyncCustomReturn> {
 pub async fn async_throw() -> Result<(), js_sys::Error> {
     Err(js_sys::Error::new("async message"))
 }
+
+#[wasm_bindgen]
+pub async fn async_return_err() -> Result<u32, JsValue> {
+    Err(JsValue::from("async error"))
+}
+
+#[wasm_bindgen]
+pub async fn async_panic() -> Result<u32, JsValue> {
+    panic!("async panic message")
+}